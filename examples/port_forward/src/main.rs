@@ -0,0 +1,26 @@
+use ssh_rs::ssh;
+use std::thread;
+
+fn main() {
+    let mut session = ssh::create_session()
+        .username("ubuntu")
+        .password("password")
+        .private_key_path("./id_rsa")
+        .connect("127.0.0.1:22")
+        .unwrap()
+        .run_backend();
+
+    // same as `ssh -R 127.0.0.1:0:127.0.0.1:3000`
+    // the server picks a port, connections to it will be forwarded to local port 3000
+    let forward = session.tcpip_forward("127.0.0.1", 0).unwrap();
+    println!("remote port {} is forwarded", forward.port());
+    thread::spawn(move || forward.forward_to("127.0.0.1:3000").unwrap());
+
+    // same as `ssh -L 127.0.0.1:8080:127.0.0.1:80`
+    // connections to 127.0.0.1:8080 will be forwarded to port 80 of the server
+    session
+        .forward_local_port("127.0.0.1:8080", "127.0.0.1", 80)
+        .unwrap();
+
+    session.close();
+}
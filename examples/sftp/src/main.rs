@@ -0,0 +1,46 @@
+use ssh_rs::ssh;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+fn main() {
+    let mut session = ssh::create_session()
+        .username("ubuntu")
+        .password("password")
+        .private_key_path("./id_rsa")
+        .connect("127.0.0.1:22")
+        .unwrap()
+        .run_local();
+
+    let mut sftp = session.open_sftp().unwrap();
+    let home = sftp.realpath(".").unwrap();
+    println!("home: {}", home);
+
+    // write a file
+    let mut file = sftp.create("./sftp_test.txt").unwrap();
+    file.write_all(b"hello sftp").unwrap();
+    file.close().unwrap();
+
+    // read it back from the middle
+    let mut file = sftp.open("./sftp_test.txt").unwrap();
+    file.seek(SeekFrom::Start(6)).unwrap();
+    let mut content = String::new();
+    file.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "sftp");
+    drop(file);
+
+    // directories
+    sftp.mkdir("./sftp_dir").unwrap();
+    sftp.rename("./sftp_test.txt", "./sftp_dir/a.txt").unwrap();
+    for entry in sftp.read_dir("./sftp_dir").unwrap() {
+        println!("{}", entry.longname);
+    }
+    let attrs = sftp.stat("./sftp_dir/a.txt").unwrap();
+    assert!(attrs.is_file());
+    assert_eq!(attrs.size, Some(10));
+
+    // clean up
+    sftp.remove("./sftp_dir/a.txt").unwrap();
+    sftp.rmdir("./sftp_dir").unwrap();
+    sftp.close().unwrap();
+
+    session.close();
+}
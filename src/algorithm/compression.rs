@@ -0,0 +1,117 @@
+use flate2::{FlushCompress, FlushDecompress, Status};
+
+use crate::{constant::size, SshError, SshResult};
+
+use super::Compress;
+
+/// # 压缩算法
+/// 如果已经协商了压缩，有效载荷域（且只有它）会被使用所协商的算法压缩。长度域和 MAC 将
+/// 由压缩后的有效载荷计算得出。加密在压缩之后进行。
+/// 每个方向的压缩是独立的，压缩的上下文在整个连接中持续存在。
+pub(crate) trait Compression: Send + Sync {
+    // `zlib@openssh.com` starts after the user auth
+    fn activate(&mut self);
+    fn compress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>>;
+    fn decompress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>>;
+}
+
+pub(crate) fn from(s: &Compress, authenticated: bool) -> Box<dyn Compression> {
+    match s {
+        Compress::None => Box::new(CompressNone {}),
+        Compress::Zlib => Box::new(CompressZlib::new(true)),
+        Compress::ZlibOpenssh => Box::new(CompressZlib::new(authenticated)),
+    }
+}
+
+pub(crate) struct CompressNone {}
+
+impl Compression for CompressNone {
+    fn activate(&mut self) {
+        // do nothing
+    }
+    fn compress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+    fn decompress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>> {
+        Ok(buf.to_vec())
+    }
+}
+
+/// a deflate stream & an inflate stream, each packet ends with a partial flush
+struct CompressZlib {
+    deflate: flate2::Compress,
+    inflate: flate2::Decompress,
+    active: bool,
+}
+
+impl CompressZlib {
+    fn new(active: bool) -> Self {
+        CompressZlib {
+            deflate: flate2::Compress::new(flate2::Compression::default(), true),
+            inflate: flate2::Decompress::new(true),
+            active,
+        }
+    }
+}
+
+impl Compression for CompressZlib {
+    fn activate(&mut self) {
+        self.active = true;
+    }
+
+    fn compress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>> {
+        if !self.active {
+            return Ok(buf.to_vec());
+        }
+        let start = self.deflate.total_in();
+        let mut out = Vec::with_capacity(buf.len() + 64);
+        loop {
+            let consumed = (self.deflate.total_in() - start) as usize;
+            let status = self
+                .deflate
+                .compress_vec(&buf[consumed..], &mut out, FlushCompress::Partial)
+                .map_err(|e| SshError::from(e.to_string()))?;
+            let consumed = (self.deflate.total_in() - start) as usize;
+            // the flush is done if there is room left
+            let room = out.len() < out.capacity();
+            if consumed == buf.len() && room {
+                return Ok(out);
+            }
+            if status == Status::BufError && room {
+                return Err(SshError::from("compression error."));
+            }
+            out.reserve(out.capacity());
+        }
+    }
+
+    fn decompress(&mut self, buf: &[u8]) -> SshResult<Vec<u8>> {
+        if !self.active {
+            return Ok(buf.to_vec());
+        }
+        let start = self.inflate.total_in();
+        let mut out = Vec::with_capacity((buf.len() * 4).min(size::MAX_PAYLOAD));
+        loop {
+            let consumed = (self.inflate.total_in() - start) as usize;
+            let status = self
+                .inflate
+                .decompress_vec(&buf[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|e| SshError::from(e.to_string()))?;
+            let consumed = (self.inflate.total_in() - start) as usize;
+            let room = out.len() < out.capacity();
+            // a tiny packet may inflate to gigabytes, stop past the largest payload of a packet
+            if out.len() > size::MAX_PAYLOAD {
+                return Err(SshError::from("decompressed packet too large."));
+            }
+            if consumed == buf.len() && room {
+                return Ok(out);
+            }
+            // the stream never ends in ssh, and no progress with room is a broken input
+            match (status, room) {
+                (Status::StreamEnd, _) | (Status::BufError, true) => {
+                    return Err(SshError::from("compression error."))
+                }
+                _ => out.reserve(out.capacity()),
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::algorithm::encryption::{checked_packet_len, Encryption};
+use crate::algorithm::hash::Hash;
+use crate::algorithm::mac::Mac;
+use crate::{SshError, SshResult};
+use aes::cipher::{NewCipher, StreamCipher, StreamCipherSeek};
+use aes::{Aes128Ctr, Aes192Ctr, Aes256Ctr};
+use ring::constant_time::verify_slices_are_equal;
+
+const BSIZE: usize = 16;
+const IV_SIZE: usize = 16;
+
+// the same except the key size
+macro_rules! create_aes_ctr {
+    ($name: ident, $cipher: ident, $key_size: expr) => {
+        pub(super) struct $name {
+            pub(crate) client_key: $cipher,
+            pub(crate) server_key: $cipher,
+
+            client_ik: Vec<u8>,
+            server_ik: Vec<u8>,
+            mac: Box<dyn Mac>,
+        }
+
+        impl Encryption for $name {
+            fn bsize(&self) -> usize {
+                BSIZE
+            }
+            fn iv_size(&self) -> usize {
+                IV_SIZE
+            }
+
+            fn new(hash: Hash, mac: Box<dyn Mac>) -> Self {
+                let (ck, sk) = hash.extend_key($key_size);
+                let mut ckey = [0u8; $key_size];
+                let mut skey = [0u8; $key_size];
+
+                let mut civ = [0u8; IV_SIZE];
+                let mut siv = [0u8; IV_SIZE];
+
+                ckey.clone_from_slice(&ck[..$key_size]);
+                skey.clone_from_slice(&sk[..$key_size]);
+
+                civ.clone_from_slice(&hash.iv_c_s[..IV_SIZE]);
+                siv.clone_from_slice(&hash.iv_s_c[..IV_SIZE]);
+
+                // TODO unwrap 未处理
+                let c = $cipher::new_from_slices(&ckey, &civ).unwrap();
+                let r = $cipher::new_from_slices(&skey, &siv).unwrap();
+
+                let (client_ik, server_ik) = hash.extend_mac_key(mac.bsize());
+
+                $name {
+                    client_key: c,
+                    server_key: r,
+                    client_ik,
+                    server_ik,
+                    mac,
+                }
+            }
+
+            fn encrypt(&mut self, client_sequence_num: u32, buf: &mut Vec<u8>) {
+                let tag = if self.mac.is_etm() {
+                    // the packet length is left in plain text
+                    self.client_key.apply_keystream(&mut buf[4..]);
+                    self.mac.sign(&self.client_ik, client_sequence_num, buf)
+                } else {
+                    let tag = self.mac.sign(&self.client_ik, client_sequence_num, buf);
+                    self.client_key.apply_keystream(buf);
+                    tag
+                };
+                buf.extend(tag.as_ref())
+            }
+
+            fn decrypt(
+                &mut self,
+                server_sequence_number: u32,
+                buf: &mut [u8],
+            ) -> SshResult<Vec<u8>> {
+                let pl = self.packet_len(server_sequence_number, buf)?;
+                let data = &mut buf[..(pl + self.mac.bsize())];
+                let (d, m) = data.split_at_mut(pl);
+                if self.mac.is_etm() {
+                    // verify the cipher text before decrypting it
+                    let tag = self.mac.sign(&self.server_ik, server_sequence_number, d);
+                    verify_slices_are_equal(m, tag.as_ref())
+                        .map_err(|_| SshError::from("encryption error."))?;
+                    self.server_key.apply_keystream(&mut d[4..]);
+                } else {
+                    self.server_key.apply_keystream(d);
+                    let tag = self.mac.sign(&self.server_ik, server_sequence_number, d);
+                    verify_slices_are_equal(m, tag.as_ref())
+                        .map_err(|_| SshError::from("encryption error."))?;
+                }
+                Ok(d.to_vec())
+            }
+
+            fn packet_len(&mut self, _: u32, buf: &[u8]) -> SshResult<usize> {
+                let mut u32_bytes = [0_u8; 4];
+                if self.mac.is_etm() {
+                    u32_bytes.clone_from_slice(&buf[..4]);
+                } else {
+                    let bsize = self.bsize();
+                    let mut r = vec![0_u8; bsize];
+                    r.clone_from_slice(&buf[..bsize]);
+                    self.server_key.apply_keystream(&mut r);
+                    let pos: usize = self.server_key.current_pos();
+                    self.server_key.seek(pos - bsize);
+                    u32_bytes.clone_from_slice(&r[..4]);
+                }
+                // the mac is not verified yet
+                let packet_len = u32::from_be_bytes(u32_bytes);
+                checked_packet_len(packet_len, BSIZE, self.mac.is_etm())
+            }
+
+            fn data_len(&mut self, server_sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
+                let pl = self.packet_len(server_sequence_number, buf)?;
+                let bsize = self.mac.bsize();
+                Ok(pl + bsize)
+            }
+
+            // the packet length is not encrypted with an etm mac
+            fn is_cp(&self) -> bool {
+                self.mac.is_etm()
+            }
+        }
+    };
+}
+
+create_aes_ctr!(AesCtr128, Aes128Ctr, 16);
+create_aes_ctr!(AesCtr192, Aes192Ctr, 24);
+create_aes_ctr!(AesCtr256, Aes256Ctr, 32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{self, encryption::loopback_hash, mac};
+    use crate::constant::size;
+
+    // packet length, padding length, payload & padding,
+    // the packet length is out of the encrypted blocks with an etm mac
+    fn packet(fill: u8, etm: bool) -> Vec<u8> {
+        let len = if etm { 32 } else { 28 };
+        let mut buf = vec![fill; len + 4];
+        buf[..4].copy_from_slice(&(len as u32).to_be_bytes());
+        buf[4] = 4;
+        buf
+    }
+
+    fn round_trip<E: Encryption>(alg: algorithm::Mac) {
+        let mut enc = E::new(loopback_hash(), mac::from(&alg));
+        for seq in 0..3 {
+            let plain = packet(seq as u8, enc.is_cp());
+            let mut buf = plain.clone();
+            enc.encrypt(seq, &mut buf);
+            assert_ne!(buf[4..plain.len()], plain[4..]);
+            assert_eq!(enc.data_len(seq, &buf).unwrap(), buf.len());
+            assert_eq!(enc.decrypt(seq, &mut buf).unwrap(), plain);
+        }
+    }
+
+    #[test]
+    fn aes_ctr() {
+        round_trip::<AesCtr128>(algorithm::Mac::HmacSha1);
+        round_trip::<AesCtr192>(algorithm::Mac::HmacSha2_256);
+        round_trip::<AesCtr256>(algorithm::Mac::HmacSha2_512);
+    }
+
+    #[test]
+    fn aes_ctr_etm() {
+        round_trip::<AesCtr128>(algorithm::Mac::HmacSha1Etm);
+        round_trip::<AesCtr256>(algorithm::Mac::HmacSha2_512Etm);
+
+        let mut enc = AesCtr256::new(loopback_hash(), mac::from(&algorithm::Mac::HmacSha2_256Etm));
+        let plain = packet(1, true);
+        let mut first = plain.clone();
+        enc.encrypt(0, &mut first);
+        let mut second = packet(2, true);
+        enc.encrypt(1, &mut second);
+        // the packet length is sent in plain text
+        assert_eq!(first[..4], plain[..4]);
+
+        // a tampered cipher text is rejected before it is decrypted,
+        // so the key stream is not consumed
+        let mut tampered = first.clone();
+        tampered[8] ^= 1;
+        assert!(enc.decrypt(0, &mut tampered).is_err());
+        let mut tampered = first.clone();
+        tampered[36] ^= 1;
+        assert!(enc.decrypt(0, &mut tampered).is_err());
+
+        assert_eq!(enc.decrypt(0, &mut first).unwrap(), plain);
+        assert_eq!(enc.decrypt(1, &mut second).unwrap(), packet(2, true));
+    }
+
+    #[test]
+    fn aes_ctr_etm_packet_len() {
+        let mut enc = AesCtr128::new(loopback_hash(), mac::from(&algorithm::Mac::HmacSha2_256Etm));
+        let mut buf = [0_u8; 16];
+        for len in [size::MAX_PACKET_LEN as u32, u32::MAX, u32::MAX - 3, 0, 28] {
+            buf[..4].copy_from_slice(&len.to_be_bytes());
+            assert!(enc.data_len(0, &buf).is_err());
+        }
+        buf[..4].copy_from_slice(&32_u32.to_be_bytes());
+        assert_eq!(enc.data_len(0, &buf).unwrap(), 4 + 32 + 32);
+    }
+}
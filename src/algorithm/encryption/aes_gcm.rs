@@ -0,0 +1,195 @@
+use crate::algorithm::encryption::{checked_packet_len, Encryption};
+use crate::algorithm::hash::Hash;
+use crate::algorithm::mac::Mac;
+use crate::{SshError, SshResult};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_128_GCM, AES_256_GCM};
+
+const BSIZE: usize = 16;
+const IV_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// The nonce of one direction, see RFC 5647 7.1
+///
+/// a fixed field of 4 bytes & an invocation counter of 8 bytes,
+/// the counter is increased after each packet
+///
+struct Iv([u8; IV_SIZE]);
+
+impl Iv {
+    fn new(iv: &[u8]) -> Self {
+        let mut fixed = [0_u8; IV_SIZE];
+        fixed.copy_from_slice(&iv[..IV_SIZE]);
+        Iv(fixed)
+    }
+
+    fn next(&mut self) -> Nonce {
+        let nonce = Nonce::assume_unique_for_key(self.0);
+        let mut counter = [0_u8; 8];
+        counter.copy_from_slice(&self.0[4..]);
+        let counter = u64::from_be_bytes(counter).wrapping_add(1);
+        self.0[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+}
+
+/*
+    uint32    packet_length       not encrypted, but authenticated
+    byte      padding_length      \
+    byte[n1]  payload              } encrypted
+    byte[n2]  random padding      /
+    byte[16]  authentication tag  the implicit mac
+*/
+macro_rules! create_aes_gcm {
+    ($name: ident, $alg: ident, $key_size: expr) => {
+        pub(super) struct $name {
+            client_key: LessSafeKey,
+            server_key: LessSafeKey,
+            client_iv: Iv,
+            server_iv: Iv,
+        }
+
+        impl Encryption for $name {
+            fn bsize(&self) -> usize {
+                BSIZE
+            }
+
+            fn iv_size(&self) -> usize {
+                IV_SIZE
+            }
+
+            // the mac negotiated is ignored
+            fn new(hash: Hash, _mac: Box<dyn Mac>) -> Self {
+                let (ck, sk) = hash.extend_key($key_size);
+                // TODO unwrap 未处理
+                let client_key = UnboundKey::new(&$alg, &ck[..$key_size]).unwrap();
+                let server_key = UnboundKey::new(&$alg, &sk[..$key_size]).unwrap();
+
+                $name {
+                    client_key: LessSafeKey::new(client_key),
+                    server_key: LessSafeKey::new(server_key),
+                    client_iv: Iv::new(&hash.iv_c_s),
+                    server_iv: Iv::new(&hash.iv_s_c),
+                }
+            }
+
+            fn encrypt(&mut self, _: u32, buf: &mut Vec<u8>) {
+                let mut len = [0_u8; 4];
+                len.copy_from_slice(&buf[..4]);
+                // TODO unwrap 未处理
+                let tag = self
+                    .client_key
+                    .seal_in_place_separate_tag(
+                        self.client_iv.next(),
+                        Aad::from(len),
+                        &mut buf[4..],
+                    )
+                    .unwrap();
+                buf.extend(tag.as_ref())
+            }
+
+            fn decrypt(&mut self, sequence_number: u32, buf: &mut [u8]) -> SshResult<Vec<u8>> {
+                let pl = self.packet_len(sequence_number, buf)?;
+                let mut len = [0_u8; 4];
+                len.copy_from_slice(&buf[..4]);
+                let data = &mut buf[4..(pl + TAG_SIZE)];
+                match self
+                    .server_key
+                    .open_in_place(self.server_iv.next(), Aad::from(len), data)
+                {
+                    Ok(result) => Ok([&len[..], result].concat()),
+                    Err(_) => Err(SshError::from("encryption error.")),
+                }
+            }
+
+            // only authenticated as the aad, not checked by the tag yet
+            fn packet_len(&mut self, _: u32, buf: &[u8]) -> SshResult<usize> {
+                let mut u32_bytes = [0_u8; 4];
+                u32_bytes.clone_from_slice(&buf[..4]);
+                let packet_len = u32::from_be_bytes(u32_bytes);
+                checked_packet_len(packet_len, BSIZE, true)
+            }
+
+            fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
+                let pl = self.packet_len(sequence_number, buf)?;
+                Ok(pl + TAG_SIZE)
+            }
+
+            fn is_cp(&self) -> bool {
+                true
+            }
+        }
+    };
+}
+
+create_aes_gcm!(AesGcm128, AES_128_GCM, 16);
+create_aes_gcm!(AesGcm256, AES_256_GCM, 32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::{encryption::loopback_hash, mac::MacNone};
+    use crate::constant::size;
+
+    fn with_counter(counter: u64) -> Iv {
+        let mut iv = [0xaa_u8; 16];
+        iv[4..IV_SIZE].copy_from_slice(&counter.to_be_bytes());
+        Iv::new(&iv)
+    }
+
+    #[test]
+    fn iv_next() {
+        let mut iv = with_counter(0x01ff);
+        assert_eq!(iv.next().as_ref(), &with_counter(0x01ff).0);
+        // the carry goes to the next byte
+        assert_eq!(iv.0, [0xaa, 0xaa, 0xaa, 0xaa, 0, 0, 0, 0, 0, 0, 2, 0]);
+        assert_eq!(iv.next().as_ref(), &with_counter(0x0200).0);
+        assert_eq!(iv.0, with_counter(0x0201).0);
+
+        // the counter wraps around, the fixed field is never touched
+        let mut iv = with_counter(u64::MAX);
+        iv.next();
+        assert_eq!(iv.0, with_counter(0).0);
+    }
+
+    #[test]
+    fn aes_gcm() {
+        let mut enc = AesGcm256::new(loopback_hash(), Box::new(MacNone::new()));
+        let mut packets = vec![];
+        for seq in 0..3 {
+            let mut buf = vec![seq as u8; 36];
+            buf[..4].copy_from_slice(&32_u32.to_be_bytes());
+            let plain = buf.clone();
+            enc.encrypt(seq, &mut buf);
+            // the packet length is only authenticated
+            assert_eq!(buf[..4], plain[..4]);
+            assert_eq!(enc.data_len(seq, &buf).unwrap(), buf.len());
+            packets.push((plain, buf));
+        }
+
+        let mut tampered = packets[0].1.clone();
+        tampered[36] ^= 1;
+        assert!(enc.decrypt(0, &mut tampered).is_err());
+
+        // each packet takes the next nonce
+        let mut enc = AesGcm256::new(loopback_hash(), Box::new(MacNone::new()));
+        let (plain, mut buf) = packets.remove(0);
+        assert_eq!(enc.decrypt(0, &mut buf).unwrap(), plain);
+        let (_, mut buf) = packets.remove(1);
+        assert!(enc.decrypt(2, &mut buf).is_err());
+    }
+
+    #[test]
+    fn packet_len() {
+        let mut enc = AesGcm256::new(loopback_hash(), Box::new(MacNone::new()));
+        let max = size::MAX_PACKET_LEN as u32 - 16;
+        let mut buf = [0_u8; 16];
+        buf[..4].copy_from_slice(&max.to_be_bytes());
+        assert_eq!(enc.data_len(0, &buf).unwrap(), max as usize + 4 + TAG_SIZE);
+
+        // too large, overflowing, too small or not block aligned
+        for len in [max + 16, u32::MAX, u32::MAX - 3, 0, 20] {
+            buf[..4].copy_from_slice(&len.to_be_bytes());
+            assert!(enc.data_len(0, &buf).is_err());
+        }
+    }
+}
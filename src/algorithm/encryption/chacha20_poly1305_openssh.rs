@@ -1,7 +1,10 @@
 use crate::algorithm::hash::Hash;
 use crate::algorithm::mac::Mac;
 use crate::error::SshError;
-use crate::{algorithm::encryption::Encryption, error::SshResult};
+use crate::{
+    algorithm::encryption::{checked_packet_len, Encryption},
+    error::SshResult,
+};
 use ring::aead::chacha20_poly1305_openssh::{OpeningKey, SealingKey};
 
 const BSIZE: usize = 64;
@@ -57,18 +60,21 @@ impl Encryption for ChaCha20Poly1305 {
         }
     }
 
-    fn packet_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize {
+    fn packet_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
         let mut packet_len_slice = [0_u8; 4];
         packet_len_slice.copy_from_slice(&buf[..4]);
         let packet_len_slice = self
             .server_key
             .decrypt_packet_length(sequence_number, packet_len_slice);
-        u32::from_be_bytes(packet_len_slice) as usize
+        let packet_len = u32::from_be_bytes(packet_len_slice);
+        // the padding is made in blocks of 8 bytes
+        checked_packet_len(packet_len, 8, true)?;
+        Ok(packet_len as usize)
     }
 
-    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize {
-        let packet_len = self.packet_len(sequence_number, buf);
-        packet_len + 4 + 16
+    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
+        let packet_len = self.packet_len(sequence_number, buf)?;
+        Ok(packet_len + 4 + 16)
     }
 
     fn is_cp(&self) -> bool {
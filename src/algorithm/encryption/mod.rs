@@ -1,12 +1,18 @@
-mod aes_ctr_128;
+mod aes_ctr;
+mod aes_gcm;
 mod chacha20_poly1305_openssh;
 
 use crate::algorithm::hash::Hash;
 use crate::algorithm::mac::Mac;
-use crate::SshResult;
+use crate::constant::size;
+use crate::{SshError, SshResult};
 
 use super::{hash::HashCtx, mac::MacNone, Enc};
-use {aes_ctr_128::AesCtr128, chacha20_poly1305_openssh::ChaCha20Poly1305};
+use {
+    aes_ctr::{AesCtr128, AesCtr192, AesCtr256},
+    aes_gcm::{AesGcm128, AesGcm256},
+    chacha20_poly1305_openssh::ChaCha20Poly1305,
+};
 
 /// # 加密算法
 /// 在密钥交互中将协商出一种加密算法和一个密钥。当加密生效时，每个数据包的数据包长度、填
@@ -25,8 +31,10 @@ pub(crate) trait Encryption: Send + Sync {
         Self: Sized;
     fn encrypt(&mut self, client_sequence_num: u32, buf: &mut Vec<u8>);
     fn decrypt(&mut self, sequence_number: u32, buf: &mut [u8]) -> SshResult<Vec<u8>>;
-    fn packet_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize;
-    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize;
+    fn packet_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize>;
+    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize>;
+    // the packet length is not encrypted with the payload
+    // (chacha20-poly1305, aes-gcm & the etm macs), so the padding does not count it
     fn is_cp(&self) -> bool;
 }
 
@@ -34,6 +42,10 @@ pub(crate) fn from(s: &Enc, hash: Hash, mac: Box<dyn Mac>) -> Box<dyn Encryption
     match s {
         Enc::Chacha20Poly1305Openssh => Box::new(ChaCha20Poly1305::new(hash, mac)),
         Enc::Aes128Ctr => Box::new(AesCtr128::new(hash, mac)),
+        Enc::Aes192Ctr => Box::new(AesCtr192::new(hash, mac)),
+        Enc::Aes256Ctr => Box::new(AesCtr256::new(hash, mac)),
+        Enc::Aes128GcmOpenssh => Box::new(AesGcm128::new(hash, mac)),
+        Enc::Aes256GcmOpenssh => Box::new(AesGcm256::new(hash, mac)),
     }
 }
 
@@ -58,17 +70,45 @@ impl Encryption for EncryptionNone {
     fn decrypt(&mut self, _sequence_number: u32, buf: &mut [u8]) -> SshResult<Vec<u8>> {
         Ok(buf.to_vec())
     }
-    fn packet_len(&mut self, _sequence_number: u32, buf: &[u8]) -> usize {
-        u32::from_be_bytes(buf[0..4].try_into().unwrap()) as usize
+    fn packet_len(&mut self, _sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
+        let packet_len = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        checked_packet_len(packet_len, self.bsize(), false)?;
+        Ok(packet_len as usize)
     }
-    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> usize {
-        self.packet_len(sequence_number, buf) + 4
+    fn data_len(&mut self, sequence_number: u32, buf: &[u8]) -> SshResult<usize> {
+        Ok(self.packet_len(sequence_number, buf)? + 4)
     }
     fn is_cp(&self) -> bool {
         false
     }
 }
 
+/// check the packet length before the packet is authenticated,
+/// and return the length of the whole packet with the length field
+///
+/// `aad` is whether the length field is left out of the encrypted blocks
+/// (chacha20-poly1305, aes-gcm & the etm macs), the same rules as OpenSSH
+///
+pub(super) fn checked_packet_len(packet_len: u32, bsize: usize, aad: bool) -> SshResult<usize> {
+    let whole = match packet_len.checked_add(4) {
+        Some(whole) if packet_len >= 5 && whole as usize <= size::MAX_PACKET_LEN => whole as usize,
+        _ => {
+            return Err(SshError::from(format!(
+                "invalid packet length {}.",
+                packet_len
+            )))
+        }
+    };
+    let encrypted = if aad { packet_len as usize } else { whole };
+    if !encrypted.is_multiple_of(bsize) {
+        return Err(SshError::from(format!(
+            "packet length {} is not a multiple of the block size {}.",
+            packet_len, bsize
+        )));
+    }
+    Ok(whole)
+}
+
 impl Default for EncryptionNone {
     fn default() -> Self {
         let hash = Hash::new(HashCtx::new(), &[], super::hash::HashType::None);
@@ -76,3 +116,16 @@ impl Default for EncryptionNone {
         Self::new(hash, mac)
     }
 }
+
+// the same keys in both directions,
+// so a packet encrypted by the client can be decrypted as if it came from the server
+#[cfg(test)]
+pub(super) fn loopback_hash() -> Hash {
+    let mut ctx = HashCtx::new();
+    ctx.set_k(b"shared secret");
+    let mut hash = Hash::new(ctx, b"session id", super::hash::HashType::SHA256);
+    hash.iv_s_c = hash.iv_c_s.clone();
+    hash.ek_s_c = hash.ek_c_s.clone();
+    hash.ik_s_c = hash.ik_c_s.clone();
+    hash
+}
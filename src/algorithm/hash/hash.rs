@@ -82,6 +82,17 @@ impl Hash {
         (ck, sk)
     }
 
+    // the mac key shall be as long as the digest of the mac algorithm
+    pub fn extend_mac_key(&self, key_size: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut ck = self.ik_c_s.to_vec();
+        let mut sk = self.ik_s_c.to_vec();
+        while key_size > ck.len() {
+            ck.extend(self.extend(ck.as_slice()));
+            sk.extend(self.extend(sk.as_slice()));
+        }
+        (ck, sk)
+    }
+
     fn extend(&self, key: &[u8]) -> Vec<u8> {
         let k = self.hash_ctx.k.clone();
         let h = hash::digest(self.hash_ctx.as_bytes().as_slice(), self.hash_type);
@@ -100,6 +100,11 @@ impl HashCtx {
         data.put_mpint(k);
         self.k = data.to_vec();
     }
+    pub fn set_k_string(&mut self, k: &[u8]) {
+        let mut data = Data::new();
+        data.put_u8s(k);
+        self.k = data.to_vec();
+    }
 
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut v = vec![];
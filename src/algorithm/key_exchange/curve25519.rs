@@ -40,4 +40,8 @@ impl KeyExchange for CURVE25519 {
     fn get_hash_type(&self) -> HashType {
         HashType::SHA256
     }
+
+    fn is_kem(&self) -> bool {
+        false
+    }
 }
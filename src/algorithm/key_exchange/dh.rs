@@ -104,6 +104,10 @@ macro_rules! create_dh_with_group {
             fn get_hash_type(&self) -> HashType {
                 $hash
             }
+
+            fn is_kem(&self) -> bool {
+                false
+            }
         }
     };
 }
@@ -39,4 +39,8 @@ impl KeyExchange for EcdhP256 {
     fn get_hash_type(&self) -> HashType {
         HashType::SHA256
     }
+
+    fn is_kem(&self) -> bool {
+        false
+    }
 }
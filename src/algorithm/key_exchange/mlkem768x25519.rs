@@ -0,0 +1,67 @@
+use super::{
+    super::hash::{self, HashType},
+    curve25519::CURVE25519,
+    KeyExchange,
+};
+use crate::error::SshError;
+use crate::SshResult;
+use ml_kem::{kem::Decapsulate, Ciphertext, EncodedSizeUser, KemCore, MlKem768};
+
+const CIPHERTEXT_SIZE: usize = 1088;
+const X25519_SIZE: usize = 32;
+
+/// The hybrid of ML-KEM-768 & X25519
+///
+/// the client sends the encapsulation key & the ecdh public key,
+/// the server replies the ciphertext & its ecdh public key,
+/// the shared secret is `sha256(mlkem secret || x25519 secret)`
+///
+pub(super) struct MlKem768X25519 {
+    decapsulation_key: <MlKem768 as KemCore>::DecapsulationKey,
+    x25519: CURVE25519,
+    public_key: Vec<u8>,
+}
+
+impl KeyExchange for MlKem768X25519 {
+    fn new() -> SshResult<Self> {
+        let (decapsulation_key, encapsulation_key) = MlKem768::generate(&mut rand::rngs::OsRng);
+        let x25519 = CURVE25519::new()?;
+        let mut public_key = encapsulation_key.as_bytes().to_vec();
+        public_key.extend(x25519.get_public_key());
+        Ok(MlKem768X25519 {
+            decapsulation_key,
+            x25519,
+            public_key,
+        })
+    }
+
+    fn get_public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn get_shared_secret(&self, puk: Vec<u8>) -> SshResult<Vec<u8>> {
+        if puk.len() != CIPHERTEXT_SIZE + X25519_SIZE {
+            return Err(SshError::from("encryption error."));
+        }
+        let (ciphertext, server_pub) = puk.split_at(CIPHERTEXT_SIZE);
+        let ciphertext = Ciphertext::<MlKem768>::try_from(ciphertext)
+            .map_err(|_| SshError::from("encryption error."))?;
+        let mlkem_secret = self
+            .decapsulation_key
+            .decapsulate(&ciphertext)
+            .map_err(|_| SshError::from("encryption error."))?;
+        let x25519_secret = self.x25519.get_shared_secret(server_pub.to_vec())?;
+
+        let mut secret = mlkem_secret.to_vec();
+        secret.extend(x25519_secret);
+        Ok(hash::digest(&secret, HashType::SHA256))
+    }
+
+    fn get_hash_type(&self) -> HashType {
+        HashType::SHA256
+    }
+
+    fn is_kem(&self) -> bool {
+        true
+    }
+}
@@ -10,6 +10,7 @@ use ring::agreement::{EphemeralPrivateKey, UnparsedPublicKey};
 mod curve25519;
 mod dh;
 mod ecdh_sha2_nistp256;
+mod mlkem768x25519;
 
 use super::Kex;
 use curve25519::CURVE25519;
@@ -17,6 +18,7 @@ use curve25519::CURVE25519;
 use dh::DiffieHellmanGroup1Sha1;
 use dh::{DiffieHellmanGroup14Sha1, DiffieHellmanGroup14Sha256};
 use ecdh_sha2_nistp256::EcdhP256;
+use mlkem768x25519::MlKem768X25519;
 
 pub(crate) trait KeyExchange: Send + Sync {
     fn new() -> SshResult<Self>
@@ -25,6 +27,8 @@ pub(crate) trait KeyExchange: Send + Sync {
     fn get_public_key(&self) -> &[u8];
     fn get_shared_secret(&self, puk: Vec<u8>) -> SshResult<Vec<u8>>;
     fn get_hash_type(&self) -> HashType;
+    // the shared secret of a kem is a string instead of a mpint
+    fn is_kem(&self) -> bool;
 }
 
 pub(crate) fn agree_ephemeral<B: AsRef<[u8]>>(
@@ -44,6 +48,7 @@ pub(crate) fn agree_ephemeral<B: AsRef<[u8]>>(
 
 pub(crate) fn from(s: &Kex) -> SshResult<Box<dyn KeyExchange>> {
     match s {
+        Kex::Mlkem768x25519Sha256 => Ok(Box::new(MlKem768X25519::new()?)),
         Kex::Curve25519Sha256 => Ok(Box::new(CURVE25519::new()?)),
         Kex::EcdhSha2Nistrp256 => Ok(Box::new(EcdhP256::new()?)),
         #[cfg(feature = "dangerous-dh-group1-sha1")]
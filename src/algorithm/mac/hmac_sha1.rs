@@ -26,4 +26,8 @@ impl Mac for HMacSha1 {
     fn bsize(&self) -> usize {
         BSIZE
     }
+
+    fn is_etm(&self) -> bool {
+        false
+    }
 }
@@ -2,14 +2,15 @@ use crate::algorithm::mac::Mac;
 use ring::hmac;
 use ring::hmac::{Context, Tag};
 
-const BSIZE: usize = 20;
+const BSIZE_256: usize = 32;
+const BSIZE_512: usize = 64;
 
 pub(super) struct HmacSha2_256;
 pub(super) struct HmacSha2_512;
 
 impl Mac for HmacSha2_256 {
     fn sign(&self, ik: &[u8], sequence_num: u32, buf: &[u8]) -> Tag {
-        let ik = &ik[..BSIZE];
+        let ik = &ik[..BSIZE_256];
         let key = hmac::Key::new(hmac::HMAC_SHA256, ik);
         let mut c = Context::with_key(&key);
         c.update(sequence_num.to_be_bytes().as_slice());
@@ -25,13 +26,17 @@ impl Mac for HmacSha2_256 {
     }
 
     fn bsize(&self) -> usize {
-        BSIZE
+        BSIZE_256
+    }
+
+    fn is_etm(&self) -> bool {
+        false
     }
 }
 
 impl Mac for HmacSha2_512 {
     fn sign(&self, ik: &[u8], sequence_num: u32, buf: &[u8]) -> Tag {
-        let ik = &ik[..BSIZE];
+        let ik = &ik[..BSIZE_512];
         let key = hmac::Key::new(hmac::HMAC_SHA512, ik);
         let mut c = Context::with_key(&key);
         c.update(sequence_num.to_be_bytes().as_slice());
@@ -47,6 +52,10 @@ impl Mac for HmacSha2_512 {
     }
 
     fn bsize(&self) -> usize {
-        BSIZE
+        BSIZE_512
+    }
+
+    fn is_etm(&self) -> bool {
+        false
     }
 }
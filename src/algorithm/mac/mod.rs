@@ -10,7 +10,10 @@ pub(crate) trait Mac: Send + Sync {
     fn new() -> Self
     where
         Self: Sized;
+    // the length of both the key and the tag
     fn bsize(&self) -> usize;
+    // encrypt-then-mac, the tag is computed over the cipher text
+    fn is_etm(&self) -> bool;
 }
 
 pub(crate) fn from(s: &super::Mac) -> Box<dyn Mac> {
@@ -18,6 +21,33 @@ pub(crate) fn from(s: &super::Mac) -> Box<dyn Mac> {
         super::Mac::HmacSha1 => Box::new(HMacSha1::new()),
         super::Mac::HmacSha2_256 => Box::new(HmacSha2_256::new()),
         super::Mac::HmacSha2_512 => Box::new(HmacSha2_512::new()),
+        super::Mac::HmacSha1Etm => Box::new(Etm::<HMacSha1>::new()),
+        super::Mac::HmacSha2_256Etm => Box::new(Etm::<HmacSha2_256>::new()),
+        super::Mac::HmacSha2_512Etm => Box::new(Etm::<HmacSha2_512>::new()),
+    }
+}
+
+/// The `*-etm@openssh.com` variant of a mac algorithm
+///
+/// the same tag, but over the packet length & the cipher text
+///
+struct Etm<M: Mac>(M);
+
+impl<M: Mac> Mac for Etm<M> {
+    fn sign(&self, ik: &[u8], sequence_num: u32, buf: &[u8]) -> Tag {
+        self.0.sign(ik, sequence_num, buf)
+    }
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self(M::new())
+    }
+    fn bsize(&self) -> usize {
+        self.0.bsize()
+    }
+    fn is_etm(&self) -> bool {
+        true
     }
 }
 
@@ -36,4 +66,44 @@ impl Mac for MacNone {
     fn bsize(&self) -> usize {
         unreachable!()
     }
+    fn is_etm(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm;
+
+    #[test]
+    fn etm() {
+        let ik = [7_u8; 64];
+        let buf = b"packet";
+        for (plain, etm) in [
+            (algorithm::Mac::HmacSha1, algorithm::Mac::HmacSha1Etm),
+            (
+                algorithm::Mac::HmacSha2_256,
+                algorithm::Mac::HmacSha2_256Etm,
+            ),
+            (
+                algorithm::Mac::HmacSha2_512,
+                algorithm::Mac::HmacSha2_512Etm,
+            ),
+        ] {
+            let (plain, etm) = (from(&plain), from(&etm));
+            assert!(!plain.is_etm());
+            assert!(etm.is_etm());
+            // only the data covered differs, not the tag itself
+            assert_eq!(plain.bsize(), etm.bsize());
+            assert_eq!(
+                plain.sign(&ik, 3, buf).as_ref(),
+                etm.sign(&ik, 3, buf).as_ref()
+            );
+            assert_ne!(
+                etm.sign(&ik, 3, buf).as_ref(),
+                etm.sign(&ik, 4, buf).as_ref()
+            );
+        }
+    }
 }
@@ -1,25 +1,36 @@
+pub(crate) mod compression;
 pub(crate) mod encryption;
 pub(crate) mod hash;
 pub(crate) mod key_exchange;
 pub(crate) mod mac;
 pub(crate) mod public_key;
 
-use strum_macros::{AsRefStr, EnumString};
+use strum_macros::{AsRefStr, EnumIter, EnumString};
 
 use self::{hash::HashCtx, key_exchange::KeyExchange};
 
 /// symmetrical encryption algorithm
-#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
+#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, EnumIter)]
 pub enum Enc {
     #[strum(serialize = "chacha20-poly1305@openssh.com")]
     Chacha20Poly1305Openssh,
     #[strum(serialize = "aes128-ctr")]
     Aes128Ctr,
+    #[strum(serialize = "aes192-ctr")]
+    Aes192Ctr,
+    #[strum(serialize = "aes256-ctr")]
+    Aes256Ctr,
+    #[strum(serialize = "aes128-gcm@openssh.com")]
+    Aes128GcmOpenssh,
+    #[strum(serialize = "aes256-gcm@openssh.com")]
+    Aes256GcmOpenssh,
 }
 
 /// key exchange algorithm
-#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
+#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, EnumIter)]
 pub enum Kex {
+    #[strum(serialize = "mlkem768x25519-sha256")]
+    Mlkem768x25519Sha256,
     #[strum(serialize = "curve25519-sha256")]
     Curve25519Sha256,
     #[strum(serialize = "ecdh-sha2-nistp256")]
@@ -34,7 +45,7 @@ pub enum Kex {
 }
 
 /// pubkey hash algorithm
-#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
+#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, EnumIter)]
 pub enum PubKey {
     #[strum(serialize = "ssh-ed25519")]
     SshEd25519,
@@ -45,10 +56,43 @@ pub enum PubKey {
     RsaSha2_256,
     #[strum(serialize = "rsa-sha2-512")]
     RsaSha2_512,
+    #[strum(serialize = "ecdsa-sha2-nistp256")]
+    EcdsaSha2Nistp256,
+    #[strum(serialize = "ecdsa-sha2-nistp384")]
+    EcdsaSha2Nistp384,
+    #[strum(serialize = "ecdsa-sha2-nistp521")]
+    EcdsaSha2Nistp521,
+    #[strum(serialize = "ssh-ed25519-cert-v01@openssh.com")]
+    SshEd25519CertV01,
+    #[strum(serialize = "rsa-sha2-256-cert-v01@openssh.com")]
+    RsaSha2_256CertV01,
+    #[strum(serialize = "rsa-sha2-512-cert-v01@openssh.com")]
+    RsaSha2_512CertV01,
+    #[strum(serialize = "ecdsa-sha2-nistp256-cert-v01@openssh.com")]
+    EcdsaSha2Nistp256CertV01,
+    #[strum(serialize = "ecdsa-sha2-nistp384-cert-v01@openssh.com")]
+    EcdsaSha2Nistp384CertV01,
+    #[strum(serialize = "ecdsa-sha2-nistp521-cert-v01@openssh.com")]
+    EcdsaSha2Nistp521CertV01,
+}
+
+impl PubKey {
+    /// the certificate algorithm of a plain key algorithm
+    pub(crate) fn certificate(&self) -> Option<PubKey> {
+        match self {
+            PubKey::SshEd25519 => Some(PubKey::SshEd25519CertV01),
+            PubKey::RsaSha2_256 => Some(PubKey::RsaSha2_256CertV01),
+            PubKey::RsaSha2_512 => Some(PubKey::RsaSha2_512CertV01),
+            PubKey::EcdsaSha2Nistp256 => Some(PubKey::EcdsaSha2Nistp256CertV01),
+            PubKey::EcdsaSha2Nistp384 => Some(PubKey::EcdsaSha2Nistp384CertV01),
+            PubKey::EcdsaSha2Nistp521 => Some(PubKey::EcdsaSha2Nistp521CertV01),
+            _ => None,
+        }
+    }
 }
 
 /// MAC(message authentication code) algorithm
-#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString)]
+#[derive(Copy, Clone, PartialEq, Eq, AsRefStr, EnumString, EnumIter)]
 pub enum Mac {
     #[strum(serialize = "hmac-sha1")]
     HmacSha1,
@@ -56,6 +100,12 @@ pub enum Mac {
     HmacSha2_256,
     #[strum(serialize = "hmac-sha2-512")]
     HmacSha2_512,
+    #[strum(serialize = "hmac-sha1-etm@openssh.com")]
+    HmacSha1Etm,
+    #[strum(serialize = "hmac-sha2-256-etm@openssh.com")]
+    HmacSha2_256Etm,
+    #[strum(serialize = "hmac-sha2-512-etm@openssh.com")]
+    HmacSha2_512Etm,
 }
 
 /// compression algorithm
@@ -63,6 +113,11 @@ pub enum Mac {
 pub enum Compress {
     #[strum(serialize = "none")]
     None,
+    #[strum(serialize = "zlib")]
+    Zlib,
+    // started after the user auth
+    #[strum(serialize = "zlib@openssh.com")]
+    ZlibOpenssh,
 }
 
 #[derive(Default)]
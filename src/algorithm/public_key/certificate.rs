@@ -0,0 +1,217 @@
+use std::str::FromStr;
+
+use crate::{
+    algorithm::{public_key::PublicKey, PubKey},
+    config::known_hosts::key_type,
+    error::{SshError, SshResult},
+    model::Data,
+    util,
+};
+
+/// the suffix of all the OpenSSH certificate key types,
+/// e.g. `ssh-ed25519-cert-v01@openssh.com`
+pub(crate) const CERT_SUFFIX: &str = "-cert-v01@openssh.com";
+pub(crate) const SSH_CERT_TYPE_USER: u32 = 1;
+pub(crate) const SSH_CERT_TYPE_HOST: u32 = 2;
+
+/// An OpenSSH certificate, see `PROTOCOL.certkeys` of OpenSSH
+///
+pub(crate) struct Certificate {
+    // the plain key type, e.g. `ssh-ed25519`
+    pub key_type: String,
+    // the plain public key blob of the certified key
+    pub public_key: Vec<u8>,
+    pub serial: u64,
+    pub cert_type: u32,
+    pub key_id: String,
+    // empty for any principal
+    pub principals: Vec<String>,
+    pub valid_after: u64,
+    pub valid_before: u64,
+    pub critical_options: Vec<String>,
+    // the public key blob of the CA
+    pub signature_key: Vec<u8>,
+    // everything before the signature
+    signed: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+// the certificate comes from the peer,
+// so the lengths are checked instead of panic
+fn get_u8s(data: &mut Data) -> SshResult<Vec<u8>> {
+    data.try_get_u8s()
+        .map_err(|_| SshError::from("invalid certificate."))
+}
+
+fn get_u32(data: &mut Data) -> SshResult<u32> {
+    data.try_get_u32()
+        .map_err(|_| SshError::from("invalid certificate."))
+}
+
+fn get_u64(data: &mut Data) -> SshResult<u64> {
+    data.try_get_u64()
+        .map_err(|_| SshError::from("invalid certificate."))
+}
+
+// a list of strings packed in one string
+fn get_str_list(data: &mut Data) -> SshResult<Vec<String>> {
+    let mut list = Data::from(get_u8s(data)?);
+    let mut strs = vec![];
+    while !list.is_empty() {
+        strs.push(util::from_utf8(get_u8s(&mut list)?)?);
+    }
+    Ok(strs)
+}
+
+// the names of `string name, string data` pairs
+fn get_option_names(data: &mut Data) -> SshResult<Vec<String>> {
+    let mut options = Data::from(get_u8s(data)?);
+    let mut names = vec![];
+    while !options.is_empty() {
+        names.push(util::from_utf8(get_u8s(&mut options)?)?);
+        get_u8s(&mut options)?;
+    }
+    Ok(names)
+}
+
+impl Certificate {
+    /*
+        string    "<key type>-cert-v01@openssh.com"
+        string    nonce
+        ....      the public key fields of the key type
+        uint64    serial
+        uint32    type
+        string    key id
+        string    valid principals
+        uint64    valid after
+        uint64    valid before
+        string    critical options
+        string    extensions
+        string    reserved
+        string    signature key
+        string    signature
+    */
+    pub fn parse(blob: &[u8]) -> SshResult<Self> {
+        let mut data = Data::from(blob);
+        let cert_type = util::from_utf8(get_u8s(&mut data)?)?;
+        let key_type = match cert_type.strip_suffix(CERT_SUFFIX) {
+            Some(t) => t.to_string(),
+            None => {
+                return Err(SshError::from(format!(
+                    "{} is not a certificate.",
+                    cert_type
+                )))
+            }
+        };
+        // nonce
+        get_u8s(&mut data)?;
+
+        let mut public_key = Data::new();
+        public_key.put_str(&key_type);
+        match key_type.as_str() {
+            // e, n
+            "ssh-rsa" => {
+                public_key.put_u8s(&get_u8s(&mut data)?);
+                public_key.put_u8s(&get_u8s(&mut data)?);
+            }
+            "ssh-ed25519" => {
+                public_key.put_u8s(&get_u8s(&mut data)?);
+            }
+            // curve, Q
+            x if x.starts_with("ecdsa-sha2-") => {
+                public_key.put_u8s(&get_u8s(&mut data)?);
+                public_key.put_u8s(&get_u8s(&mut data)?);
+            }
+            x => {
+                return Err(SshError::from(format!(
+                    "unsupported certificate key type {}.",
+                    x
+                )))
+            }
+        }
+
+        let serial = get_u64(&mut data)?;
+        let cert_type = get_u32(&mut data)?;
+        let key_id = util::from_utf8(get_u8s(&mut data)?)?;
+        let principals = get_str_list(&mut data)?;
+        let valid_after = get_u64(&mut data)?;
+        let valid_before = get_u64(&mut data)?;
+        let critical_options = get_option_names(&mut data)?;
+        // extensions & reserved
+        get_u8s(&mut data)?;
+        get_u8s(&mut data)?;
+        let signature_key = get_u8s(&mut data)?;
+        let signed = blob[..blob.len() - data.len()].to_vec();
+        let signature = get_u8s(&mut data)?;
+
+        Ok(Self {
+            key_type,
+            public_key: public_key.to_vec(),
+            serial,
+            cert_type,
+            key_id,
+            principals,
+            valid_after,
+            valid_before,
+            critical_options,
+            signature_key,
+            signed,
+            signature,
+        })
+    }
+
+    /// verify the signature of the CA
+    pub fn verify_signature(&self) -> SshResult<bool> {
+        let mut signature = Data::from(self.signature.clone());
+        let alg_name = util::from_utf8(get_u8s(&mut signature)?)?;
+        let sig = get_u8s(&mut signature)?;
+        let alg = PubKey::from_str(&alg_name).map_err(|_| {
+            SshError::from(format!(
+                "unsupported certificate signature algorithm {}.",
+                alg_name
+            ))
+        })?;
+        // the signature must be made by the CA key,
+        // e.g. not an ecdsa signature claimed by an rsa CA
+        let ca_type = key_type(&self.signature_key)?;
+        let sig_type = match alg {
+            PubKey::RsaSha2_256 | PubKey::RsaSha2_512 => "ssh-rsa",
+            _ => alg_name.as_str(),
+        };
+        if ca_type != sig_type || ca_type.ends_with(CERT_SUFFIX) {
+            return Err(SshError::from(format!(
+                "certificate signature algorithm {} doesn't match the CA key {}.",
+                alg_name, ca_type
+            )));
+        }
+        let mut ca_key = Data::new();
+        ca_key.put_u8s(&self.signature_key);
+        super::from(&alg).verify_signature(&ca_key, &self.signed, &sig)
+    }
+
+    /// whether the certificate is valid at `now`, in seconds since the unix epoch
+    pub fn is_valid_at(&self, now: u64) -> bool {
+        self.valid_after <= now && now < self.valid_before
+    }
+}
+
+/// Verify the signatures made by the key in a certificate,
+/// the certificate itself is checked by the host key verifier
+///
+pub(super) struct CertV01<K: PublicKey>(K);
+
+impl<K: PublicKey> PublicKey for CertV01<K> {
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self(K::new())
+    }
+
+    fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
+        let cert = Certificate::parse(&get_u8s(&mut Data::from(ks))?)?;
+        let mut key = Data::new();
+        key.put_u8s(&cert.public_key);
+        self.0.verify_signature(&key, message, sig)
+    }
+}
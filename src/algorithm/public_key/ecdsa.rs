@@ -0,0 +1,99 @@
+use crate::algorithm::public_key::PublicKey;
+use crate::model::Data;
+use crate::SshError;
+use ring::signature;
+
+/*
+    string    "ecdsa-sha2-[identifier]"
+    string    [identifier]
+    string    Q
+
+    the signature blob:
+    mpint     r
+    mpint     s
+*/
+// parse the public point Q & the signature (r, s) with fixed length
+fn parse(ks: &[u8], sig: &[u8], len: usize) -> Result<(Vec<u8>, Vec<u8>), SshError> {
+    let mut data = Data::from(Data::from(ks).try_get_u8s()?);
+    data.try_get_u8s()?;
+    data.try_get_u8s()?;
+    let q = data.try_get_u8s()?;
+
+    let mut sig = Data::from(sig);
+    let mut fixed = vec![];
+    for _ in 0..2 {
+        let int = sig.try_get_u8s()?;
+        // strip the leading 0 of the positive mpint
+        let int = match int.iter().position(|b| *b != 0) {
+            Some(i) => &int[i..],
+            None => &int[int.len()..],
+        };
+        if int.len() > len {
+            return Err(SshError::from("invalid ecdsa signature."));
+        }
+        fixed.extend(vec![0; len - int.len()]);
+        fixed.extend_from_slice(int);
+    }
+    Ok((q, fixed))
+}
+
+pub(super) struct EcdsaSha2Nistp256;
+
+impl PublicKey for EcdsaSha2Nistp256 {
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
+        let (q, sig) = parse(ks, sig, 32)?;
+        let pub_key = signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, q);
+        Ok(pub_key.verify(message, &sig).is_ok())
+    }
+}
+
+pub(super) struct EcdsaSha2Nistp384;
+
+impl PublicKey for EcdsaSha2Nistp384 {
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
+        let (q, sig) = parse(ks, sig, 48)?;
+        let pub_key = signature::UnparsedPublicKey::new(&signature::ECDSA_P384_SHA384_FIXED, q);
+        Ok(pub_key.verify(message, &sig).is_ok())
+    }
+}
+
+// ring doesn't support P-521
+pub(super) struct EcdsaSha2Nistp521;
+
+impl PublicKey for EcdsaSha2Nistp521 {
+    fn new() -> Self
+    where
+        Self: Sized,
+    {
+        Self
+    }
+
+    fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
+        use p521::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+
+        let (q, sig) = parse(ks, sig, 66)?;
+        let pub_key = match VerifyingKey::from_sec1_bytes(&q) {
+            Ok(key) => key,
+            Err(_) => return Err(SshError::from("invalid ecdsa public key.")),
+        };
+        let sig = match Signature::from_slice(&sig) {
+            Ok(sig) => sig,
+            Err(_) => return Ok(false),
+        };
+        Ok(pub_key.verify(message, &sig).is_ok())
+    }
+}
@@ -14,9 +14,9 @@ impl PublicKey for Ed25519 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-        let host_key = data.get_u8s();
+        let mut data = Data::from(Data::from(ks).try_get_u8s()?);
+        data.try_get_u8s()?;
+        let host_key = data.try_get_u8s()?;
         let pub_key = signature::UnparsedPublicKey::new(&signature::ED25519, host_key);
         Ok(pub_key.verify(message, sig).is_ok())
     }
@@ -1,5 +1,7 @@
 use crate::SshError;
 
+pub(crate) mod certificate;
+mod ecdsa;
 mod ed25519;
 mod rsa;
 
@@ -8,6 +10,8 @@ use self::rsa::RsaSha1;
 use self::rsa::RsaSha256;
 use self::rsa::RsaSha512;
 use super::PubKey;
+use certificate::CertV01;
+use ecdsa::{EcdsaSha2Nistp256, EcdsaSha2Nistp384, EcdsaSha2Nistp521};
 use ed25519::Ed25519;
 
 /// # 公钥算法
@@ -27,5 +31,14 @@ pub(crate) fn from(s: &PubKey) -> Box<dyn PublicKey> {
         PubKey::SshRsa => Box::new(RsaSha1::new()),
         PubKey::RsaSha2_256 => Box::new(RsaSha256::new()),
         PubKey::RsaSha2_512 => Box::new(RsaSha512::new()),
+        PubKey::EcdsaSha2Nistp256 => Box::new(EcdsaSha2Nistp256::new()),
+        PubKey::EcdsaSha2Nistp384 => Box::new(EcdsaSha2Nistp384::new()),
+        PubKey::EcdsaSha2Nistp521 => Box::new(EcdsaSha2Nistp521::new()),
+        PubKey::SshEd25519CertV01 => Box::new(CertV01::<Ed25519>::new()),
+        PubKey::RsaSha2_256CertV01 => Box::new(CertV01::<RsaSha256>::new()),
+        PubKey::RsaSha2_512CertV01 => Box::new(CertV01::<RsaSha512>::new()),
+        PubKey::EcdsaSha2Nistp256CertV01 => Box::new(CertV01::<EcdsaSha2Nistp256>::new()),
+        PubKey::EcdsaSha2Nistp384CertV01 => Box::new(CertV01::<EcdsaSha2Nistp384>::new()),
+        PubKey::EcdsaSha2Nistp521CertV01 => Box::new(CertV01::<EcdsaSha2Nistp521>::new()),
     }
 }
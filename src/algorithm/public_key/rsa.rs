@@ -3,6 +3,21 @@ use crate::model::Data;
 use crate::SshError;
 use rsa::PublicKey;
 
+/*
+    string    "ssh-rsa"
+    mpint     e
+    mpint     n
+*/
+// the key may come from the peer, e.g. the CA key of a certificate
+fn parse(ks: &[u8]) -> Result<rsa::RsaPublicKey, SshError> {
+    let mut data = Data::from(Data::from(ks).try_get_u8s()?);
+    data.try_get_u8s()?;
+
+    let e = rsa::BigUint::from_bytes_be(data.try_get_u8s()?.as_slice());
+    let n = rsa::BigUint::from_bytes_be(data.try_get_u8s()?.as_slice());
+    rsa::RsaPublicKey::new(n, e).map_err(|_| SshError::from("invalid rsa public key."))
+}
+
 pub(super) struct RsaSha256;
 
 impl PubK for RsaSha256 {
@@ -14,12 +29,7 @@ impl PubK for RsaSha256 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = parse(ks)?;
         let scheme = rsa::PaddingScheme::new_pkcs1v15_sign::<sha2::Sha256>();
 
         let digest = ring::digest::digest(&ring::digest::SHA256, message);
@@ -40,12 +50,7 @@ impl PubK for RsaSha512 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = parse(ks)?;
         let scheme = rsa::PaddingScheme::new_pkcs1v15_sign::<sha2::Sha512>();
 
         let digest = ring::digest::digest(&ring::digest::SHA512, message);
@@ -67,12 +72,7 @@ impl PubK for RsaSha1 {
     }
 
     fn verify_signature(&self, ks: &[u8], message: &[u8], sig: &[u8]) -> Result<bool, SshError> {
-        let mut data = Data::from(ks[4..].to_vec());
-        data.get_u8s();
-
-        let e = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let n = rsa::BigUint::from_bytes_be(data.get_u8s().as_slice());
-        let public_key = rsa::RsaPublicKey::new(n, e).unwrap();
+        let public_key = parse(ks)?;
         let scheme = rsa::PaddingScheme::new_pkcs1v15_sign::<sha1::Sha1>();
 
         let digest = ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, message);
@@ -0,0 +1,346 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::spawn,
+    time::Duration,
+};
+
+use crate::{
+    client::Client,
+    config::agent::AgentClient,
+    constant::{agent, size, ssh_msg_code},
+    error::{SshError, SshResult},
+    model::{Data, FlowControl, Packet},
+};
+
+// how long a session w/o a waker waits for the reply of the agent
+const AGENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// called by the relay threads when a reply of the agent is ready,
+/// then [AgentChannels::poll] sends it
+pub(crate) type Waker = Arc<dyn Fn() + Send + Sync>;
+
+/// An `auth-agent@openssh.com` channel opened by the server,
+/// the requests are relayed to the local agent by a dedicated thread
+///
+struct AgentChannel {
+    server_channel_no: u32,
+    requests: Sender<Vec<u8>>,
+    replies: Receiver<SshResult<Vec<u8>>>,
+    flow_control: FlowControl,
+    // a request may be split into several packets
+    request_buf: Vec<u8>,
+    // the number of requests not yet answered
+    waiting: usize,
+    pending_send: Vec<u8>,
+    local_close: bool,
+}
+
+/// The forwarded agent channels of one session
+///
+/// w/o a waker, the replies are waited in place,
+/// which is for the sessions running on the local thread
+///
+#[derive(Default)]
+pub(crate) struct AgentChannels {
+    channels: HashMap<u32, AgentChannel>,
+    waker: Option<Waker>,
+}
+
+impl AgentChannels {
+    pub fn with_waker(waker: Waker) -> Self {
+        Self {
+            channels: HashMap::new(),
+            waker: Some(waker),
+        }
+    }
+
+    pub fn contains(&self, client_channel_no: u32) -> bool {
+        self.channels.contains_key(&client_channel_no)
+    }
+
+    /*
+        byte      SSH_MSG_CHANNEL_OPEN
+        string    "auth-agent@openssh.com"
+        uint32    sender channel
+        uint32    initial window size
+        uint32    maximum packet size
+    */
+    // `data` starts from the sender channel,
+    // the channel is refused if agent forwarding is not enabled
+    pub fn open<S>(
+        &mut self,
+        mut data: Data,
+        client_channel_no: u32,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let server_channel_no = data.get_u32();
+        let remote_window_size = data.get_u32();
+        // 远程的最大数据包大小， 暂时不需要
+        data.get_u32();
+
+        let agent = match client.agent_forward() {
+            Some(path) => connect(&path),
+            None => Err(SshError::from("agent forwarding is not enabled.")),
+        };
+        let agent = match agent {
+            Ok(agent) => agent,
+            Err(e) => {
+                log::debug!("reject agent channel: {}", e);
+                let mut data = Data::new();
+                data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE)
+                    .put_u32(server_channel_no)
+                    .put_u32(ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED)
+                    .put_str("")
+                    .put_str("");
+                return data.pack(client).write_stream(stream);
+            }
+        };
+
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_CONFIRMATION)
+            .put_u32(server_channel_no)
+            .put_u32(client_channel_no)
+            .put_u32(size::LOCAL_WINDOW_SIZE)
+            .put_u32(size::BUF_SIZE as u32);
+        data.pack(client).write_stream(stream)?;
+
+        log::info!("agent channel {} opened.", client_channel_no);
+        let (requests, replies) = spawn_relay(agent, self.waker.clone());
+        self.channels.insert(
+            client_channel_no,
+            AgentChannel {
+                server_channel_no,
+                requests,
+                replies,
+                flow_control: FlowControl::new(remote_window_size),
+                request_buf: vec![],
+                waiting: 0,
+                pending_send: vec![],
+                local_close: false,
+            },
+        );
+        Ok(())
+    }
+
+    // `data` starts after the recipient channel
+    pub fn handle<S>(
+        &mut self,
+        message_code: u8,
+        client_channel_no: u32,
+        mut data: Data,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let channel = match self.channels.get_mut(&client_channel_no) {
+            Some(channel) => channel,
+            None => return Ok(()),
+        };
+        match message_code {
+            ssh_msg_code::SSH_MSG_CHANNEL_DATA if !channel.local_close => {
+                let mut result = channel.recv(data, client, stream);
+                if result.is_ok() && self.waker.is_none() {
+                    result = channel.wait_replies(client, stream);
+                }
+                if let Err(e) = result {
+                    // the agent has gone, only this channel is affected
+                    log::error!("agent channel {} failed: {}", client_channel_no, e);
+                    channel.send_close(client, stream)?;
+                }
+            }
+            ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST => {
+                let to_add = data.get_u32();
+                channel.flow_control.on_recv(to_add);
+                channel.try_send_data(client, stream)?;
+            }
+            ssh_msg_code::SSH_MSG_CHANNEL_CLOSE => {
+                log::info!("agent channel {} closed.", client_channel_no);
+                if let Some(mut channel) = self.channels.remove(&client_channel_no) {
+                    channel.send_close(client, stream)?;
+                }
+            }
+            ssh_msg_code::SSH_MSG_CHANNEL_REQUEST => {
+                data.get_u8s();
+                if data.get_u8() != 0 {
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_FAILURE)
+                        .put_u32(channel.server_channel_no);
+                    data.pack(client).write_stream(stream)?;
+                }
+            }
+            x => log::debug!("agent channel ignore message {}", x),
+        }
+        Ok(())
+    }
+
+    /// send the replies of the agent ready by now
+    ///
+    pub fn poll<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        for (client_channel_no, channel) in self.channels.iter_mut() {
+            if channel.local_close {
+                continue;
+            }
+            if let Err(e) = channel.take_replies(client, stream) {
+                log::error!("agent channel {} failed: {}", client_channel_no, e);
+                channel.send_close(client, stream)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// the relay thread exits when the channel is dropped or the agent fails
+fn spawn_relay(
+    mut agent: AgentClient,
+    waker: Option<Waker>,
+) -> (Sender<Vec<u8>>, Receiver<SshResult<Vec<u8>>>) {
+    let (rqst_snd, rqst_rcv) = mpsc::channel::<Vec<u8>>();
+    let (reply_snd, reply_rcv) = mpsc::channel();
+    spawn(move || {
+        while let Ok(request) = rqst_rcv.recv() {
+            let reply = agent.relay(&request);
+            let failed = reply.is_err();
+            if reply_snd.send(reply).is_err() {
+                return;
+            }
+            if let Some(ref wake) = waker {
+                wake();
+            }
+            if failed {
+                return;
+            }
+        }
+    });
+    (rqst_snd, reply_rcv)
+}
+
+impl AgentChannel {
+    fn recv<S>(&mut self, mut data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let mut buf = data.get_u8s();
+        self.flow_control.tune_on_recv(&mut buf);
+
+        let mut adjust = Data::new();
+        adjust
+            .put_u8(ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST)
+            .put_u32(self.server_channel_no)
+            .put_u32(buf.len() as u32);
+        self.flow_control.on_send(buf.len() as u32);
+        adjust.pack(client).write_stream(stream)?;
+
+        self.request_buf.append(&mut buf);
+        while self.request_buf.len() >= 4 {
+            let len = u32::from_be_bytes(self.request_buf[..4].try_into().unwrap()) as usize;
+            if len == 0 || len > agent::MAX_MSG_LEN {
+                return Err(SshError::from("invalid agent message length."));
+            }
+            if self.request_buf.len() < 4 + len {
+                break;
+            }
+            let request = self.request_buf.drain(..4 + len).collect::<Vec<u8>>();
+            self.requests
+                .send(request)
+                .map_err(|_| SshError::from("the agent has gone."))?;
+            self.waiting += 1;
+        }
+        Ok(())
+    }
+
+    fn take_replies<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        while self.waiting > 0 {
+            match self.replies.try_recv() {
+                Ok(reply) => self.push_reply(reply?),
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    return Err(SshError::from("the agent has gone."))
+                }
+            }
+        }
+        self.try_send_data(client, stream)
+    }
+
+    fn wait_replies<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        while self.waiting > 0 {
+            match self.replies.recv_timeout(AGENT_TIMEOUT) {
+                Ok(reply) => self.push_reply(reply?),
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(SshError::from("the agent is not responding."))
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(SshError::from("the agent has gone."))
+                }
+            }
+        }
+        self.try_send_data(client, stream)
+    }
+
+    fn push_reply(&mut self, mut reply: Vec<u8>) {
+        self.waiting -= 1;
+        self.pending_send.append(&mut reply);
+    }
+
+    fn try_send_data<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        while !self.pending_send.is_empty() && self.flow_control.can_send() {
+            let maybe_remain = self.flow_control.tune_on_send(&mut self.pending_send);
+            let mut data = Data::new();
+            data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_DATA)
+                .put_u32(self.server_channel_no)
+                .put_u8s(&self.pending_send);
+            self.pending_send = maybe_remain;
+            data.pack(client).write_stream(stream)?;
+        }
+        Ok(())
+    }
+
+    fn send_close<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if self.local_close {
+            return Ok(());
+        }
+        self.local_close = true;
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_CLOSE)
+            .put_u32(self.server_channel_no);
+        data.pack(client).write_stream(stream)
+    }
+}
+
+#[cfg(unix)]
+fn connect(path: &Path) -> SshResult<AgentClient> {
+    AgentClient::connect(path)
+}
+
+#[cfg(not(unix))]
+fn connect(_path: &Path) -> SshResult<AgentClient> {
+    Err(SshError::from(
+        "agent forwarding is only supported on unix.",
+    ))
+}
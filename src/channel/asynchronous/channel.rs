@@ -0,0 +1,177 @@
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    channel::ExecOutput,
+    constant::ssh_msg_code,
+    error::{SshError, SshResult},
+    model::{AsyncRqst, BackendResp, Data},
+};
+
+use super::{channel_exec::AsyncExec, channel_scp::AsyncScp, channel_shell::AsyncShell};
+
+pub struct AsyncChannel {
+    pub(crate) client_channel_no: u32,
+    pub(crate) server_channel_no: u32,
+    pub(crate) rcv: UnboundedReceiver<BackendResp>,
+    pub(crate) snd: UnboundedSender<AsyncRqst>,
+    pub(crate) close: bool,
+}
+
+impl AsyncChannel {
+    pub(crate) fn new(
+        client_id: u32,
+        server_id: u32,
+        rcv: UnboundedReceiver<BackendResp>,
+        snd: UnboundedSender<AsyncRqst>,
+    ) -> Self {
+        Self {
+            client_channel_no: client_id,
+            server_channel_no: server_id,
+            rcv,
+            snd,
+            close: false,
+        }
+    }
+
+    /// open an [AsyncExec] channel which can excute commands
+    ///
+    pub fn exec(self) -> SshResult<AsyncExec> {
+        Ok(AsyncExec::open(self))
+    }
+
+    /// open an [AsyncScp] channel which can download/upload files/directories
+    ///
+    pub fn scp(self) -> SshResult<AsyncScp> {
+        Ok(AsyncScp::open(self))
+    }
+
+    /// open an [AsyncShell] channel which can be used as a pseudo terminal (AKA PTY)
+    ///
+    pub async fn shell(self) -> SshResult<AsyncShell> {
+        AsyncShell::open(self).await
+    }
+
+    /// close the channel and consume it
+    ///
+    pub fn close(mut self) -> SshResult<()> {
+        self.close_no_consume()
+    }
+
+    fn close_no_consume(&mut self) -> SshResult<()> {
+        if !self.close {
+            let mut data = Data::new();
+            data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_CLOSE)
+                .put_u32(self.server_channel_no);
+            self.close = true;
+            self.snd
+                .send(AsyncRqst::CloseChannel(self.client_channel_no, data))?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn send_data(&self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(AsyncRqst::Data(self.client_channel_no, data))?;
+        Ok(())
+    }
+
+    pub(super) async fn send(&mut self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(AsyncRqst::Command(self.client_channel_no, data))?;
+        if !self.close {
+            match self.rcv.recv().await {
+                Some(BackendResp::Ok(_)) => {
+                    log::trace!("{}: control command ok", self.client_channel_no)
+                }
+                Some(BackendResp::Fail(msg)) => log::error!(
+                    "{}: channel error with reason {}",
+                    self.client_channel_no,
+                    msg
+                ),
+                Some(_) => unreachable!(),
+                None => return Err(SshError::from("the session is closed.")),
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn recv(&mut self) -> SshResult<Vec<u8>> {
+        while !self.close {
+            match self.rcv.recv().await {
+                Some(BackendResp::Close) => {
+                    // the remote actively close their end
+                    // but we can send close later when the channel get dropped
+                    // just set a flag here
+                    self.close = true;
+                }
+                Some(BackendResp::Data(data)) => return Ok(data.into_inner()),
+                Some(BackendResp::ExtendedData(_))
+                | Some(BackendResp::ExitStatus(_))
+                | Some(BackendResp::ExitSignal(_)) => {
+                    log::trace!("{}: ignore non-stdout output", self.client_channel_no)
+                }
+                Some(_) => unreachable!(),
+                None => return Err(SshError::from("the session is closed.")),
+            }
+        }
+        Ok(vec![])
+    }
+
+    pub(super) fn try_recv(&mut self) -> SshResult<Option<Vec<u8>>> {
+        if !self.close {
+            while let Ok(resp) = self.rcv.try_recv() {
+                match resp {
+                    BackendResp::Close => {
+                        self.close = true;
+                        return Ok(None);
+                    }
+                    BackendResp::Data(data) => return Ok(Some(data.into_inner())),
+                    BackendResp::ExtendedData(_)
+                    | BackendResp::ExitStatus(_)
+                    | BackendResp::ExitSignal(_) => {
+                        log::trace!("{}: ignore non-stdout output", self.client_channel_no)
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Ok(None)
+        } else {
+            Err(SshError::from("Read data on a closed channel"))
+        }
+    }
+
+    pub(super) async fn recv_to_end(&mut self) -> SshResult<Vec<u8>> {
+        let mut buf = vec![];
+        while !self.close {
+            buf.append(&mut self.recv().await?);
+        }
+        Ok(buf)
+    }
+
+    /// receive until the channel is closed,
+    /// stdout, stderr and the exit status are all collected
+    ///
+    pub(super) async fn recv_output_to_end(&mut self) -> SshResult<ExecOutput> {
+        let mut output = ExecOutput::default();
+        while !self.close {
+            match self.rcv.recv().await {
+                Some(BackendResp::Close) => self.close = true,
+                Some(BackendResp::Data(data)) => output.stdout.append(&mut data.into_inner()),
+                Some(BackendResp::ExtendedData(data)) => {
+                    output.stderr.append(&mut data.into_inner())
+                }
+                Some(BackendResp::ExitStatus(status)) => output.exit_status = Some(status),
+                Some(BackendResp::ExitSignal(signal)) => output.exit_signal = Some(signal),
+                Some(_) => unreachable!(),
+                None => return Err(SshError::from("the session is closed.")),
+            }
+        }
+        Ok(output)
+    }
+}
+
+impl Drop for AsyncChannel {
+    fn drop(&mut self) {
+        let _ = self.close_no_consume();
+    }
+}
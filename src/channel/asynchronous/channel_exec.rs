@@ -0,0 +1,64 @@
+use super::channel::AsyncChannel;
+use crate::channel::ExecOutput;
+use crate::constant::{ssh_msg_code, ssh_str};
+use crate::error::SshResult;
+use crate::model::Data;
+use std::ops::{Deref, DerefMut};
+
+pub struct AsyncExec(AsyncChannel);
+
+impl AsyncExec {
+    pub(crate) fn open(channel: AsyncChannel) -> Self {
+        AsyncExec(channel)
+    }
+
+    /// Send an executable command to the server
+    ///
+    /// This method only waits for the server to accept the command, not for the result
+    ///
+    pub async fn send_command(&mut self, command: &str) -> SshResult<()> {
+        log::debug!("Send command {}", command);
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::EXEC)
+            .put_u8(true as u8)
+            .put_str(command);
+        self.send(data).await
+    }
+
+    /// Get the result of the prior command
+    ///
+    /// This method will wait until the server close the channel
+    ///
+    /// This method also implicitly consume the channel object,
+    /// since the exec channel can only execute one command
+    ///
+    pub async fn get_result(mut self) -> SshResult<Vec<u8>> {
+        self.recv_to_end().await
+    }
+
+    /// Get the stdout, stderr and exit status of the prior command
+    ///
+    /// This method will wait until the server close the channel
+    ///
+    /// This method also implicitly consume the channel object,
+    /// since the exec channel can only execute one command
+    ///
+    pub async fn get_output(mut self) -> SshResult<ExecOutput> {
+        self.recv_output_to_end().await
+    }
+}
+
+impl Deref for AsyncExec {
+    type Target = AsyncChannel;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for AsyncExec {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
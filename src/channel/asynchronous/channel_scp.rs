@@ -0,0 +1,436 @@
+use super::channel::AsyncChannel;
+use crate::{
+    channel::ScpBroker,
+    constant::{permission, scp, size},
+    error::{SshError, SshResult},
+    model::ScpFile,
+    util::file_time,
+};
+use crate::{
+    constant::{ssh_msg_code, ssh_str},
+    util,
+};
+use crate::{model::Data, util::check_path};
+use std::{
+    ffi::OsStr,
+    fs::{self, File, OpenOptions},
+    future::Future,
+    io::{Read, Write},
+    ops::{Deref, DerefMut},
+    path::Path,
+    pin::Pin,
+};
+
+pub struct AsyncScp(AsyncChannel);
+
+impl AsyncScp {
+    pub(crate) fn open(channel: AsyncChannel) -> Self {
+        AsyncScp(channel)
+    }
+
+    async fn exec_scp(&mut self, command: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::EXEC)
+            .put_u8(true as u8)
+            .put_str(command);
+        self.send(data).await
+    }
+
+    fn command_init(&self, remote_path: &str, arg: &str) -> String {
+        format!(
+            "{} {} {} {} {} {}",
+            ssh_str::SCP,
+            arg,
+            scp::QUIET,
+            scp::RECURSIVE,
+            scp::PRESERVE_TIMES,
+            remote_path
+        )
+    }
+
+    fn send_end(&mut self) -> SshResult<()> {
+        self.send_bytes(&[scp::END])
+    }
+
+    async fn get_end(&mut self) -> SshResult<()> {
+        let vec = self.recv().await?;
+        if vec.is_empty() {
+            Err(SshError::from("read a closed channel"))
+        } else {
+            match vec[0] {
+                scp::END => Ok(()),
+                // error
+                scp::ERR | scp::FATAL_ERR => Err(SshError::from(util::from_utf8(vec)?)),
+                _ => Err(SshError::from("unknown error.")),
+            }
+        }
+    }
+
+    fn send_bytes(&mut self, bytes: &[u8]) -> SshResult<()> {
+        self.send_data(bytes.to_vec().into())?;
+        Ok(())
+    }
+}
+
+// upload related
+impl AsyncScp {
+    /// upload a file from local path to remote path
+    ///
+    /// this method is equivalent to shell command
+    /// ```bash
+    /// scp -P port local_path user@ip:remote_path
+    /// ```
+    ///
+    pub async fn upload<P: AsRef<OsStr> + ?Sized>(
+        mut self,
+        local_path: &P,
+        remote_path: &P,
+    ) -> SshResult<()> {
+        let local_path = Path::new(local_path);
+        let remote_path = Path::new(remote_path);
+
+        check_path(local_path)?;
+        check_path(remote_path)?;
+
+        let remote_path_str = remote_path.to_str().unwrap();
+        let local_path_str = local_path.to_str().unwrap();
+
+        log::info!(
+            "start to upload files, \
+        local [{}] files will be synchronized to the remote [{}] folder.",
+            local_path_str,
+            remote_path_str
+        );
+
+        let command = self.command_init(remote_path_str, scp::SINK);
+        self.exec_scp(&command).await?;
+        self.get_end().await?;
+        let mut scp_file = ScpFile::new();
+        scp_file.local_path = local_path.to_path_buf();
+        self.file_all(&mut scp_file).await?;
+
+        log::info!("files upload successful.");
+
+        self.0.close()
+    }
+
+    // recursive, so the future is boxed
+    fn file_all<'a>(
+        &'a mut self,
+        scp_file: &'a mut ScpFile,
+    ) -> Pin<Box<dyn Future<Output = SshResult<()>> + Send + 'a>> {
+        Box::pin(async move {
+            // 如果获取不到文件或者目录名的话，就不处理该数据
+            // 如果文件不是有效的Unicode数据的话，也不处理
+            scp_file.name = match scp_file.local_path.file_name() {
+                None => return Ok(()),
+                Some(name) => match name.to_str() {
+                    None => return Ok(()),
+                    Some(name) => name.to_string(),
+                },
+            };
+            self.send_time(scp_file).await?;
+            if scp_file.local_path.is_dir() {
+                // 文件夹如果读取异常的话。就略过该文件夹
+                // 详细的错误信息请查看 [std::fs::read_dir] 方法介绍
+                let entries = match fs::read_dir(scp_file.local_path.as_path()) {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        log::error!("read dir error, error info: {}", e);
+                        return Ok(());
+                    }
+                };
+                self.send_dir(scp_file).await?;
+                for p in entries {
+                    match p {
+                        Ok(dir_entry) => {
+                            scp_file.local_path = dir_entry.path().clone();
+                            self.file_all(scp_file).await?
+                        }
+                        Err(e) => {
+                            // 暂不处理
+                            log::error!("dir entry error, error info: {}", e);
+                        }
+                    }
+                }
+
+                self.send_bytes(&[scp::E, b'\n'])?;
+                self.get_end().await?;
+            } else {
+                scp_file.size = scp_file.local_path.as_path().metadata()?.len();
+                self.send_file(scp_file).await?
+            }
+            Ok(())
+        })
+    }
+
+    async fn send_file(&mut self, scp_file: &mut ScpFile) -> SshResult<()> {
+        let mut file = match File::open(scp_file.local_path.as_path()) {
+            Ok(f) => f,
+            // 文件打开异常，不影响后续操作
+            Err(e) => {
+                log::error!(
+                    "failed to open the folder, \
+            it is possible that the path does not exist, \
+            which does not affect subsequent operations. \
+            error info: {:?}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+
+        log::debug!(
+            "name: [{}] size: [{}] type: [file] start upload.",
+            scp_file.name,
+            scp_file.size
+        );
+
+        let cmd = format!(
+            "C0{} {} {}\n",
+            permission::FILE,
+            scp_file.size,
+            scp_file.name
+        );
+        self.send_bytes(cmd.as_bytes())?;
+        self.get_end().await?;
+
+        let mut count = 0;
+        let mut chunk = [0u8; size::FILE_CHUNK];
+
+        loop {
+            let i = file.read(&mut chunk)?;
+            count += i;
+            self.send_bytes(&chunk[..i])?;
+            if count == scp_file.size as usize {
+                self.send_end()?;
+                break;
+            }
+        }
+        self.get_end().await?;
+
+        log::debug!("file: [{}] upload completed.", scp_file.name);
+
+        Ok(())
+    }
+
+    async fn send_dir(&mut self, scp_file: &ScpFile) -> SshResult<()> {
+        log::debug!(
+            "name: [{}] size: [0], type: [dir] start upload.",
+            scp_file.name
+        );
+
+        let cmd = format!("D0{} 0 {}\n", permission::DIR, scp_file.name);
+        self.send_bytes(cmd.as_bytes())?;
+        self.get_end().await?;
+
+        log::debug!("dir: [{}] upload completed.", scp_file.name);
+
+        Ok(())
+    }
+
+    async fn send_time(&mut self, scp_file: &mut ScpFile) -> SshResult<()> {
+        ScpBroker::get_time(scp_file)?;
+        let cmd = format!("T{} 0 {} 0\n", scp_file.modify_time, scp_file.access_time);
+        self.send_bytes(cmd.as_bytes())?;
+        self.get_end().await
+    }
+}
+
+// download related
+impl AsyncScp {
+    /// download a file from remote path to local path
+    ///
+    /// this method is equivalent to shell command
+    /// ```bash
+    /// scp -P port user@ip:remote_path local_path
+    /// ```
+    ///
+    pub async fn download<P: AsRef<OsStr> + ?Sized>(
+        mut self,
+        local_path: &P,
+        remote_path: &P,
+    ) -> SshResult<()> {
+        let local_path = Path::new(local_path);
+        let remote_path = Path::new(remote_path);
+
+        check_path(local_path)?;
+        check_path(remote_path)?;
+
+        let local_path_str = local_path.to_str().unwrap();
+        let remote_path_str = remote_path.to_str().unwrap();
+
+        log::info!(
+            "start to download files, \
+        remote [{}] files will be synchronized to the local [{}] folder.",
+            remote_path_str,
+            local_path_str
+        );
+
+        let command = self.command_init(remote_path_str, scp::SOURCE);
+        self.exec_scp(&command).await?;
+        let mut scp_file = ScpFile::new();
+        scp_file.local_path = local_path.to_path_buf();
+        self.process_d(&mut scp_file, local_path).await
+    }
+
+    async fn process_d(&mut self, scp_file: &mut ScpFile, local_path: &Path) -> SshResult<()> {
+        while !self.close {
+            self.send_end()?;
+            let data = self.recv().await?;
+            if data.is_empty() {
+                break;
+            }
+            let code = &data[0];
+            match *code {
+                scp::T => {
+                    // 处理时间
+                    let (modify_time, access_time) = file_time(data)?;
+                    scp_file.modify_time = modify_time;
+                    scp_file.access_time = access_time;
+                }
+                scp::C => self.process_file_d(data, scp_file).await?,
+                scp::D => self.process_dir_d(data, scp_file)?,
+                scp::E => match scp_file.local_path.parent() {
+                    None => {}
+                    Some(v) => {
+                        let buf = v.to_path_buf();
+                        if !buf.eq(local_path) {
+                            scp_file.local_path = buf;
+                        }
+                    }
+                },
+                // error
+                scp::ERR | scp::FATAL_ERR => return Err(SshError::from(util::from_utf8(data)?)),
+                _ => return Err(SshError::from("unknown error.")),
+            }
+        }
+        Ok(())
+    }
+
+    fn process_dir_d(&mut self, data: Vec<u8>, scp_file: &mut ScpFile) -> SshResult<()> {
+        let string = util::from_utf8(data)?;
+        let dir_info = string.trim();
+        let split = dir_info.split(' ').collect::<Vec<&str>>();
+        match split.get(2) {
+            None => return Ok(()),
+            Some(v) => scp_file.name = v.to_string(),
+        }
+        scp_file.is_dir = true;
+        let buf = scp_file.join(&scp_file.name);
+        log::debug!(
+            "name: [{}] size: [0], type: [dir] start download.",
+            scp_file.name
+        );
+        if !buf.exists() {
+            fs::create_dir(buf.as_path())?;
+        }
+
+        scp_file.local_path = buf;
+
+        #[cfg(windows)]
+        ScpBroker::sync_permissions(scp_file);
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            match fs::File::open(scp_file.local_path.as_path()) {
+                Ok(file) => {
+                    ScpBroker::sync_permissions(scp_file, file);
+                }
+                Err(e) => {
+                    log::error!(
+                        "failed to open the folder, \
+            it is possible that the path does not exist, \
+            which does not affect subsequent operations. \
+            error info: {:?}, path: {:?}",
+                        e,
+                        scp_file.local_path.to_str()
+                    );
+                    return Err(SshError::from(format!("file open error: {}", e)));
+                }
+            };
+        }
+
+        log::debug!("dir: [{}] download completed.", scp_file.name);
+        Ok(())
+    }
+
+    async fn process_file_d(&mut self, data: Vec<u8>, scp_file: &mut ScpFile) -> SshResult<()> {
+        let string = util::from_utf8(data)?;
+        let file_info = string.trim();
+        let split = file_info.split(' ').collect::<Vec<&str>>();
+        let size_str = *split.get(1).unwrap_or(&"0");
+        let size = util::str_to_i64(size_str)?;
+        scp_file.size = size as u64;
+        match split.get(2) {
+            None => return Ok(()),
+            Some(v) => scp_file.name = v.to_string(),
+        }
+        scp_file.is_dir = false;
+        self.save_file(scp_file).await
+    }
+
+    async fn save_file(&mut self, scp_file: &mut ScpFile) -> SshResult<()> {
+        log::debug!(
+            "name: [{}] size: [{}] type: [file] start download.",
+            scp_file.name,
+            scp_file.size
+        );
+        let path = scp_file.join(&scp_file.name);
+        if path.exists() {
+            fs::remove_file(path.as_path())?;
+        }
+        let mut file = match OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path.as_path())
+        {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("file processing error, error info: {}", e);
+                return Err(SshError::from(format!(
+                    "{:?} file processing exception",
+                    path
+                )));
+            }
+        };
+        self.send_end()?;
+        let mut count = 0;
+        while !self.close {
+            let data = self.recv().await?;
+            if data.is_empty() {
+                break;
+            }
+            count += data.len() as u64;
+            if count == scp_file.size + 1 {
+                file.write_all(&data[..(data.len() - 1)])?;
+                break;
+            }
+            file.write_all(&data)?;
+        }
+
+        #[cfg(windows)]
+        ScpBroker::sync_permissions(scp_file);
+
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        ScpBroker::sync_permissions(scp_file, file);
+
+        log::debug!("file: [{}] download completed.", scp_file.name);
+        Ok(())
+    }
+}
+
+impl Deref for AsyncScp {
+    type Target = AsyncChannel;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for AsyncScp {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
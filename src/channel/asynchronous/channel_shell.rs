@@ -0,0 +1,83 @@
+use super::channel::AsyncChannel;
+use crate::constant::{ssh_msg_code, ssh_str};
+use crate::error::SshResult;
+use crate::model::Data;
+use std::ops::{Deref, DerefMut};
+
+pub struct AsyncShell(AsyncChannel);
+
+impl AsyncShell {
+    pub(crate) async fn open(channel: AsyncChannel) -> SshResult<Self> {
+        // shell 形式需要一个伪终端
+        let mut channel_shell = AsyncShell(channel);
+        channel_shell.request_pty().await?;
+        channel_shell.get_shell().await?;
+        Ok(channel_shell)
+    }
+
+    async fn request_pty(&mut self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::PTY_REQ)
+            .put_u8(true as u8)
+            .put_str(ssh_str::XTERM_VAR)
+            .put_u32(80)
+            .put_u32(24)
+            .put_u32(640)
+            .put_u32(480);
+        let model = [
+            128, // TTY_OP_ISPEED
+            0, 1, 0xc2, 0,   // 115200
+            129, // TTY_OP_OSPEED
+            0, 1, 0xc2, 0,    // 115200 again
+            0_u8, // TTY_OP_END
+        ];
+        data.put_u8s(&model);
+        self.send(data).await
+    }
+
+    async fn get_shell(&mut self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SHELL)
+            .put_u8(true as u8);
+        self.send(data).await
+    }
+
+    /// this method will try to read as much data as we can from the server,
+    /// but it will wait until at least one packet is received
+    ///
+    pub async fn read(&mut self) -> SshResult<Vec<u8>> {
+        let mut out = self.recv().await?;
+        while let Ok(Some(mut data)) = self.try_recv() {
+            out.append(&mut data)
+        }
+        Ok(out)
+    }
+
+    /// this method send `buf` to the remote pty
+    ///
+    pub fn write(&mut self, buf: &[u8]) -> SshResult<()> {
+        self.send_data(buf.to_vec().into())?;
+        Ok(())
+    }
+
+    pub fn close(self) -> SshResult<()> {
+        self.0.close()
+    }
+}
+
+impl Deref for AsyncShell {
+    type Target = AsyncChannel;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for AsyncShell {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
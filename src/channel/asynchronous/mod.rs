@@ -0,0 +1,9 @@
+mod channel;
+mod channel_exec;
+mod channel_scp;
+mod channel_shell;
+
+pub use channel::AsyncChannel;
+pub use channel_exec::AsyncExec;
+pub use channel_scp::AsyncScp;
+pub use channel_shell::AsyncShell;
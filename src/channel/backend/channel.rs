@@ -1,51 +1,101 @@
 use std::{
-    io::{Read, Write},
+    cell::RefCell,
+    collections::VecDeque,
+    io::Write,
     sync::mpsc::{Receiver, Sender},
     vec,
 };
 
 use crate::{
+    channel::{sftp::SftpIo, ExecOutput, ExitSignal, SftpBroker},
     client::Client,
-    constant::ssh_msg_code,
+    constant::{ssh_msg_code, ssh_str},
     error::{SshError, SshResult},
-    model::{BackendResp, BackendRqst, Data, FlowControl, Packet},
+    model::{BackendResp, BackendRqst, Data, FlowControl, Packet, RespSender},
+    util,
 };
 
 use super::{channel_exec::ExecBroker, channel_scp::ScpBroker, channel_shell::ShellBrocker};
 
-pub(crate) struct Channel {
-    snd: Sender<BackendResp>,
+// the responses are sent by `R`, which is a tokio sender for async sessions
+pub(crate) struct Channel<R = Sender<BackendResp>>
+where
+    R: RespSender,
+{
+    snd: R,
     server_channel_no: u32,
     client_channel_no: u32,
     remote_close: bool,
     local_close: bool,
+    // the user side has been dropped, the data is discarded
+    local_gone: bool,
     flow_control: FlowControl,
     pending_send: Vec<u8>,
 }
 
-impl Channel {
-    pub fn new(
+impl<R> Channel<R>
+where
+    R: RespSender,
+{
+    pub fn new<S>(
         server_channel_no: u32,
         client_channel_no: u32,
         remote_window: u32,
-        snd: Sender<BackendResp>,
-    ) -> SshResult<Self> {
-        snd.send(BackendResp::Ok(server_channel_no))?;
-
-        Ok(Self {
+        snd: R,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<Self>
+    where
+        S: Write,
+    {
+        let mut channel = Self {
             snd,
             server_channel_no,
             client_channel_no,
             remote_close: false,
             local_close: false,
+            local_gone: false,
             flow_control: FlowControl::new(remote_window),
             pending_send: vec![],
-        })
+        };
+        channel.deliver(BackendResp::Ok(server_channel_no), client, stream)?;
+        Ok(channel)
+    }
+
+    // the user side may have been dropped, e.g. a forwarding broker whose local peer has gone,
+    // which only closes this channel rather than ending the whole session
+    fn deliver<S>(
+        &mut self,
+        resp: BackendResp,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if self.local_gone {
+            return Ok(());
+        }
+        if self.snd.send_resp(resp).is_err() {
+            log::info!(
+                "Channel {} is dropped by the user, close it",
+                self.client_channel_no
+            );
+            self.local_gone = true;
+            if !self.local_close {
+                let mut data = Data::new();
+                data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_CLOSE)
+                    .put_u32(self.server_channel_no);
+                self.send(data, client, stream)?;
+                self.local_close = true;
+            }
+        }
+        Ok(())
     }
 
     pub fn send_data<S>(&mut self, data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         self.pending_send.append(&mut data.into_inner());
         self.try_send_data(client, stream)
@@ -53,7 +103,7 @@ impl Channel {
 
     fn try_send_data<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         // try to send as much as we can
         while !self.pending_send.is_empty() {
@@ -79,7 +129,7 @@ impl Channel {
 
     pub fn send<S>(&mut self, data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         if !self.is_close() {
             data.pack(client).write_stream(stream)
@@ -90,13 +140,69 @@ impl Channel {
 
     pub fn recv<S>(&mut self, mut data: Data, client: &mut Client, stream: &mut S) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         let mut buf = data.get_u8s();
         // flow_control
         self.flow_control.tune_on_recv(&mut buf);
         self.send_window_adjust(buf.len() as u32, client, stream)?;
-        self.snd.send(BackendResp::Data(buf.into()))?;
+        self.deliver(BackendResp::Data(buf.into()), client, stream)
+    }
+
+    pub fn recv_extended<S>(
+        &mut self,
+        mut data: Data,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let data_type = data.get_u32();
+        let mut buf = data.get_u8s();
+        // flow_control
+        self.flow_control.tune_on_recv(&mut buf);
+        self.send_window_adjust(buf.len() as u32, client, stream)?;
+        if data_type == ssh_msg_code::SSH_EXTENDED_DATA_STDERR {
+            self.deliver(BackendResp::ExtendedData(buf.into()), client, stream)?;
+        }
+        Ok(())
+    }
+
+    pub fn recv_request<S>(
+        &mut self,
+        mut data: Data,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let request = util::from_utf8(data.get_u8s())?;
+        let want_reply = data.get_u8() != 0;
+        match request.as_str() {
+            ssh_str::EXIT_STATUS => {
+                let status = data.get_u32();
+                self.deliver(BackendResp::ExitStatus(status), client, stream)?;
+            }
+            ssh_str::EXIT_SIGNAL => {
+                let signal = ExitSignal::unpack(&mut data)?;
+                self.deliver(BackendResp::ExitSignal(signal), client, stream)?;
+            }
+            x => {
+                log::debug!(
+                    "Channel {} currently ignore request {}",
+                    self.client_channel_no,
+                    x
+                );
+                if want_reply {
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_FAILURE)
+                        .put_u32(self.server_channel_no);
+                    self.send(data, client, stream)?;
+                }
+            }
+        }
         Ok(())
     }
 
@@ -107,7 +213,7 @@ impl Channel {
         stream: &mut S,
     ) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         let mut data = Data::new();
         data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST)
@@ -124,7 +230,7 @@ impl Channel {
         stream: &mut S,
     ) -> SshResult<()>
     where
-        S: Read + Write,
+        S: Write,
     {
         self.flow_control.on_recv(to_add);
         if !self.pending_send.is_empty() {
@@ -134,29 +240,49 @@ impl Channel {
         }
     }
 
-    pub fn local_close(&mut self) -> SshResult<()> {
+    /// send the close message of the user unless it is already sent
+    pub fn local_close<S>(
+        &mut self,
+        data: Data,
+        client: &mut Client,
+        stream: &mut S,
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        if self.local_close {
+            return Ok(());
+        }
         log::trace!("Channel {} send local close", self.client_channel_no);
+        self.send(data, client, stream)?;
         self.local_close = true;
         Ok(())
     }
 
-    pub fn remote_close(&mut self) -> SshResult<()> {
+    pub fn remote_close<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
         log::trace!("Channel {} recv remote close", self.client_channel_no);
         self.remote_close = true;
         if !self.local_close {
-            self.snd.send(BackendResp::Close)?;
+            self.deliver(BackendResp::Close, client, stream)?;
         }
         Ok(())
     }
 
-    pub fn success(&mut self) -> SshResult<()> {
-        self.snd.send(BackendResp::Ok(self.client_channel_no))?;
-        Ok(())
+    pub fn success<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.deliver(BackendResp::Ok(self.client_channel_no), client, stream)
     }
 
-    pub fn failed(&mut self) -> SshResult<()> {
-        self.snd.send(BackendResp::Fail("".to_owned()))?;
-        Ok(())
+    pub fn failed<S>(&mut self, client: &mut Client, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        self.deliver(BackendResp::Fail("".to_owned()), client, stream)
     }
 
     pub fn is_close(&self) -> bool {
@@ -164,7 +290,10 @@ impl Channel {
     }
 }
 
-impl Drop for Channel {
+impl<R> Drop for Channel<R>
+where
+    R: RespSender,
+{
     fn drop(&mut self) {
         log::info!("Channel {} closed", self.client_channel_no);
     }
@@ -176,6 +305,8 @@ pub struct ChannelBroker {
     pub(crate) rcv: Receiver<BackendResp>,
     pub(crate) snd: Sender<BackendRqst>,
     pub(crate) close: bool,
+    // the output received while waiting for the reply of a command
+    pub(crate) buffered: RefCell<VecDeque<BackendResp>>,
 }
 
 impl ChannelBroker {
@@ -191,6 +322,7 @@ impl ChannelBroker {
             rcv,
             snd,
             close: false,
+            buffered: RefCell::new(VecDeque::new()),
         }
     }
 
@@ -212,6 +344,32 @@ impl ChannelBroker {
         ShellBrocker::open(self)
     }
 
+    /// start the `sftp` subsystem and convert the raw channel to an [SftpBroker]
+    ///
+    pub fn sftp(self) -> SshResult<SftpBroker> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SUBSYSTEM)
+            .put_u8(true as u8)
+            .put_str(ssh_str::SFTP);
+        self.send_and_wait(data)?;
+        SftpBroker::start(self)
+    }
+
+    /// ask the server to forward the connections to the agent back,
+    /// which is `ssh -A`
+    ///
+    pub(crate) fn request_agent_forwarding(&self) -> SshResult<()> {
+        log::info!("request agent forwarding.");
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::AUTH_AGENT_REQ)
+            .put_u8(true as u8);
+        self.send(data)
+    }
+
     /// close the backend channel and consume the channel broker itself
     ///
     pub fn close(mut self) -> SshResult<()> {
@@ -240,7 +398,7 @@ impl ChannelBroker {
         self.snd
             .send(BackendRqst::Command(self.client_channel_no, data))?;
         if !self.close {
-            match self.rcv.recv().unwrap() {
+            match self.wait_reply()? {
                 BackendResp::Ok(_) => log::trace!("{}: control command ok", self.client_channel_no),
                 BackendResp::Fail(msg) => log::error!(
                     "{}: channel error with reason {}",
@@ -253,41 +411,89 @@ impl ChannelBroker {
         Ok(())
     }
 
+    // unlike `send`, a failure reply is returned as an error
+    fn send_and_wait(&self, data: Data) -> SshResult<()> {
+        self.snd
+            .send(BackendRqst::Command(self.client_channel_no, data))?;
+        match self.wait_reply()? {
+            BackendResp::Ok(_) => Ok(()),
+            BackendResp::Fail(_) => Err(SshError::from("channel failure.")),
+            _ => unreachable!(),
+        }
+    }
+
+    // the reply of a command, the output arrived before it is kept for `recv`
+    fn wait_reply(&self) -> SshResult<BackendResp> {
+        loop {
+            match self.rcv.recv()? {
+                resp @ (BackendResp::Ok(_) | BackendResp::Fail(_)) => return Ok(resp),
+                // the remote closed before replying
+                BackendResp::Close => {
+                    self.buffered.borrow_mut().push_back(BackendResp::Close);
+                    return Ok(BackendResp::Fail("channel closed.".to_owned()));
+                }
+                resp => self.buffered.borrow_mut().push_back(resp),
+            }
+        }
+    }
+
+    // the buffered output first
+    fn next_resp(&self) -> SshResult<BackendResp> {
+        if let Some(resp) = self.buffered.borrow_mut().pop_front() {
+            return Ok(resp);
+        }
+        Ok(self.rcv.recv()?)
+    }
+
+    fn try_next_resp(&self) -> Option<BackendResp> {
+        if let Some(resp) = self.buffered.borrow_mut().pop_front() {
+            return Some(resp);
+        }
+        self.rcv.try_recv().ok()
+    }
+
     pub(super) fn recv(&mut self) -> SshResult<Vec<u8>> {
-        if self.close {
-            Ok(vec![])
-        } else {
-            match self.rcv.recv().unwrap() {
+        while !self.close {
+            match self.next_resp()? {
                 BackendResp::Close => {
                     // the remote actively close their end
                     // but we can send close later when the broker get dropped
                     // just set a flag here
                     self.close = true;
-                    Ok(vec![])
                 }
-                BackendResp::Data(data) => Ok(data.into_inner()),
+                BackendResp::Data(data) => return Ok(data.into_inner()),
+                BackendResp::ExtendedData(_)
+                | BackendResp::ExitStatus(_)
+                | BackendResp::ExitSignal(_) => {
+                    log::trace!("{}: ignore non-stdout output", self.client_channel_no)
+                }
                 _ => unreachable!(),
             }
         }
+        Ok(vec![])
     }
 
     pub(super) fn try_recv(&mut self) -> SshResult<Option<Vec<u8>>> {
         if !self.close {
-            if let Ok(rqst) = self.rcv.try_recv() {
+            while let Some(rqst) = self.try_next_resp() {
                 match rqst {
                     BackendResp::Close => {
                         // the remote actively close their end
                         // but we can send close later when the broker get dropped
                         // just set a flag here
                         self.close = true;
-                        Ok(None)
+                        return Ok(None);
+                    }
+                    BackendResp::Data(data) => return Ok(Some(data.into_inner())),
+                    BackendResp::ExtendedData(_)
+                    | BackendResp::ExitStatus(_)
+                    | BackendResp::ExitSignal(_) => {
+                        log::trace!("{}: ignore non-stdout output", self.client_channel_no)
                     }
-                    BackendResp::Data(data) => Ok(Some(data.into_inner())),
                     _ => unreachable!(),
                 }
-            } else {
-                Ok(None)
             }
+            Ok(None)
         } else {
             Err(SshError::from("Read data on a closed channel"))
         }
@@ -300,6 +506,24 @@ impl ChannelBroker {
         }
         Ok(buf)
     }
+
+    /// receive until the channel is closed,
+    /// stdout, stderr and the exit status are all collected
+    ///
+    pub(super) fn recv_output_to_end(&mut self) -> SshResult<ExecOutput> {
+        let mut output = ExecOutput::default();
+        while !self.close {
+            match self.next_resp()? {
+                BackendResp::Close => self.close = true,
+                BackendResp::Data(data) => output.stdout.append(&mut data.into_inner()),
+                BackendResp::ExtendedData(data) => output.stderr.append(&mut data.into_inner()),
+                BackendResp::ExitStatus(status) => output.exit_status = Some(status),
+                BackendResp::ExitSignal(signal) => output.exit_signal = Some(signal),
+                _ => unreachable!(),
+            }
+        }
+        Ok(output)
+    }
 }
 
 impl Drop for ChannelBroker {
@@ -307,3 +531,18 @@ impl Drop for ChannelBroker {
         let _ = self.close_no_consue();
     }
 }
+
+impl SftpIo for ChannelBroker {
+    fn send_bytes(&mut self, buf: Vec<u8>) -> SshResult<Vec<u8>> {
+        self.send_data(buf.into())?;
+        Ok(vec![])
+    }
+
+    fn recv_bytes(&mut self) -> SshResult<Vec<u8>> {
+        self.recv()
+    }
+
+    fn close_channel(&mut self) -> SshResult<()> {
+        self.close_no_consue()
+    }
+}
@@ -0,0 +1,182 @@
+use super::channel::ChannelBroker;
+use crate::constant::ssh_msg_code;
+use crate::error::{SshError, SshResult};
+use crate::model::{BackendRqst, Data};
+use crate::BackendStream;
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpStream},
+    ops::{Deref, DerefMut},
+    sync::mpsc::Sender,
+    thread,
+};
+
+/// A `direct-tcpip` channel,
+/// the data written to it is relayed to the target by the server
+///
+pub struct DirectTcpipBroker {
+    channel: ChannelBroker,
+    read_buf: Vec<u8>,
+    nonblocking: bool,
+}
+
+impl DirectTcpipBroker {
+    pub(crate) fn open(channel: ChannelBroker) -> Self {
+        DirectTcpipBroker {
+            channel,
+            read_buf: vec![],
+            nonblocking: false,
+        }
+    }
+
+    /// in nonblocking mode, `read` returns `WouldBlock` instead of waiting for the data
+    ///
+    pub fn set_nonblocking(&mut self, nonblocking: bool) {
+        self.nonblocking = nonblocking;
+    }
+
+    /// tell the server that no more data will be sent
+    ///
+    pub fn send_eof(&self) -> SshResult<()> {
+        self.writer().send_eof()
+    }
+
+    fn writer(&self) -> ChannelWriter {
+        ChannelWriter {
+            client_channel_no: self.client_channel_no,
+            server_channel_no: self.server_channel_no,
+            snd: self.snd.clone(),
+        }
+    }
+
+    /// copy the data between the channel and `tcp` in both directions,
+    /// until the server closes the channel
+    ///
+    pub(crate) fn relay(mut self, tcp: TcpStream) -> SshResult<()> {
+        let mut tcp_read = tcp.try_clone()?;
+        let mut writer = self.writer();
+        let upstream = thread::spawn(move || {
+            if let Err(e) = io::copy(&mut tcp_read, &mut writer) {
+                log::debug!("forwarding to channel stopped: {}", e);
+            }
+            let _ = writer.send_eof();
+        });
+
+        let mut tcp_write = tcp;
+        let result = io::copy(&mut self, &mut tcp_write);
+        // wake up the upstream thread if it is still reading
+        let _ = tcp_write.shutdown(Shutdown::Both);
+        let _ = upstream.join();
+        result?;
+        Ok(())
+    }
+}
+
+impl Read for DirectTcpipBroker {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_buf.is_empty() {
+            if !self.nonblocking {
+                // an empty result means the channel is closed
+                self.read_buf = self.channel.recv()?;
+            } else if self.channel.close {
+                return Ok(0);
+            } else {
+                match self.channel.try_recv()? {
+                    Some(data) => self.read_buf = data,
+                    None if self.channel.close => return Ok(0),
+                    None => return Err(io::ErrorKind::WouldBlock.into()),
+                }
+            }
+        }
+        let n = self.read_buf.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.read_buf[..n]);
+        self.read_buf.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for DirectTcpipBroker {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.send_data(buf.to_vec().into())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// the next hop of a jump host, the channel is read in blocking mode
+// & written by its sending half
+impl BackendStream for DirectTcpipBroker {
+    type Reader = DirectTcpipBroker;
+    type Writer = ChannelWriter;
+
+    fn split(mut self) -> io::Result<(DirectTcpipBroker, ChannelWriter)> {
+        self.set_nonblocking(false);
+        let writer = self.writer();
+        Ok((self, writer))
+    }
+
+    fn shutdown(writer: &mut ChannelWriter) {
+        let _ = writer.close();
+    }
+}
+
+impl Deref for DirectTcpipBroker {
+    type Target = ChannelBroker;
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
+
+impl DerefMut for DirectTcpipBroker {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.channel
+    }
+}
+
+// the sending half of a channel,
+// so the two directions can be served by different threads
+pub(crate) struct ChannelWriter {
+    client_channel_no: u32,
+    server_channel_no: u32,
+    snd: Sender<BackendRqst>,
+}
+
+impl ChannelWriter {
+    fn send_eof(&self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_EOF)
+            .put_u32(self.server_channel_no);
+        self.snd
+            .send(BackendRqst::Command(self.client_channel_no, data))
+            .map_err(SshError::from)
+    }
+
+    // the reading half gets an empty read once the channel is closed
+    fn close(&self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_CLOSE)
+            .put_u32(self.server_channel_no);
+        self.snd
+            .send(BackendRqst::CloseChannel(self.client_channel_no, data))
+            .map_err(SshError::from)
+    }
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.snd
+            .send(BackendRqst::Data(
+                self.client_channel_no,
+                buf.to_vec().into(),
+            ))
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
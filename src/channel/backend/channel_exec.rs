@@ -1,4 +1,5 @@
 use super::channel::ChannelBroker;
+use crate::channel::ExecOutput;
 use crate::constant::{ssh_msg_code, ssh_str};
 use crate::error::SshResult;
 use crate::model::Data;
@@ -36,6 +37,17 @@ impl ExecBroker {
     pub fn get_result(mut self) -> SshResult<Vec<u8>> {
         self.recv_to_end()
     }
+
+    /// Get the stdout, stderr and exit status of the prior command
+    ///
+    /// This method will block until the server close the channel
+    ///
+    /// This method also implicitly consume the channel object,
+    /// since the exec channel can only execute one command
+    ///
+    pub fn get_output(mut self) -> SshResult<ExecOutput> {
+        self.recv_output_to_end()
+    }
 }
 
 impl Deref for ExecBroker {
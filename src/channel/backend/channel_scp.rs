@@ -220,13 +220,13 @@ impl ScpBroker {
     }
 
     fn send_time(&mut self, scp_file: &mut ScpFile) -> SshResult<()> {
-        self.get_time(scp_file)?;
+        Self::get_time(scp_file)?;
         let cmd = format!("T{} 0 {} 0\n", scp_file.modify_time, scp_file.access_time);
         self.send_bytes(cmd.as_bytes())?;
         self.get_end()
     }
 
-    fn get_time(&self, scp_file: &mut ScpFile) -> SshResult<()> {
+    pub(crate) fn get_time(scp_file: &mut ScpFile) -> SshResult<()> {
         let metadata = scp_file.local_path.as_path().metadata()?;
         // 最后修改时间
         let modified_time = match metadata.modified() {
@@ -353,13 +353,13 @@ impl ScpBroker {
         scp_file.local_path = buf;
 
         #[cfg(windows)]
-        self.sync_permissions(scp_file);
+        Self::sync_permissions(scp_file);
 
         #[cfg(any(target_os = "linux", target_os = "macos"))]
         {
             match fs::File::open(scp_file.local_path.as_path()) {
                 Ok(file) => {
-                    self.sync_permissions(scp_file, file);
+                    Self::sync_permissions(scp_file, file);
                 }
                 Err(e) => {
                     log::error!(
@@ -439,17 +439,17 @@ impl ScpBroker {
         }
 
         #[cfg(windows)]
-        self.sync_permissions(scp_file);
+        Self::sync_permissions(scp_file);
 
         #[cfg(any(target_os = "linux", target_os = "macos"))]
-        self.sync_permissions(scp_file, file);
+        Self::sync_permissions(scp_file, file);
 
         log::debug!("file: [{}] download completed.", scp_file.name);
         Ok(())
     }
 
     #[cfg(windows)]
-    fn sync_permissions(&self, scp_file: &mut ScpFile) {
+    pub(crate) fn sync_permissions(scp_file: &mut ScpFile) {
         let modify_time = filetime::FileTime::from_unix_time(scp_file.modify_time, 0);
         let access_time = filetime::FileTime::from_unix_time(scp_file.access_time, 0);
         if let Err(e) =
@@ -466,7 +466,7 @@ impl ScpBroker {
     }
 
     #[cfg(any(target_os = "linux", target_os = "macos"))]
-    fn sync_permissions(&self, scp_file: &mut ScpFile, file: fs::File) {
+    pub(crate) fn sync_permissions(scp_file: &mut ScpFile, file: fs::File) {
         let modify_time = filetime::FileTime::from_unix_time(scp_file.modify_time, 0);
         let access_time = filetime::FileTime::from_unix_time(scp_file.access_time, 0);
         if let Err(e) =
@@ -1,10 +1,14 @@
 mod channel;
+mod channel_direct_tcpip;
 mod channel_exec;
 mod channel_scp;
 mod channel_shell;
+mod remote_forward;
 
 pub(crate) use channel::Channel;
 pub use channel::ChannelBroker;
+pub use channel_direct_tcpip::DirectTcpipBroker;
 pub use channel_exec::ExecBroker;
 pub use channel_scp::ScpBroker;
 pub use channel_shell::ShellBrocker;
+pub use remote_forward::RemoteForwardBroker;
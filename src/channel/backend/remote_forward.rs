@@ -0,0 +1,166 @@
+use super::{channel::ChannelBroker, channel_direct_tcpip::DirectTcpipBroker};
+use crate::constant::{ssh_msg_code, ssh_str};
+use crate::error::{SshError, SshResult};
+use crate::model::{BackendResp, BackendRqst, Data, ForwardListener, ForwardedOpen};
+use std::{
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+/*
+    byte      SSH_MSG_GLOBAL_REQUEST
+    string    "tcpip-forward" / "cancel-tcpip-forward"
+    boolean   want reply
+    string    address to bind (e.g., "0.0.0.0")
+    uint32    port number to bind
+*/
+fn forward_request(request: &str, address: &str, port: u32) -> Data {
+    let mut data = Data::new();
+    data.put_u8(ssh_msg_code::SSH_MSG_GLOBAL_REQUEST)
+        .put_str(request)
+        .put_u8(true as u8)
+        .put_str(address)
+        .put_u32(port);
+    data
+}
+
+// send a global request and wait for the reply,
+// return the port sent back by the server, 0 if there isn't
+fn global_request(
+    snd: &Sender<BackendRqst>,
+    data: Data,
+    listener: Option<ForwardListener>,
+) -> SshResult<u32> {
+    let (resp_send, resp_recv) = mpsc::channel();
+    snd.send(BackendRqst::GlobalRequest(data, resp_send, listener))?;
+    match resp_recv.recv()? {
+        BackendResp::Ok(port) => Ok(port),
+        BackendResp::Fail(msg) => Err(SshError::from(msg)),
+        _ => unreachable!(),
+    }
+}
+
+/// A remote port forwarding, which is `ssh -R`
+///
+/// The server listens on the bound address,
+/// and each connection to it comes as a channel accepted here
+///
+/// The server keeps listening until [RemoteForwardBroker::cancel] is called
+///
+pub struct RemoteForwardBroker {
+    address: String,
+    port: u32,
+    opens: Receiver<ForwardedOpen>,
+    snd: Sender<BackendRqst>,
+}
+
+impl RemoteForwardBroker {
+    /// ask the server to listen on `address:port`,
+    /// port 0 means any port the server can allocate
+    pub(crate) fn open(address: &str, port: u32, snd: Sender<BackendRqst>) -> SshResult<Self> {
+        let (open_send, open_recv) = mpsc::channel();
+        let listener = ForwardListener {
+            port,
+            opens: open_send,
+        };
+        let data = forward_request(ssh_str::TCPIP_FORWARD, address, port);
+        let allocated = global_request(&snd, data, Some(listener))?;
+        let port = if port == 0 { allocated } else { port };
+        log::info!("remote forwarding on {}:{} started.", address, port);
+
+        Ok(Self {
+            address: address.to_string(),
+            port,
+            opens: open_recv,
+            snd,
+        })
+    }
+
+    /// the port bound by the server
+    ///
+    pub fn port(&self) -> u32 {
+        self.port
+    }
+
+    /// wait for the next connection to the server,
+    /// return the channel with the originator address & port
+    ///
+    pub fn accept(&self) -> SshResult<(DirectTcpipBroker, String, u32)> {
+        let open = self.opens.recv()?;
+        // consume the `Ok` sent when the backend channel is created
+        match open.rcv.recv()? {
+            BackendResp::Ok(_) => (),
+            _ => unreachable!(),
+        }
+        log::info!(
+            "accept forwarded connection from {}:{}.",
+            open.originator_address,
+            open.originator_port
+        );
+        let channel = ChannelBroker::new(
+            open.client_channel_no,
+            open.server_channel_no,
+            open.rcv,
+            self.snd.clone(),
+        );
+        Ok((
+            DirectTcpipBroker::open(channel),
+            open.originator_address,
+            open.originator_port,
+        ))
+    }
+
+    /// call `handler` for each accepted connection,
+    /// with the channel, the originator address & port
+    ///
+    /// This method blocks until the session is closed
+    ///
+    pub fn serve_with<F>(&self, mut handler: F) -> SshResult<()>
+    where
+        F: FnMut(DirectTcpipBroker, String, u32),
+    {
+        loop {
+            let (channel, address, port) = self.accept()?;
+            handler(channel, address, port);
+        }
+    }
+
+    /// connect each accepted connection to `target`,
+    /// every connection is served by its own threads
+    ///
+    /// This method blocks until the session is closed
+    ///
+    pub fn forward_to<A>(&self, target: A) -> SshResult<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let target = target.to_socket_addrs()?.collect::<Vec<SocketAddr>>();
+        self.serve_with(
+            |channel, address, port| match TcpStream::connect(&target[..]) {
+                Ok(stream) => {
+                    thread::spawn(move || {
+                        if let Err(e) = channel.relay(stream) {
+                            log::debug!("forwarding from {}:{} stopped: {}", address, port, e);
+                        }
+                    });
+                }
+                // the channel is closed when dropped
+                Err(e) => log::error!("cannot connect to the forwarding target: {}", e),
+            },
+        )
+    }
+
+    /// ask the server to stop listening and consume the forwarding
+    ///
+    pub fn cancel(self) -> SshResult<()> {
+        let data = forward_request(ssh_str::CANCEL_TCPIP_FORWARD, &self.address, self.port);
+        global_request(&self.snd, data, None)?;
+        log::info!(
+            "remote forwarding on {}:{} cancelled.",
+            self.address,
+            self.port
+        );
+        Ok(())
+    }
+}
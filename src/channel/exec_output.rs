@@ -0,0 +1,69 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::{error::SshResult, model::Data, util};
+
+/// The full result of a command executed on an exec channel
+///
+#[derive(Debug, Clone, Default)]
+pub struct ExecOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// reported by the server via an `exit-status` request,
+    /// `None` if the command was terminated by a signal or the server didn't report it
+    pub exit_status: Option<u32>,
+    /// reported by the server via an `exit-signal` request
+    pub exit_signal: Option<ExitSignal>,
+}
+
+impl ExecOutput {
+    /// whether the command exited normally with status 0
+    ///
+    pub fn success(&self) -> bool {
+        self.exit_status == Some(0) && self.exit_signal.is_none()
+    }
+}
+
+/// The signal which terminated a remote command
+///
+#[derive(Debug, Clone)]
+pub struct ExitSignal {
+    /// signal name without the "SIG" prefix, e.g. "TERM", "KILL"
+    pub signal_name: String,
+    pub core_dumped: bool,
+    pub error_message: String,
+    pub language_tag: String,
+}
+
+impl ExitSignal {
+    /*
+        string    signal name (without the "SIG" prefix)
+        boolean   core dumped
+        string    error message in ISO-10646 UTF-8 encoding
+        string    language tag [RFC3066]
+    */
+    pub(crate) fn unpack(data: &mut Data) -> SshResult<Self> {
+        let signal_name = util::from_utf8(data.get_u8s())?;
+        let core_dumped = data.get_u8() != 0;
+        let error_message = String::from_utf8_lossy(&data.get_u8s()).to_string();
+        let language_tag = String::from_utf8_lossy(&data.get_u8s()).to_string();
+        Ok(Self {
+            signal_name,
+            core_dumped,
+            error_message,
+            language_tag,
+        })
+    }
+}
+
+impl Display for ExitSignal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "SIG{}", self.signal_name)?;
+        if self.core_dumped {
+            write!(f, " (core dumped)")?;
+        }
+        if !self.error_message.is_empty() {
+            write!(f, ": {}", self.error_message)?;
+        }
+        Ok(())
+    }
+}
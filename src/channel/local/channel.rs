@@ -2,17 +2,22 @@ use std::io::{Read, Write};
 
 use crate::{
     algorithm::Digest,
+    channel::{sftp::SftpIo, AgentChannels, ExecOutput, ExitSignal, Sftp},
     client::Client,
     config::algorithm::AlgList,
-    constant::ssh_msg_code,
+    constant::{ssh_msg_code, ssh_str},
     error::{SshError, SshResult},
-    model::{Data, FlowControl, Packet, RcMut, SecPacket},
+    model::{Data, FlowControl, Packet, RcMut, SecPacket, U32Iter},
+    util,
 };
 
 use super::{ChannelExec, ChannelScp, ChannelShell};
 
 pub(super) enum ChannelRead {
     Data(Vec<u8>),
+    ExtendedData(Vec<u8>),
+    ExitStatus(u32),
+    ExitSignal(ExitSignal),
     Code(u8),
 }
 
@@ -27,6 +32,10 @@ where
     pub(crate) flow_control: FlowControl,
     pub(crate) client: RcMut<Client>,
     pub(crate) stream: RcMut<S>,
+    // shared by all channels of the session,
+    // for the agent channels opened by the server
+    pub(crate) channel_num: RcMut<U32Iter>,
+    pub(crate) agents: RcMut<AgentChannels>,
 }
 
 impl<S> Channel<S>
@@ -39,6 +48,8 @@ where
         remote_window: u32,
         client: RcMut<Client>,
         stream: RcMut<S>,
+        channel_num: RcMut<U32Iter>,
+        agents: RcMut<AgentChannels>,
     ) -> Self {
         Self {
             server_channel_no,
@@ -48,6 +59,8 @@ where
             flow_control: FlowControl::new(remote_window),
             client,
             stream,
+            channel_num,
+            agents,
         }
     }
 
@@ -74,6 +87,33 @@ where
         ChannelShell::open(self, row, column)
     }
 
+    /// start the `sftp` subsystem and convert the raw channel to an [Sftp] client
+    ///
+    pub fn sftp(mut self) -> SshResult<Sftp<Self>> {
+        log::info!("sftp opened.");
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::SUBSYSTEM)
+            .put_u8(true as u8)
+            .put_str(ssh_str::SFTP);
+        self.send_and_wait(data)?;
+        Sftp::start(self)
+    }
+
+    /// ask the server to forward the connections to the agent back,
+    /// which is `ssh -A`
+    ///
+    pub(crate) fn request_agent_forwarding(&mut self) -> SshResult<()> {
+        log::info!("request agent forwarding.");
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST)
+            .put_u32(self.server_channel_no)
+            .put_str(ssh_str::AUTH_AGENT_REQ)
+            .put_u8(false as u8);
+        self.send(data)
+    }
+
     /// close the channel gracefully, but donnot consume it
     ///
     pub fn close(&mut self) -> SshResult<()> {
@@ -102,8 +142,25 @@ where
     }
 
     pub(super) fn send(&mut self, data: Data) -> SshResult<()> {
-        data.pack(&mut self.client.borrow_mut())
-            .write_stream(&mut *self.stream.borrow_mut())
+        let mut client = self.client.borrow_mut();
+        let mut stream = self.stream.borrow_mut();
+        if client.need_rekey() {
+            client.rekey(&mut *stream)?;
+        }
+        data.pack(&mut client).write_stream(&mut *stream)
+    }
+
+    // send a channel request with `want reply` set,
+    // and wait until the server accepts it
+    fn send_and_wait(&mut self, data: Data) -> SshResult<()> {
+        self.send(data)?;
+        loop {
+            match self.recv_once()? {
+                ChannelRead::Code(ssh_msg_code::SSH_MSG_CHANNEL_SUCCESS) => return Ok(()),
+                _ if self.is_close() => return Err(SshError::from("channel closed.")),
+                _ => {}
+            }
+        }
     }
 
     // only send SSH_MSG_CHANNEL_DATA will call this,
@@ -164,15 +221,34 @@ where
         Ok(resp)
     }
 
+    /// receive until the channel is closed,
+    /// stdout, stderr and the exit status are all collected
+    ///
+    pub(super) fn recv_output_to_end(&mut self) -> SshResult<ExecOutput> {
+        let mut output = ExecOutput::default();
+        while !self.is_close() {
+            match self.recv_once()? {
+                ChannelRead::Data(mut data) => output.stdout.append(&mut data),
+                ChannelRead::ExtendedData(mut data) => output.stderr.append(&mut data),
+                ChannelRead::ExitStatus(status) => output.exit_status = Some(status),
+                ChannelRead::ExitSignal(signal) => output.exit_signal = Some(signal),
+                ChannelRead::Code(_) => {}
+            }
+        }
+        Ok(output)
+    }
+
     pub(super) fn try_recv(&mut self) -> SshResult<Option<Vec<u8>>> {
-        let data = {
-            match SecPacket::try_from_stream(
+        let deferred = self.client.borrow_mut().take_deferred();
+        let data = match deferred {
+            Some(data) => data,
+            None => match SecPacket::try_from_stream(
                 &mut *self.stream.borrow_mut(),
                 &mut self.client.borrow_mut(),
             )? {
                 Some(pkt) => Data::unpack(pkt)?,
                 None => return Ok(None),
-            }
+            },
         };
         if let ChannelRead::Data(d) = self.handle_msg(data)? {
             Ok(Some(d))
@@ -182,10 +258,15 @@ where
     }
 
     fn recv_once(&mut self) -> SshResult<ChannelRead> {
-        let data = Data::unpack(SecPacket::from_stream(
-            &mut *self.stream.borrow_mut(),
-            &mut self.client.borrow_mut(),
-        )?)?;
+        // the messages received during a rekey come first
+        let deferred = self.client.borrow_mut().take_deferred();
+        let data = match deferred {
+            Some(data) => data,
+            None => Data::unpack(SecPacket::from_stream(
+                &mut *self.stream.borrow_mut(),
+                &mut self.client.borrow_mut(),
+            )?)?,
+        };
         self.handle_msg(data)
     }
 
@@ -215,16 +296,42 @@ where
 
                     return Ok(ChannelRead::Data(data));
                 }
+                self.handle_agent_msg(x, cc, data)?;
+                Ok(ChannelRead::Code(x))
+            }
+            x @ ssh_msg_code::SSH_MSG_CHANNEL_EXTENDED_DATA => {
+                let cc = data.get_u32();
+                if cc == self.client_channel_no {
+                    let data_type = data.get_u32();
+                    let mut data = data.get_u8s();
+
+                    // flow_control
+                    self.flow_control.tune_on_recv(&mut data);
+                    self.send_window_adjust(data.len() as u32)?;
+
+                    if data_type == ssh_msg_code::SSH_EXTENDED_DATA_STDERR {
+                        return Ok(ChannelRead::ExtendedData(data));
+                    }
+                }
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => {
-                let mut data = Data::new();
-                data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
-                self.send(data)?;
+                let request = util::from_utf8(data.get_u8s())?;
+                let want_reply = data.get_u8() != 0;
+                log::debug!("Currently ignore global request {}", request);
+                if want_reply {
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
+                    self.send(data)?;
+                }
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST => {
-                data.get_u32();
+                let cc = data.get_u32();
+                if self.agents.borrow().contains(cc) {
+                    self.handle_agent_msg(x, cc, data)?;
+                    return Ok(ChannelRead::Code(x));
+                }
                 // to add
                 let rws = data.get_u32();
                 self.recv_window_adjust(rws)?;
@@ -235,7 +342,11 @@ where
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_msg_code::SSH_MSG_CHANNEL_REQUEST => {
-                log::debug!("Currently ignore message {}", x);
+                let cc = data.get_u32();
+                if cc == self.client_channel_no {
+                    return self.handle_request(data);
+                }
+                self.handle_agent_msg(x, cc, data)?;
                 Ok(ChannelRead::Code(x))
             }
             x @ ssh_msg_code::SSH_MSG_CHANNEL_SUCCESS => {
@@ -248,6 +359,31 @@ where
                 if cc == self.client_channel_no {
                     self.remote_close = true;
                     self.send_close()?;
+                } else {
+                    self.handle_agent_msg(x, cc, data)?;
+                }
+                Ok(ChannelRead::Code(x))
+            }
+            x @ ssh_msg_code::SSH_MSG_CHANNEL_OPEN => {
+                let channel_type = util::from_utf8(data.get_u8s())?;
+                if channel_type == ssh_str::AUTH_AGENT {
+                    let client_channel_no = self.channel_num.borrow_mut().next().unwrap();
+                    self.agents.borrow_mut().open(
+                        data,
+                        client_channel_no,
+                        &mut self.client.borrow_mut(),
+                        &mut *self.stream.borrow_mut(),
+                    )?;
+                } else {
+                    log::debug!("reject {} channel opened by the server", channel_type);
+                    let server_channel_no = data.get_u32();
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE)
+                        .put_u32(server_channel_no)
+                        .put_u32(ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE)
+                        .put_str("")
+                        .put_str("");
+                    self.send(data)?;
                 }
                 Ok(ChannelRead::Code(x))
             }
@@ -258,6 +394,44 @@ where
         }
     }
 
+    /*
+        byte      SSH_MSG_CHANNEL_REQUEST
+        uint32    recipient channel
+        string    request type in US-ASCII characters only
+        boolean   want reply
+        ....      type-specific data follows
+    */
+    fn handle_request(&mut self, mut data: Data) -> SshResult<ChannelRead> {
+        let request = util::from_utf8(data.get_u8s())?;
+        let want_reply = data.get_u8() != 0;
+        match request.as_str() {
+            ssh_str::EXIT_STATUS => Ok(ChannelRead::ExitStatus(data.get_u32())),
+            ssh_str::EXIT_SIGNAL => Ok(ChannelRead::ExitSignal(ExitSignal::unpack(&mut data)?)),
+            x => {
+                log::debug!("Currently ignore channel request {}", x);
+                if want_reply {
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_FAILURE)
+                        .put_u32(self.server_channel_no);
+                    self.send(data)?;
+                }
+                Ok(ChannelRead::Code(ssh_msg_code::SSH_MSG_CHANNEL_REQUEST))
+            }
+        }
+    }
+
+    // the messages of the agent channels are dispatched here,
+    // as they arrive while this channel is reading
+    fn handle_agent_msg(&mut self, message_code: u8, cc: u32, data: Data) -> SshResult<()> {
+        self.agents.borrow_mut().handle(
+            message_code,
+            cc,
+            data,
+            &mut self.client.borrow_mut(),
+            &mut *self.stream.borrow_mut(),
+        )
+    }
+
     fn send_window_adjust(&mut self, to_add: u32) -> SshResult<()> {
         let mut data = Data::new();
         data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST)
@@ -276,3 +450,20 @@ where
         self.local_close && self.remote_close
     }
 }
+
+impl<S> SftpIo for Channel<S>
+where
+    S: Read + Write,
+{
+    fn send_bytes(&mut self, buf: Vec<u8>) -> SshResult<Vec<u8>> {
+        self.send_data(buf)
+    }
+
+    fn recv_bytes(&mut self) -> SshResult<Vec<u8>> {
+        self.recv()
+    }
+
+    fn close_channel(&mut self) -> SshResult<()> {
+        self.close()
+    }
+}
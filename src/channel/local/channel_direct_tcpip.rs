@@ -0,0 +1,87 @@
+use super::channel::Channel;
+use crate::constant::ssh_msg_code;
+use crate::error::SshResult;
+use crate::model::Data;
+use std::{
+    io::{self, Read, Write},
+    ops::{Deref, DerefMut},
+};
+
+/// A `direct-tcpip` channel,
+/// the data written to it is relayed to the target by the server
+///
+pub struct ChannelDirectTcpip<S: Read + Write> {
+    channel: Channel<S>,
+    read_buf: Vec<u8>,
+}
+
+impl<S> ChannelDirectTcpip<S>
+where
+    S: Read + Write,
+{
+    pub(crate) fn open(channel: Channel<S>) -> Self {
+        ChannelDirectTcpip {
+            channel,
+            read_buf: vec![],
+        }
+    }
+
+    /// tell the server that no more data will be sent
+    ///
+    pub fn send_eof(&mut self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_EOF)
+            .put_u32(self.server_channel_no);
+        self.send(data)
+    }
+}
+
+impl<S> Read for ChannelDirectTcpip<S>
+where
+    S: Read + Write,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_buf.is_empty() {
+            // an empty result means the channel is closed
+            self.read_buf = self.channel.recv()?;
+        }
+        let n = self.read_buf.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.read_buf[..n]);
+        self.read_buf.drain(..n);
+        Ok(n)
+    }
+}
+
+impl<S> Write for ChannelDirectTcpip<S>
+where
+    S: Read + Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut received = self.channel.send_data(buf.to_vec())?;
+        self.read_buf.append(&mut received);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<S> Deref for ChannelDirectTcpip<S>
+where
+    S: Read + Write,
+{
+    type Target = Channel<S>;
+    fn deref(&self) -> &Self::Target {
+        &self.channel
+    }
+}
+
+impl<S> DerefMut for ChannelDirectTcpip<S>
+where
+    S: Read + Write,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.channel
+    }
+}
@@ -1,4 +1,5 @@
 use super::channel::Channel;
+use crate::channel::ExecOutput;
 use crate::constant::{ssh_msg_code, ssh_str};
 use crate::error::SshResult;
 use crate::model::Data;
@@ -40,6 +41,18 @@ where
         let r = self.recv_to_end()?;
         Ok(r)
     }
+
+    /// Send an executable command to the server
+    /// and get its stdout, stderr and exit status
+    ///
+    /// This method also implicitly consume the channel object,
+    /// since the exec channel can only execute one command
+    ///
+    pub fn send_command_output(mut self, command: &str) -> SshResult<ExecOutput> {
+        self.exec_command(command)?;
+
+        self.recv_output_to_end()
+    }
 }
 
 impl<S> Deref for ChannelExec<S>
@@ -1,9 +1,11 @@
 mod channel;
+mod channel_direct_tcpip;
 mod channel_exec;
 mod channel_scp;
 mod channel_shell;
 
 pub(crate) use channel::Channel;
+pub use channel_direct_tcpip::ChannelDirectTcpip;
 pub use channel_exec::ChannelExec;
 pub use channel_scp::ChannelScp;
 pub use channel_shell::ChannelShell;
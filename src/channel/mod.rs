@@ -1,10 +1,26 @@
+mod agent_forward;
+#[cfg(feature = "async")]
+mod asynchronous;
 mod backend;
+mod exec_output;
 mod local;
+mod sftp;
 
+pub(crate) use agent_forward::AgentChannels;
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncChannel, AsyncExec, AsyncScp, AsyncShell};
 pub(crate) use backend::Channel as BackendChannel;
-pub use backend::{ChannelBroker, ExecBroker, ScpBroker, ShellBrocker};
+pub use backend::{
+    ChannelBroker, DirectTcpipBroker, ExecBroker, RemoteForwardBroker, ScpBroker, ShellBrocker,
+};
+pub use exec_output::{ExecOutput, ExitSignal};
 
 pub(crate) use local::Channel as LocalChannel;
+pub use local::ChannelDirectTcpip as LocalDirectTcpip;
 pub use local::ChannelExec as LocalExec;
 pub use local::ChannelScp as LocalScp;
 pub use local::ChannelShell as LocalShell;
+
+pub use sftp::{DirEntry, FileAttributes, OpenFlags, Sftp, SftpFile};
+pub type LocalSftp<S> = Sftp<local::Channel<S>>;
+pub type SftpBroker = Sftp<backend::ChannelBroker>;
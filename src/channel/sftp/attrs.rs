@@ -0,0 +1,158 @@
+use std::ops::BitOr;
+
+use crate::{constant::sftp, error::SshResult, model::Data};
+
+/// The attributes of a remote file
+///
+/// fields that are `None` were not sent by the server,
+/// or will not be changed when used in `setstat`
+///
+#[derive(Debug, Clone, Default)]
+pub struct FileAttributes {
+    pub size: Option<u64>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub permissions: Option<u32>,
+    pub atime: Option<u32>,
+    pub mtime: Option<u32>,
+}
+
+impl FileAttributes {
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Some(sftp::S_IFDIR)
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Some(sftp::S_IFREG)
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == Some(sftp::S_IFLNK)
+    }
+
+    fn file_type(&self) -> Option<u32> {
+        self.permissions.map(|p| p & sftp::S_IFMT)
+    }
+
+    /*
+        uint32   flags
+        uint64   size           present only if flag SSH_FILEXFER_ATTR_SIZE
+        uint32   uid            present only if flag SSH_FILEXFER_ATTR_UIDGID
+        uint32   gid            present only if flag SSH_FILEXFER_ATTR_UIDGID
+        uint32   permissions    present only if flag SSH_FILEXFER_ATTR_PERMISSIONS
+        uint32   atime          present only if flag SSH_FILEXFER_ACMODTIME
+        uint32   mtime          present only if flag SSH_FILEXFER_ACMODTIME
+        uint32   extended_count present only if flag SSH_FILEXFER_ATTR_EXTENDED
+        string   extended_type
+        string   extended_data
+        ...      more extended data (extended_type - extended_data pairs),
+    */
+    pub(crate) fn pack(&self, data: &mut Data) {
+        let mut flags = 0;
+        if self.size.is_some() {
+            flags |= sftp::SSH_FILEXFER_ATTR_SIZE;
+        }
+        if self.uid.is_some() && self.gid.is_some() {
+            flags |= sftp::SSH_FILEXFER_ATTR_UIDGID;
+        }
+        if self.permissions.is_some() {
+            flags |= sftp::SSH_FILEXFER_ATTR_PERMISSIONS;
+        }
+        if self.atime.is_some() && self.mtime.is_some() {
+            flags |= sftp::SSH_FILEXFER_ATTR_ACMODTIME;
+        }
+
+        data.put_u32(flags);
+        if let Some(size) = self.size {
+            data.put_u64(size);
+        }
+        if let (Some(uid), Some(gid)) = (self.uid, self.gid) {
+            data.put_u32(uid).put_u32(gid);
+        }
+        if let Some(permissions) = self.permissions {
+            data.put_u32(permissions);
+        }
+        if let (Some(atime), Some(mtime)) = (self.atime, self.mtime) {
+            data.put_u32(atime).put_u32(mtime);
+        }
+    }
+
+    pub(crate) fn unpack(data: &mut Data) -> SshResult<Self> {
+        let flags = data.try_get_u32()?;
+        let mut attrs = Self::default();
+        if flags & sftp::SSH_FILEXFER_ATTR_SIZE != 0 {
+            attrs.size = Some(data.try_get_u64()?);
+        }
+        if flags & sftp::SSH_FILEXFER_ATTR_UIDGID != 0 {
+            attrs.uid = Some(data.try_get_u32()?);
+            attrs.gid = Some(data.try_get_u32()?);
+        }
+        if flags & sftp::SSH_FILEXFER_ATTR_PERMISSIONS != 0 {
+            attrs.permissions = Some(data.try_get_u32()?);
+        }
+        if flags & sftp::SSH_FILEXFER_ATTR_ACMODTIME != 0 {
+            attrs.atime = Some(data.try_get_u32()?);
+            attrs.mtime = Some(data.try_get_u32()?);
+        }
+        if flags & sftp::SSH_FILEXFER_ATTR_EXTENDED != 0 {
+            // extended attributes are not used, just skip them
+            let count = data.try_get_u32()?;
+            for _ in 0..count {
+                data.try_get_u8s()?;
+                data.try_get_u8s()?;
+            }
+        }
+        Ok(attrs)
+    }
+}
+
+/// One entry returned by `readdir`
+///
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub filename: String,
+    /// the `ls -l` like description, the format is server specific
+    pub longname: String,
+    pub attrs: FileAttributes,
+}
+
+impl DirEntry {
+    pub(crate) fn unpack(data: &mut Data) -> SshResult<Self> {
+        // the names are not always utf-8 on the server side
+        let filename = String::from_utf8_lossy(&data.try_get_u8s()?).to_string();
+        let longname = String::from_utf8_lossy(&data.try_get_u8s()?).to_string();
+        let attrs = FileAttributes::unpack(data)?;
+        Ok(Self {
+            filename,
+            longname,
+            attrs,
+        })
+    }
+}
+
+/// The `pflags` used to open a remote file
+///
+/// can be combined with `|`, e.g. `OpenFlags::WRITE | OpenFlags::CREATE`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: Self = Self(sftp::SSH_FXF_READ);
+    pub const WRITE: Self = Self(sftp::SSH_FXF_WRITE);
+    pub const APPEND: Self = Self(sftp::SSH_FXF_APPEND);
+    pub const CREATE: Self = Self(sftp::SSH_FXF_CREAT);
+    pub const TRUNCATE: Self = Self(sftp::SSH_FXF_TRUNC);
+    pub const EXCLUSIVE: Self = Self(sftp::SSH_FXF_EXCL);
+
+    pub fn bits(&self) -> u32 {
+        self.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = Self;
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
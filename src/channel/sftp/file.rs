@@ -0,0 +1,130 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::{constant::sftp, error::SshResult};
+
+use super::{FileAttributes, Sftp, SftpIo};
+
+/// A remote file opened by [Sftp]
+///
+/// The handle is closed when dropped, call `close()` to get the result
+///
+pub struct SftpFile<'a, C: SftpIo> {
+    sftp: &'a mut Sftp<C>,
+    handle: Vec<u8>,
+    offset: u64,
+    closed: bool,
+}
+
+impl<'a, C> SftpFile<'a, C>
+where
+    C: SftpIo,
+{
+    pub(super) fn new(sftp: &'a mut Sftp<C>, handle: Vec<u8>) -> Self {
+        Self {
+            sftp,
+            handle,
+            offset: 0,
+            closed: false,
+        }
+    }
+
+    /// the attributes of the opened file
+    ///
+    pub fn stat(&mut self) -> SshResult<FileAttributes> {
+        self.sftp.fstat(&self.handle)
+    }
+
+    pub fn setstat(&mut self, attrs: &FileAttributes) -> SshResult<()> {
+        self.sftp.fsetstat(&self.handle, attrs)
+    }
+
+    /// close the file handle and consume it
+    ///
+    pub fn close(mut self) -> SshResult<()> {
+        self.closed = true;
+        self.sftp.close_handle(&self.handle)
+    }
+}
+
+impl<'a, C> Read for SftpFile<'a, C>
+where
+    C: SftpIo,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(sftp::MAX_DATA_LEN as usize) as u32;
+        match self.sftp.read_handle(&self.handle, self.offset, len)? {
+            Some(data) => {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                self.offset += n as u64;
+                Ok(n)
+            }
+            None => Ok(0),
+        }
+    }
+}
+
+impl<'a, C> Write for SftpFile<'a, C>
+where
+    C: SftpIo,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf.len().min(sftp::MAX_DATA_LEN as usize);
+        self.sftp
+            .write_handle(&self.handle, self.offset, &buf[..n])?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // every write is already acknowledged by the server
+        Ok(())
+    }
+}
+
+impl<'a, C> Seek for SftpFile<'a, C>
+where
+    C: SftpIo,
+{
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, delta) = match pos {
+            SeekFrom::Start(n) => {
+                self.offset = n;
+                return Ok(n);
+            }
+            SeekFrom::Current(d) => (self.offset, d),
+            SeekFrom::End(d) => {
+                let size = self
+                    .stat()?
+                    .size
+                    .ok_or_else(|| io::Error::other("file size is not available."))?;
+                (size, d)
+            }
+        };
+        let offset = base as i128 + delta as i128;
+        if offset < 0 || offset > u64::MAX as i128 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position.",
+            ));
+        }
+        self.offset = offset as u64;
+        Ok(self.offset)
+    }
+}
+
+impl<'a, C> Drop for SftpFile<'a, C>
+where
+    C: SftpIo,
+{
+    fn drop(&mut self) {
+        if !self.closed {
+            if let Err(e) = self.sftp.close_handle(&self.handle) {
+                log::warn!("failed to close sftp file handle: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,418 @@
+mod attrs;
+mod file;
+
+pub use attrs::{DirEntry, FileAttributes, OpenFlags};
+pub use file::SftpFile;
+
+use crate::{
+    constant::sftp,
+    error::{SshError, SshErrorKind, SshResult},
+    model::Data,
+};
+
+/// The transport of the sftp packets,
+/// implemented by both the local and the backend raw channels
+///
+pub trait SftpIo {
+    /// send `buf` as channel data,
+    /// return the data received while waiting for the window to be adjusted
+    fn send_bytes(&mut self, buf: Vec<u8>) -> SshResult<Vec<u8>>;
+
+    /// receive at least one data packet,
+    /// an empty result means the channel is closed
+    fn recv_bytes(&mut self) -> SshResult<Vec<u8>>;
+
+    fn close_channel(&mut self) -> SshResult<()>;
+}
+
+/// the responses of an sftp request
+enum Response {
+    /// only `SSH_FX_OK` & `SSH_FX_EOF`, other status codes are converted to errors
+    Status(u32),
+    Handle(Vec<u8>),
+    Data(Vec<u8>),
+    Name(Vec<DirEntry>),
+    Attrs(FileAttributes),
+}
+
+/// An sftp (version 3) client running on the `sftp` subsystem
+///
+/// Requests are sent one by one, each method blocks until the server responds
+///
+pub struct Sftp<C: SftpIo> {
+    channel: C,
+    request_id: u32,
+    recv_buf: Vec<u8>,
+    version: u32,
+}
+
+impl<C> Sftp<C>
+where
+    C: SftpIo,
+{
+    /// the `sftp` subsystem shall already be started on the channel
+    pub(crate) fn start(channel: C) -> SshResult<Self> {
+        let mut sftp = Self {
+            channel,
+            request_id: 0,
+            recv_buf: vec![],
+            version: 0,
+        };
+        sftp.init()?;
+        Ok(sftp)
+    }
+
+    /*
+        uint32 version
+        <extension data>
+    */
+    fn init(&mut self) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8(sftp::SSH_FXP_INIT).put_u32(sftp::VERSION);
+        self.write_packet(data)?;
+
+        let mut data = self.read_packet()?;
+        match data.try_get_u8()? {
+            sftp::SSH_FXP_VERSION => {
+                self.version = data.try_get_u32()?;
+                while !data.is_empty() {
+                    let name = String::from_utf8_lossy(&data.try_get_u8s()?).to_string();
+                    let value = String::from_utf8_lossy(&data.try_get_u8s()?).to_string();
+                    log::debug!("sftp server extension {}: {}", name, value);
+                }
+                log::info!("sftp version {} negotiated.", self.version);
+                Ok(())
+            }
+            x => Err(SshError::from(format!(
+                "unexpected sftp message {} when init.",
+                x
+            ))),
+        }
+    }
+
+    /// the protocol version sent by the server
+    ///
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// close the sftp channel and consume it
+    ///
+    pub fn close(mut self) -> SshResult<()> {
+        log::info!("sftp close.");
+        self.channel.close_channel()
+    }
+
+    /// open a remote file with the given flags,
+    /// `attrs` are applied if the file is created
+    ///
+    pub fn open_with_flags(
+        &mut self,
+        path: &str,
+        flags: OpenFlags,
+        attrs: &FileAttributes,
+    ) -> SshResult<SftpFile<'_, C>> {
+        let mut data = Data::new();
+        data.put_str(path).put_u32(flags.bits());
+        attrs.pack(&mut data);
+        let id = self.send_request(sftp::SSH_FXP_OPEN, data)?;
+        let handle = self.expect_handle(id)?;
+        Ok(SftpFile::new(self, handle))
+    }
+
+    /// open a remote file for reading
+    ///
+    pub fn open(&mut self, path: &str) -> SshResult<SftpFile<'_, C>> {
+        self.open_with_flags(path, OpenFlags::READ, &FileAttributes::default())
+    }
+
+    /// open a remote file for writing,
+    /// it will be created if it does not exist, and truncated if it does
+    ///
+    pub fn create(&mut self, path: &str) -> SshResult<SftpFile<'_, C>> {
+        self.open_with_flags(
+            path,
+            OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+            &FileAttributes::default(),
+        )
+    }
+
+    /// list a remote directory, `.` and `..` are not included
+    ///
+    pub fn read_dir(&mut self, path: &str) -> SshResult<Vec<DirEntry>> {
+        let handle = self.opendir(path)?;
+        let mut entries = vec![];
+        let result = loop {
+            match self.readdir(&handle) {
+                Ok(Some(mut batch)) => entries.append(&mut batch),
+                Ok(None) => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.close_handle(&handle)?;
+        result?;
+        entries.retain(|e| e.filename != "." && e.filename != "..");
+        Ok(entries)
+    }
+
+    /// follow symbolic links
+    ///
+    pub fn stat(&mut self, path: &str) -> SshResult<FileAttributes> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_STAT, data)?;
+        self.expect_attrs(id)
+    }
+
+    /// do not follow symbolic links
+    ///
+    pub fn lstat(&mut self, path: &str) -> SshResult<FileAttributes> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_LSTAT, data)?;
+        self.expect_attrs(id)
+    }
+
+    pub fn setstat(&mut self, path: &str, attrs: &FileAttributes) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        attrs.pack(&mut data);
+        let id = self.send_request(sftp::SSH_FXP_SETSTAT, data)?;
+        self.expect_status(id)
+    }
+
+    pub fn mkdir(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        FileAttributes::default().pack(&mut data);
+        let id = self.send_request(sftp::SSH_FXP_MKDIR, data)?;
+        self.expect_status(id)
+    }
+
+    pub fn rmdir(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_RMDIR, data)?;
+        self.expect_status(id)
+    }
+
+    /// remove a file
+    ///
+    pub fn remove(&mut self, path: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_REMOVE, data)?;
+        self.expect_status(id)
+    }
+
+    pub fn rename(&mut self, from: &str, to: &str) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_str(from).put_str(to);
+        let id = self.send_request(sftp::SSH_FXP_RENAME, data)?;
+        self.expect_status(id)
+    }
+
+    /// canonicalize a path to an absolute one,
+    /// `realpath(".")` is usually used to get the working directory
+    ///
+    pub fn realpath(&mut self, path: &str) -> SshResult<String> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_REALPATH, data)?;
+        self.expect_single_name(id)
+    }
+
+    /// create a symbolic link at `link` pointing to `target`
+    ///
+    pub fn symlink(&mut self, target: &str, link: &str) -> SshResult<()> {
+        // OpenSSH has the arguments reversed against the draft,
+        // follow it as it is what almost every server runs
+        let mut data = Data::new();
+        data.put_str(target).put_str(link);
+        let id = self.send_request(sftp::SSH_FXP_SYMLINK, data)?;
+        self.expect_status(id)
+    }
+
+    pub fn readlink(&mut self, path: &str) -> SshResult<String> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_READLINK, data)?;
+        self.expect_single_name(id)
+    }
+
+    fn opendir(&mut self, path: &str) -> SshResult<Vec<u8>> {
+        let mut data = Data::new();
+        data.put_str(path);
+        let id = self.send_request(sftp::SSH_FXP_OPENDIR, data)?;
+        self.expect_handle(id)
+    }
+
+    /// `None` when all the entries are read
+    fn readdir(&mut self, handle: &[u8]) -> SshResult<Option<Vec<DirEntry>>> {
+        let mut data = Data::new();
+        data.put_u8s(handle);
+        let id = self.send_request(sftp::SSH_FXP_READDIR, data)?;
+        match self.recv_response(id)? {
+            Response::Name(entries) => Ok(Some(entries)),
+            Response::Status(sftp::SSH_FX_EOF) => Ok(None),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn close_handle(&mut self, handle: &[u8]) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8s(handle);
+        let id = self.send_request(sftp::SSH_FXP_CLOSE, data)?;
+        self.expect_status(id)
+    }
+
+    /// `None` when reaching the end of the file
+    fn read_handle(&mut self, handle: &[u8], offset: u64, len: u32) -> SshResult<Option<Vec<u8>>> {
+        let mut data = Data::new();
+        data.put_u8s(handle).put_u64(offset).put_u32(len);
+        let id = self.send_request(sftp::SSH_FXP_READ, data)?;
+        match self.recv_response(id)? {
+            Response::Data(data) => Ok(Some(data)),
+            Response::Status(sftp::SSH_FX_EOF) => Ok(None),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn write_handle(&mut self, handle: &[u8], offset: u64, buf: &[u8]) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8s(handle).put_u64(offset).put_u8s(buf);
+        let id = self.send_request(sftp::SSH_FXP_WRITE, data)?;
+        self.expect_status(id)
+    }
+
+    fn fstat(&mut self, handle: &[u8]) -> SshResult<FileAttributes> {
+        let mut data = Data::new();
+        data.put_u8s(handle);
+        let id = self.send_request(sftp::SSH_FXP_FSTAT, data)?;
+        self.expect_attrs(id)
+    }
+
+    fn fsetstat(&mut self, handle: &[u8], attrs: &FileAttributes) -> SshResult<()> {
+        let mut data = Data::new();
+        data.put_u8s(handle);
+        attrs.pack(&mut data);
+        let id = self.send_request(sftp::SSH_FXP_FSETSTAT, data)?;
+        self.expect_status(id)
+    }
+
+    /*
+        uint32   length
+        byte     type
+        uint32   request-id
+        ...      type specific fields
+    */
+    fn send_request(&mut self, packet_type: u8, body: Data) -> SshResult<u32> {
+        self.request_id = self.request_id.wrapping_add(1);
+        let id = self.request_id;
+        let mut data = Data::new();
+        data.put_u8(packet_type).put_u32(id);
+        data.extend_from_slice(&body);
+        self.write_packet(data)?;
+        Ok(id)
+    }
+
+    fn write_packet(&mut self, payload: Data) -> SshResult<()> {
+        let mut packet = Data::new();
+        packet.put_u8s(&payload);
+        let mut received = self.channel.send_bytes(packet.into_inner())?;
+        self.recv_buf.append(&mut received);
+        Ok(())
+    }
+
+    /// one sftp packet may be split into several channel data,
+    /// and one channel data may contain several sftp packets
+    fn read_packet(&mut self) -> SshResult<Data> {
+        loop {
+            if self.recv_buf.len() >= 4 {
+                let len = u32::from_be_bytes(self.recv_buf[..4].try_into().unwrap()) as usize;
+                if self.recv_buf.len() >= len + 4 {
+                    let remain = self.recv_buf.split_off(len + 4);
+                    let packet = std::mem::replace(&mut self.recv_buf, remain);
+                    return Ok(Data::from(packet[4..].to_vec()));
+                }
+            }
+            let mut buf = self.channel.recv_bytes()?;
+            if buf.is_empty() {
+                return Err(SshError::from("sftp channel closed."));
+            }
+            self.recv_buf.append(&mut buf);
+        }
+    }
+
+    fn recv_response(&mut self, id: u32) -> SshResult<Response> {
+        let mut data = self.read_packet()?;
+        let packet_type = data.try_get_u8()?;
+        let response_id = data.try_get_u32()?;
+        if response_id != id {
+            return Err(SshError::from(format!(
+                "sftp response id mismatch, expect {} but got {}.",
+                id, response_id
+            )));
+        }
+        match packet_type {
+            sftp::SSH_FXP_STATUS => {
+                let code = data.try_get_u32()?;
+                if code == sftp::SSH_FX_OK || code == sftp::SSH_FX_EOF {
+                    return Ok(Response::Status(code));
+                }
+                // some old servers do not send the message
+                let msg = if data.len() >= 4 {
+                    String::from_utf8_lossy(&data.try_get_u8s()?).to_string()
+                } else {
+                    String::new()
+                };
+                Err(SshErrorKind::SftpError(code, msg).into())
+            }
+            sftp::SSH_FXP_HANDLE => Ok(Response::Handle(data.try_get_u8s()?)),
+            sftp::SSH_FXP_DATA => Ok(Response::Data(data.try_get_u8s()?)),
+            sftp::SSH_FXP_NAME => {
+                let count = data.try_get_u32()?;
+                // the count is not trusted, an entry takes 12 bytes at least
+                let mut entries = Vec::with_capacity((count as usize).min(data.len() / 12));
+                for _ in 0..count {
+                    entries.push(DirEntry::unpack(&mut data)?);
+                }
+                Ok(Response::Name(entries))
+            }
+            sftp::SSH_FXP_ATTRS => Ok(Response::Attrs(FileAttributes::unpack(&mut data)?)),
+            x => Err(SshError::from(format!("unexpected sftp message {}.", x))),
+        }
+    }
+
+    fn expect_status(&mut self, id: u32) -> SshResult<()> {
+        match self.recv_response(id)? {
+            Response::Status(sftp::SSH_FX_OK) => Ok(()),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn expect_handle(&mut self, id: u32) -> SshResult<Vec<u8>> {
+        match self.recv_response(id)? {
+            Response::Handle(handle) => Ok(handle),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn expect_attrs(&mut self, id: u32) -> SshResult<FileAttributes> {
+        match self.recv_response(id)? {
+            Response::Attrs(attrs) => Ok(attrs),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    fn expect_single_name(&mut self, id: u32) -> SshResult<String> {
+        match self.recv_response(id)? {
+            Response::Name(mut entries) if entries.len() == 1 => Ok(entries.remove(0).filename),
+            _ => Err(unexpected_response()),
+        }
+    }
+}
+
+fn unexpected_response() -> SshError {
+    SshError::from("unexpected sftp response.")
+}
@@ -0,0 +1,182 @@
+use crate::{
+    algorithm::PubKey,
+    config::auth::{AuthInfo, AuthMethod},
+    constant::ssh_str,
+    error::{SshErrorKind, SshResult},
+    model::Data,
+    util,
+};
+
+/// the next user auth request to send
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AuthAction {
+    // the "none" method, to learn the methods allowed by the server
+    None,
+    // with the index of the key pair & the signature algorithm
+    PublicKey(usize, PubKey),
+    // with the index of the agent identity
+    Agent(usize),
+    KeyboardInteractive,
+    Password,
+}
+
+/// Decide the order of the authentication methods
+///
+/// It only consumes the replies of the server and tells which request to send,
+/// the io is done by the caller
+///
+pub(crate) struct AuthEngine {
+    preferred: Vec<AuthMethod>,
+    // the signature algorithms of each key pair, to try in order
+    key_algs: Vec<Vec<PubKey>>,
+    agent_key_count: usize,
+    has_password: bool,
+    has_interactive: bool,
+    next_key: usize,
+    next_alg: usize,
+    next_agent_key: usize,
+    password_tried: bool,
+    interactive_tried: bool,
+    allowed: Vec<String>,
+    attempted: Vec<String>,
+    current: Option<AuthAction>,
+}
+
+impl AuthEngine {
+    pub fn new(auth: &AuthInfo, agent_key_count: usize) -> Self {
+        let preferred = if auth.preferred_methods.is_empty() {
+            AuthMethod::default_order()
+        } else {
+            auth.preferred_methods.clone()
+        };
+        Self {
+            preferred,
+            key_algs: vec![],
+            agent_key_count,
+            has_password: !auth.password.is_empty(),
+            has_interactive: auth.keyboard_interactive.is_some(),
+            next_key: 0,
+            next_alg: 0,
+            next_agent_key: 0,
+            password_tried: false,
+            interactive_tried: false,
+            allowed: vec![],
+            attempted: vec![],
+            current: None,
+        }
+    }
+
+    /// `key_algs` is known after the extensions of the server
+    pub fn start(&mut self, key_algs: Vec<Vec<PubKey>>) -> AuthAction {
+        self.key_algs = key_algs;
+        self.current = Some(AuthAction::None);
+        AuthAction::None
+    }
+
+    /// the request in progress
+    pub fn current(&self) -> Option<AuthAction> {
+        self.current
+    }
+
+    /*
+        byte         SSH_MSG_USERAUTH_FAILURE
+        name-list    authentications that can continue
+        boolean      partial success
+    */
+    pub fn on_failure(&mut self, mut data: Data) -> SshResult<AuthAction> {
+        let allowed = util::from_utf8(data.get_u8s())?;
+        let partial_success = data.get_u8() != 0;
+        self.allowed = allowed
+            .split(',')
+            .filter(|m| !m.is_empty())
+            .map(String::from)
+            .collect();
+
+        if let Some(current) = self.current {
+            if current != AuthAction::None {
+                let method = Self::method_name(current);
+                // the key is accepted, no need to try its other algorithms
+                if partial_success && matches!(current, AuthAction::PublicKey(..)) {
+                    self.next_key += 1;
+                    self.next_alg = 0;
+                }
+                if partial_success {
+                    log::info!("user auth partial success. ({}), continue", method);
+                } else {
+                    log::error!("user auth failure. ({})", method);
+                }
+            }
+        }
+        log::info!("authentications that can continue: {}", allowed);
+        self.next()
+    }
+
+    /// give up the request in progress without an answer of the server,
+    /// e.g. the agent fails to sign with an accepted key
+    pub fn skip(&mut self) -> SshResult<AuthAction> {
+        self.next()
+    }
+
+    fn next(&mut self) -> SshResult<AuthAction> {
+        // skip the key pairs without any algorithm left
+        while self.next_key < self.key_algs.len()
+            && self.next_alg >= self.key_algs[self.next_key].len()
+        {
+            self.next_key += 1;
+            self.next_alg = 0;
+        }
+
+        let mut action = None;
+        for method in self.preferred.iter() {
+            if !self.allowed.iter().any(|m| m == method.as_ref()) {
+                continue;
+            }
+            match method {
+                // the other algorithms of the same key are tried on failure
+                AuthMethod::PublicKey if self.next_key < self.key_algs.len() => {
+                    let alg = self.key_algs[self.next_key][self.next_alg];
+                    action = Some(AuthAction::PublicKey(self.next_key, alg));
+                    self.next_alg += 1;
+                }
+                AuthMethod::PublicKey if self.next_agent_key < self.agent_key_count => {
+                    action = Some(AuthAction::Agent(self.next_agent_key));
+                    self.next_agent_key += 1;
+                }
+                AuthMethod::KeyboardInteractive
+                    if self.has_interactive && !self.interactive_tried =>
+                {
+                    action = Some(AuthAction::KeyboardInteractive);
+                    self.interactive_tried = true;
+                }
+                AuthMethod::Password if self.has_password && !self.password_tried => {
+                    action = Some(AuthAction::Password);
+                    self.password_tried = true;
+                }
+                _ => continue,
+            }
+            break;
+        }
+
+        match action {
+            Some(action) => {
+                self.attempted.push(Self::method_name(action).to_string());
+                self.current = Some(action);
+                Ok(action)
+            }
+            None => Err(SshErrorKind::AuthFailure {
+                attempted: self.attempted.clone(),
+                allowed: self.allowed.clone(),
+            }
+            .into()),
+        }
+    }
+
+    fn method_name(action: AuthAction) -> &'static str {
+        match action {
+            AuthAction::None => "none",
+            AuthAction::PublicKey(..) | AuthAction::Agent(_) => ssh_str::PUBLIC_KEY,
+            AuthAction::KeyboardInteractive => ssh_str::KEYBOARD_INTERACTIVE,
+            AuthAction::Password => ssh_str::PASSWORD,
+        }
+    }
+}
@@ -1,7 +1,23 @@
-use crate::{algorithm::encryption::Encryption, config::Config};
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    algorithm::{compression::Compression, encryption::Encryption},
+    config::Config,
+    model::Data,
+    SshResult,
+};
+
+use super::ext_info::ExtInfo;
 
 use crate::config::algorithm::AlgList;
-use crate::{algorithm::encryption::EncryptionNone, model::Sequence};
+use crate::{
+    algorithm::{compression::CompressNone, encryption::EncryptionNone},
+    model::Sequence,
+};
 
 // the underlay connection
 pub(crate) struct Client {
@@ -10,6 +26,17 @@ pub(crate) struct Client {
     pub(super) negotiated: AlgList,
     pub(super) encryptor: Box<dyn Encryption>,
     pub(super) session_id: Vec<u8>,
+    // when the current key was exchanged
+    pub(super) kex_time: Instant,
+    // the messages received during a rekey started by us
+    pub(super) deferred: VecDeque<Data>,
+    // both sides agreed on the strict kex in the initial exchange
+    pub(super) kex_strict: bool,
+    pub(super) ext_info: ExtInfo,
+    // the payloads sent & received
+    pub(super) compressor: Box<dyn Compression>,
+    pub(super) decompressor: Box<dyn Compression>,
+    pub(super) authenticated: bool,
 }
 
 impl Client {
@@ -20,9 +47,24 @@ impl Client {
             negotiated: AlgList::new(),
             session_id: vec![],
             sequence: Sequence::new(),
+            kex_time: Instant::now(),
+            deferred: VecDeque::new(),
+            kex_strict: false,
+            ext_info: ExtInfo::default(),
+            compressor: Box::new(CompressNone {}),
+            decompressor: Box::new(CompressNone {}),
+            authenticated: false,
         }
     }
 
+    pub fn compress(&mut self, payload: &[u8]) -> SshResult<Vec<u8>> {
+        self.compressor.compress(payload)
+    }
+
+    pub fn decompress(&mut self, payload: &[u8]) -> SshResult<Vec<u8>> {
+        self.decompressor.decompress(payload)
+    }
+
     pub fn get_encryptor(&mut self) -> &mut dyn Encryption {
         self.encryptor.as_mut()
     }
@@ -38,4 +80,15 @@ impl Client {
     pub fn set_timeout(&mut self, tm: u128) {
         self.config.timeout = tm
     }
+
+    pub fn agent_forward(&self) -> Option<PathBuf> {
+        self.config.agent_forward.clone()
+    }
+
+    pub fn server_alive_interval(&self) -> Option<Duration> {
+        match self.config.server_alive_interval {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
 }
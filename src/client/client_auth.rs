@@ -1,13 +1,18 @@
 use std::io::{Read, Write};
 
 use crate::{
-    algorithm::Digest,
-    constant::{ssh_msg_code, ssh_str},
+    algorithm::{Digest, PubKey},
+    config::{agent::AgentIdentity, auth, interactive::Prompt},
+    constant::{agent, size, ssh_msg_code, ssh_str},
     error::{SshError, SshResult},
     model::{Data, Packet, SecPacket},
+    util,
 };
 
-use super::Client;
+use super::{
+    auth_engine::{AuthAction, AuthEngine},
+    Client,
+};
 
 impl Client {
     pub fn do_auth<S>(&mut self, stream: &mut S, digest: &mut Digest) -> SshResult<()>
@@ -20,43 +25,55 @@ impl Client {
             .put_str(ssh_str::SSH_USERAUTH);
         data.pack(self).write_stream(stream)?;
 
-        let mut tried_public_key = false;
+        let identities = self.agent_identities();
+        let mut engine = AuthEngine::new(&self.config.auth, identities.len());
         loop {
             let mut data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
             let message_code = data.get_u8();
             match message_code {
                 ssh_msg_code::SSH_MSG_SERVICE_ACCEPT => {
-                    if self.config.auth.key_pair.is_none() {
-                        tried_public_key = true;
-                        // if no private key specified
-                        // just try password auth
-                        self.password_authentication(stream)?
-                    } else {
-                        // if private key was provided
-                        // use public key auth first, then fallback to password auth
-                        self.public_key_authentication(stream)?
-                    }
+                    let action = engine.start(self.key_algorithms());
+                    self.send_auth_request(stream, action, &identities)?
                 }
                 ssh_msg_code::SSH_MSG_USERAUTH_FAILURE => {
-                    if !tried_public_key {
-                        log::error!("user auth failure. (public key)");
-                        log::info!("fallback to password authentication");
-                        tried_public_key = true;
-                        // keep the same with openssh
-                        // if the public key auth failed
-                        // try with password again
-                        self.password_authentication(stream)?
-                    } else {
-                        log::error!("user auth failure. (password)");
-                        return Err(SshError::from("user auth failure."));
-                    }
+                    let action = engine.on_failure(data)?;
+                    self.send_auth_request(stream, action, &identities)?
                 }
-                ssh_msg_code::SSH_MSG_USERAUTH_PK_OK => {
-                    log::info!("user auth support this algorithm.");
-                    self.public_key_signature(stream, digest)?
+                // the same message number means different things in different methods
+                ssh_msg_code::SSH_MSG_USERAUTH_PK_OK => match engine.current() {
+                    Some(AuthAction::PublicKey(index, alg)) => {
+                        log::info!("user auth support this algorithm.");
+                        self.public_key_signature(stream, digest, index, alg)?
+                    }
+                    Some(AuthAction::Agent(index)) => {
+                        log::info!("user auth support this algorithm.");
+                        if !self.agent_signature(stream, &identities[index])? {
+                            let action = engine.skip()?;
+                            self.send_auth_request(stream, action, &identities)?
+                        }
+                    }
+                    Some(AuthAction::KeyboardInteractive) => {
+                        self.keyboard_interactive_response(stream, data)?
+                    }
+                    Some(AuthAction::Password) => {
+                        return Err(SshError::from(
+                            "the password is expired and must be changed.",
+                        ))
+                    }
+                    _ => log::debug!("Ignore ssh msg {}", message_code),
+                },
+                // right after the first NEWKEYS, or before the auth success
+                ssh_msg_code::SSH_MSG_EXT_INFO => self.ext_info.update(data)?,
+                ssh_msg_code::SSH_MSG_USERAUTH_BANNER => {
+                    let banner = util::from_utf8(data.get_u8s())?;
+                    log::info!("banner: {}", banner);
                 }
                 ssh_msg_code::SSH_MSG_USERAUTH_SUCCESS => {
                     log::info!("user auth successful.");
+                    // the delayed compression starts from the next packet
+                    self.authenticated = true;
+                    self.compressor.activate();
+                    self.decompressor.activate();
                     return Ok(());
                 }
                 ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => {
@@ -69,6 +86,120 @@ impl Client {
         }
     }
 
+    fn send_auth_request<S>(
+        &mut self,
+        stream: &mut S,
+        action: AuthAction,
+        identities: &[AgentIdentity],
+    ) -> SshResult<()>
+    where
+        S: Write,
+    {
+        match action {
+            AuthAction::None => self.none_authentication(stream),
+            AuthAction::PublicKey(index, alg) => self.public_key_authentication(stream, index, alg),
+            AuthAction::Agent(index) => self.agent_authentication(stream, &identities[index]),
+            AuthAction::KeyboardInteractive => self.keyboard_interactive_authentication(stream),
+            AuthAction::Password => self.password_authentication(stream),
+        }
+    }
+
+    // no authentication at all,
+    // the server replies the methods that can continue unless it accepts anyone
+    fn none_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_REQUEST)
+            .put_str(self.config.auth.username.as_str())
+            .put_str(ssh_str::SSH_CONNECTION)
+            .put_str(ssh_str::NONE);
+
+        data.pack(self).write_stream(stream)
+    }
+
+    /*
+        byte      SSH_MSG_USERAUTH_REQUEST
+        string    user name (ISO-10646 UTF-8, as defined in [RFC-3629])
+        string    service name (US-ASCII)
+        string    "keyboard-interactive" (US-ASCII)
+        string    language tag (as defined in [RFC-3066])
+        string    submethods (ISO-10646 UTF-8)
+    */
+    fn keyboard_interactive_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Write,
+    {
+        log::info!("keyboard interactive authentication.");
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_REQUEST)
+            .put_str(self.config.auth.username.as_str())
+            .put_str(ssh_str::SSH_CONNECTION)
+            .put_str(ssh_str::KEYBOARD_INTERACTIVE)
+            .put_str("")
+            .put_str("");
+
+        data.pack(self).write_stream(stream)
+    }
+
+    /*
+        byte      SSH_MSG_USERAUTH_INFO_REQUEST
+        string    name (ISO-10646 UTF-8)
+        string    instruction (ISO-10646 UTF-8)
+        string    language tag (as defined in [RFC-3066])
+        int       num-prompts
+        string    prompt[1] (ISO-10646 UTF-8)
+        boolean   echo[1]
+        ...
+    */
+    fn keyboard_interactive_response<S>(&mut self, stream: &mut S, mut data: Data) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let name = util::from_utf8(data.try_get_u8s()?)?;
+        let instruction = util::from_utf8(data.try_get_u8s()?)?;
+        // language tag, deprecated
+        data.try_get_u8s()?;
+        let num_prompts = data.try_get_u32()?;
+        if num_prompts > size::MAX_PROMPTS {
+            return Err(SshError::from(format!(
+                "too many keyboard interactive prompts: {num_prompts}."
+            )));
+        }
+        // don't trust the count to allocate
+        let mut prompts = Vec::with_capacity(num_prompts.min(32) as usize);
+        for _ in 0..num_prompts {
+            let prompt = util::from_utf8(data.try_get_u8s()?)?;
+            let echo = data.try_get_u8()? != 0;
+            prompts.push(Prompt { prompt, echo });
+        }
+        log::info!("keyboard interactive got {} prompts.", prompts.len());
+
+        // the server may send a request w/o prompts at last
+        let responses = if prompts.is_empty() {
+            vec![]
+        } else {
+            let handler = self.config.auth.keyboard_interactive.clone().unwrap();
+            handler.prompt(&name, &instruction, &prompts)?
+        };
+        if responses.len() != prompts.len() {
+            return Err(SshError::from(format!(
+                "{} prompts but {} responses.",
+                prompts.len(),
+                responses.len()
+            )));
+        }
+
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_INFO_RESPONSE)
+            .put_u32(responses.len() as u32);
+        for response in responses.iter() {
+            data.put_str(response);
+        }
+        data.pack(self).write_stream(stream)
+    }
+
     fn password_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
     where
         S: Write,
@@ -85,32 +216,45 @@ impl Client {
         data.pack(self).write_stream(stream)
     }
 
-    fn public_key_authentication<S>(&mut self, stream: &mut S) -> SshResult<()>
+    // the signature algorithms of each key pair,
+    // decided by the key type & the `server-sig-algs` of the server
+    fn key_algorithms(&self) -> Vec<Vec<PubKey>> {
+        let server_sig_algs = self.ext_info.server_sig_algs.as_deref();
+        self.config
+            .auth
+            .key_pairs
+            .iter()
+            .map(|key_pair| {
+                let algs = key_pair.algorithms(server_sig_algs);
+                if algs.is_empty() {
+                    log::warn!("the server accepts no signature algorithm of a key, skip it.");
+                }
+                algs
+            })
+            .collect()
+    }
+
+    fn public_key_authentication<S>(
+        &mut self,
+        stream: &mut S,
+        index: usize,
+        pubkey_alg: PubKey,
+    ) -> SshResult<()>
     where
         S: Write,
     {
         let data = {
-            let pubkey_alg = &self.negotiated.public_key[0];
-            log::info!(
-                "public key authentication. algorithm: {}",
-                pubkey_alg.as_ref()
-            );
+            let (key_alg, key_blob) = self.config.auth.key_pairs[index]
+                .public_key(&pubkey_alg, self.config.auth.passphrase_provider.as_ref())?;
+            log::info!("public key authentication. algorithm: {}", key_alg.as_ref());
             let mut data = Data::new();
             data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_REQUEST)
                 .put_str(self.config.auth.username.as_str())
                 .put_str(ssh_str::SSH_CONNECTION)
                 .put_str(ssh_str::PUBLIC_KEY)
                 .put_u8(false as u8)
-                .put_str(pubkey_alg.as_ref())
-                .put_u8s(
-                    &self
-                        .config
-                        .auth
-                        .key_pair
-                        .as_ref()
-                        .unwrap()
-                        .get_blob(pubkey_alg),
-                );
+                .put_str(key_alg.as_ref())
+                .put_u8s(&key_blob);
             data
         };
         data.pack(self).write_stream(stream)
@@ -120,12 +264,15 @@ impl Client {
         &mut self,
         stream: &mut S,
         digest: &Digest,
+        index: usize,
+        pubkey_alg: PubKey,
     ) -> SshResult<()>
     where
         S: Write,
     {
         let data = {
-            let pubkey_alg = &self.negotiated.public_key[0];
+            let (key_alg, key_blob) = self.config.auth.key_pairs[index]
+                .public_key(&pubkey_alg, self.config.auth.passphrase_provider.as_ref())?;
 
             let mut data = Data::new();
             data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_REQUEST)
@@ -133,25 +280,138 @@ impl Client {
                 .put_str(ssh_str::SSH_CONNECTION)
                 .put_str(ssh_str::PUBLIC_KEY)
                 .put_u8(true as u8)
-                .put_str(pubkey_alg.as_ref())
-                .put_u8s(
-                    &self
-                        .config
-                        .auth
-                        .key_pair
-                        .as_ref()
-                        .unwrap()
-                        .get_blob(pubkey_alg),
-                );
-            let signature = self.config.auth.key_pair.as_ref().unwrap().signature(
+                .put_str(key_alg.as_ref())
+                .put_u8s(&key_blob);
+            // signed by the plain key algorithm even with a certificate
+            let signature = self.config.auth.key_pairs[index].signature(
                 data.as_slice(),
                 digest.hash_ctx.clone(),
                 digest.key_exchange.as_ref().unwrap().get_hash_type(),
-                pubkey_alg,
-            );
+                &pubkey_alg,
+                self.config.auth.passphrase_provider.as_ref(),
+            )?;
             data.put_u8s(&signature);
             data
         };
         data.pack(self).write_stream(stream)
     }
+
+    // the keys of the agent, empty if there is no agent or it cannot be reached
+    fn agent_identities(&self) -> Vec<AgentIdentity> {
+        let agent = match &self.config.auth.agent {
+            Some(agent) => agent,
+            None => return vec![],
+        };
+        let identities = match agent.lock() {
+            Ok(mut agent) => agent.identities(),
+            Err(_) => return vec![],
+        };
+        match identities {
+            Ok(identities) => {
+                log::info!("got {} keys from the agent.", identities.len());
+                identities
+            }
+            Err(e) => {
+                log::error!("failed to list the keys of the agent: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    // the signature algorithm of the agent key & the flags asking the agent for it
+    fn agent_algorithm(&self, identity: &AgentIdentity) -> SshResult<(String, u32)> {
+        let key_type = identity.key_type()?;
+        if key_type != "ssh-rsa" {
+            return Ok((key_type, 0));
+        }
+        let rsa_alg = auth::rsa_algorithms(self.ext_info.server_sig_algs.as_deref())
+            .first()
+            .copied()
+            .unwrap_or(PubKey::RsaSha2_256);
+        let alg = match rsa_alg {
+            PubKey::RsaSha2_512 => (
+                PubKey::RsaSha2_512.as_ref().to_string(),
+                agent::SSH_AGENT_RSA_SHA2_512,
+            ),
+            #[cfg(feature = "dangerous-rsa-sha1")]
+            PubKey::SshRsa => (key_type, 0),
+            _ => (
+                PubKey::RsaSha2_256.as_ref().to_string(),
+                agent::SSH_AGENT_RSA_SHA2_256,
+            ),
+        };
+        Ok(alg)
+    }
+
+    fn agent_request_data(
+        &self,
+        identity: &AgentIdentity,
+        alg: &str,
+        with_signature: bool,
+    ) -> Data {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_USERAUTH_REQUEST)
+            .put_str(self.config.auth.username.as_str())
+            .put_str(ssh_str::SSH_CONNECTION)
+            .put_str(ssh_str::PUBLIC_KEY)
+            .put_u8(with_signature as u8)
+            .put_str(alg)
+            .put_u8s(&identity.blob);
+        data
+    }
+
+    fn agent_authentication<S>(&mut self, stream: &mut S, identity: &AgentIdentity) -> SshResult<()>
+    where
+        S: Write,
+    {
+        let (alg, _) = self.agent_algorithm(identity)?;
+        log::info!(
+            "public key authentication with agent key {}. algorithm: {}",
+            identity.comment,
+            alg
+        );
+        let data = self.agent_request_data(identity, &alg, false);
+        data.pack(self).write_stream(stream)
+    }
+
+    /*
+        string    session identifier
+        byte      SSH_MSG_USERAUTH_REQUEST
+        string    user name
+        string    service name
+        string    "publickey"
+        boolean   TRUE
+        string    public key algorithm name
+        string    public key to be used for authentication
+    */
+    // false if the agent cannot sign, the caller moves on to the next identity or method
+    fn agent_signature<S>(&mut self, stream: &mut S, identity: &AgentIdentity) -> SshResult<bool>
+    where
+        S: Write,
+    {
+        let (alg, flags) = self.agent_algorithm(identity)?;
+        let mut data = self.agent_request_data(identity, &alg, true);
+
+        let mut sd = Data::new();
+        sd.put_u8s(&self.session_id);
+        sd.extend_from_slice(&data);
+
+        let signature = match &self.config.auth.agent {
+            Some(agent) => match agent.lock() {
+                Ok(mut agent) => agent.sign(&identity.blob, &sd, flags),
+                Err(_) => return Err(SshError::from("the agent is poisoned.")),
+            },
+            None => return Err(SshError::from("no agent for the authentication.")),
+        };
+        let signature = match signature {
+            Ok(signature) => signature,
+            Err(e) => {
+                log::warn!("the agent fails to sign, skip the identity: {}", e);
+                return Ok(false);
+            }
+        };
+        data.put_u8s(&signature);
+        data.pack(self).write_stream(stream)?;
+        Ok(true)
+    }
 }
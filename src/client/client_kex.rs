@@ -1,6 +1,11 @@
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
 use crate::{
     algorithm::{
-        encryption,
+        compression, encryption,
         hash::{self, HashCtx},
         key_exchange::{self, KeyExchange},
         mac,
@@ -13,9 +18,14 @@ use crate::{
     error::{SshError, SshResult},
     model::{Data, Packet, SecPacket},
 };
-use std::io::{Read, Write};
+
+// RFC 4344, rekey before 2^31 packets are sent or received w/ a key
+const REKEY_MAX_PACKETS: u64 = 1 << 31;
 
 impl Client {
+    /// the key exchange started by the server,
+    /// whose algorithm list is already received
+    ///
     pub fn key_agreement<S>(
         &mut self,
         stream: &mut S,
@@ -24,6 +34,82 @@ impl Client {
     ) -> SshResult<()>
     where
         S: Read + Write,
+    {
+        self.send_algs(stream, digest)?;
+        self.exchange_keys(stream, server_algs, digest)
+    }
+
+    /// the key exchange started by us when a rekey limit is reached
+    ///
+    /// the other messages received before the algorithm list of the server
+    /// are put aside, see [Client::take_deferred]
+    ///
+    pub fn rekey<S>(&mut self, stream: &mut S) -> SshResult<()>
+    where
+        S: Read + Write,
+    {
+        log::info!("rekey limit reached.");
+        let mut digest = Digest::new();
+        self.send_algs(stream, &mut digest)?;
+        let server_algs = loop {
+            let data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
+            if data[0] == ssh_msg_code::SSH_MSG_KEXINIT {
+                digest.hash_ctx.set_i_s(&data);
+                break AlgList::unpack((data, &mut *self).into())?;
+            }
+            self.deferred.push_back(data);
+        };
+        self.exchange_keys(stream, server_algs, &mut digest)
+    }
+
+    /// a message received during the rekey, which is not yet handled
+    ///
+    pub fn take_deferred(&mut self) -> Option<Data> {
+        self.deferred.pop_front()
+    }
+
+    pub fn has_deferred(&self) -> bool {
+        !self.deferred.is_empty()
+    }
+
+    /// whether a limit of the current key is reached,
+    /// the bytes one is also bounded by RFC 4344,
+    /// which is 2^(L/4) blocks for a cipher of L bits blocks
+    ///
+    pub fn need_rekey(&self) -> bool {
+        // not yet connected
+        if self.session_id.is_empty() {
+            return false;
+        }
+        let bsize = self.encryptor.bsize() as u64;
+        let mut limit = if bsize >= 16 {
+            (1 << (bsize * 2).min(32)) * bsize
+        } else {
+            1 << 30
+        };
+        if self.config.rekey_limit != 0 {
+            limit = limit.min(self.config.rekey_limit);
+        }
+        let seq = &self.sequence;
+        seq.client_bytes >= limit
+            || seq.server_bytes >= limit
+            || seq.client_packets >= REKEY_MAX_PACKETS
+            || seq.server_packets >= REKEY_MAX_PACKETS
+            || matches!(self.rekey_after(), Some(left) if left.is_zero())
+    }
+
+    /// the time left before the time limit of the current key
+    ///
+    pub fn rekey_after(&self) -> Option<Duration> {
+        match self.config.rekey_interval {
+            0 => None,
+            secs => Some(Duration::from_secs(secs).saturating_sub(self.kex_time.elapsed())),
+        }
+    }
+
+    fn send_algs<S>(&mut self, stream: &mut S, digest: &mut Digest) -> SshResult<()>
+    where
+        S: Write,
     {
         // initialize the hash context
         if let SshVersion::V2(ref our, ref their) = self.config.ver {
@@ -34,12 +120,31 @@ impl Client {
         log::info!("start for key negotiation.");
         log::info!("send client algorithm list.");
 
-        let algs = self.config.algs.clone();
+        let mut algs = self.config.algs.clone();
+        // only in the initial exchange
+        algs.kex_strict = self.session_id.is_empty();
+        algs.ext_info = self.session_id.is_empty();
         let client_algs = algs.pack(self);
         digest.hash_ctx.set_i_c(client_algs.get_inner());
-        client_algs.write_stream(stream)?;
+        client_algs.write_stream(stream)
+    }
 
+    fn exchange_keys<S>(
+        &mut self,
+        stream: &mut S,
+        server_algs: AlgList,
+        digest: &mut Digest,
+    ) -> SshResult<()>
+    where
+        S: Read + Write,
+    {
         let negotiated = self.config.algs.match_with(&server_algs)?;
+        if self.session_id.is_empty() {
+            self.kex_strict = server_algs.kex_strict;
+            if self.kex_strict {
+                log::info!("strict key exchange enabled.");
+            }
+        }
 
         // key exchange algorithm
         let mut key_exchange = key_exchange::from(&negotiated.key_exchange[0])?;
@@ -76,10 +181,20 @@ impl Client {
         // encryption algorithm
         let encryption = encryption::from(&negotiated.c_encryption[0], hash, mac);
 
+        // the compression streams last through the rekeys
+        if self.negotiated.c_compress.first() != negotiated.c_compress.first() {
+            self.compressor = compression::from(&negotiated.c_compress[0], self.authenticated);
+        }
+        if self.negotiated.s_compress.first() != negotiated.s_compress.first() {
+            self.decompressor = compression::from(&negotiated.s_compress[0], self.authenticated);
+        }
+
         self.session_id = session_id;
         self.negotiated = negotiated;
         self.encryptor = encryption;
         digest.key_exchange = Some(key_exchange);
+        self.sequence.reset_counters();
+        self.kex_time = Instant::now();
 
         log::info!("key negotiation successful.");
 
@@ -108,6 +223,8 @@ impl Client {
         S: Read + Write,
     {
         let mut session_id = vec![];
+        // any other message aborts the initial strict exchange
+        let strict = self.kex_strict && self.session_id.is_empty();
         loop {
             let mut data = Data::unpack(SecPacket::from_stream(stream, self)?)?;
             let message_code = data.get_u8();
@@ -123,12 +240,27 @@ impl Client {
                         return Err(SshError::from("signature verification failure."));
                     }
                     log::info!("signature verification success.");
+                    // the signature only proves that the server owns the key,
+                    // so we still need to check whether this key belongs to the host
+                    self.config.verify_host_key(&h.k_s[4..])?;
                 }
                 ssh_msg_code::SSH_MSG_NEWKEYS => {
+                    if self.kex_strict {
+                        self.sequence.reset_server();
+                    }
                     self.new_keys(stream)?;
                     return Ok(session_id);
                 }
-                _ => unreachable!(),
+                x @ (ssh_msg_code::SSH_MSG_IGNORE | ssh_msg_code::SSH_MSG_DEBUG) if !strict => {
+                    log::debug!("ignore message {} during key exchange.", x)
+                }
+                x => {
+                    log::error!("unexpected message {} during key exchange.", x);
+                    return Err(SshError::from(format!(
+                        "unexpected message {} during key exchange.",
+                        x
+                    )));
+                }
             }
         }
     }
@@ -142,12 +274,15 @@ impl Client {
     ) -> SshResult<Vec<u8>> {
         let ks = data.get_u8s();
         h.set_k_s(&ks);
-        // TODO 未进行密钥指纹验证！！
         let qs = data.get_u8s();
         h.set_q_c(key_exchange.get_public_key());
         h.set_q_s(&qs);
         let vec = key_exchange.get_shared_secret(qs)?;
-        h.set_k(&vec);
+        if key_exchange.is_kem() {
+            h.set_k_string(&vec);
+        } else {
+            h.set_k(&vec);
+        }
         let h = data.get_u8s();
         let mut hd = Data::from(h);
         hd.get_u8s();
@@ -163,6 +298,10 @@ impl Client {
         let mut data = Data::new();
         data.put_u8(ssh_msg_code::SSH_MSG_NEWKEYS);
         log::info!("send new keys");
-        data.pack(self).write_stream(stream)
+        data.pack(self).write_stream(stream)?;
+        if self.kex_strict {
+            self.sequence.reset_client();
+        }
+        Ok(())
     }
 }
@@ -0,0 +1,95 @@
+use crate::{constant::ssh_str, error::SshResult, model::Data, util};
+
+/// The extensions sent by the server in `SSH_MSG_EXT_INFO`, see RFC 8308
+///
+#[derive(Default)]
+pub(crate) struct ExtInfo {
+    // the signature algorithms accepted in the user auth
+    pub server_sig_algs: Option<Vec<String>>,
+    // the version of `publickey-hostbound@openssh.com`
+    pub publickey_hostbound: Option<String>,
+    // `p` for preferred or `s` for supported
+    pub no_flow_control: Option<String>,
+    // the c2s & s2c compression algorithms after the user auth
+    pub delay_compression: Option<(Vec<String>, Vec<String>)>,
+}
+
+impl ExtInfo {
+    /*
+        byte       SSH_MSG_EXT_INFO (value 7)
+        uint32     nr-extensions
+        repeat the following 2 fields "nr-extensions" times:
+          string   extension-name
+          string   extension-value (binary)
+    */
+    // the message code shall be already consumed,
+    // a later message replaces the extensions it contains
+    pub fn update(&mut self, mut data: Data) -> SshResult<()> {
+        let count = data.try_get_u32()?;
+        for _ in 0..count {
+            let name = util::from_utf8(data.try_get_u8s()?)?;
+            let value = data.try_get_u8s()?;
+            match name.as_str() {
+                ssh_str::SERVER_SIG_ALGS => {
+                    let algs = util::vec_u8_to_string(value, ",")?;
+                    log::info!("server signature algorithms: {:?}", algs);
+                    self.server_sig_algs = Some(algs);
+                }
+                ssh_str::PUBLICKEY_HOSTBOUND => {
+                    let version = self.publickey_hostbound.insert(util::from_utf8(value)?);
+                    log::info!("server publickey-hostbound version: {}", version);
+                }
+                ssh_str::NO_FLOW_CONTROL => {
+                    let flag = self.no_flow_control.insert(util::from_utf8(value)?);
+                    log::info!("server no-flow-control: {}", flag);
+                }
+                ssh_str::DELAY_COMPRESSION => {
+                    let mut value = Data::from(value);
+                    let c2s = util::vec_u8_to_string(value.try_get_u8s()?, ",")?;
+                    let s2c = util::vec_u8_to_string(value.try_get_u8s()?, ",")?;
+                    let (c2s, s2c) = self.delay_compression.insert((c2s, s2c));
+                    log::info!("server delay compression: {:?}, {:?}", c2s, s2c);
+                }
+                x => log::debug!("ignore server extension {}.", x),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext_info(name: &str, value: &[u8]) -> ExtInfo {
+        let mut data = Data::new();
+        data.put_u32(1).put_str(name).put_u8s(value);
+        let mut ext_info = ExtInfo::default();
+        ext_info.update(data).unwrap();
+        ext_info
+    }
+
+    #[test]
+    fn publickey_hostbound() {
+        let ext_info = ext_info(ssh_str::PUBLICKEY_HOSTBOUND, b"0");
+        assert_eq!(ext_info.publickey_hostbound.as_deref(), Some("0"));
+    }
+
+    #[test]
+    fn no_flow_control() {
+        let ext_info = ext_info(ssh_str::NO_FLOW_CONTROL, b"p");
+        assert_eq!(ext_info.no_flow_control.as_deref(), Some("p"));
+    }
+
+    #[test]
+    fn delay_compression() {
+        let mut value = Data::new();
+        value
+            .put_str("zlib@openssh.com,none")
+            .put_str("zlib@openssh.com");
+        let ext_info = ext_info(ssh_str::DELAY_COMPRESSION, &value);
+        let (c2s, s2c) = ext_info.delay_compression.unwrap();
+        assert_eq!(c2s, vec!["zlib@openssh.com", "none"]);
+        assert_eq!(s2c, vec!["zlib@openssh.com"]);
+    }
+}
@@ -1,6 +1,8 @@
+mod auth_engine;
 #[allow(clippy::module_inception)]
 pub(crate) mod client;
 mod client_auth;
 mod client_kex;
+mod ext_info;
 
 pub(crate) use client::Client;
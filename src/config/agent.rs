@@ -0,0 +1,164 @@
+use std::{
+    fmt::Debug,
+    io::{Read, Write},
+};
+
+use crate::{
+    constant::agent,
+    error::{SshError, SshResult},
+    model::Data,
+    util,
+};
+
+use super::known_hosts::key_type;
+
+/// the stream connected to an ssh agent
+pub trait AgentStream: Read + Write + Send {}
+
+impl<T> AgentStream for T where T: Read + Write + Send {}
+
+/// A key held by the ssh agent
+///
+#[derive(Debug, Clone)]
+pub struct AgentIdentity {
+    /// the public key blob
+    pub blob: Vec<u8>,
+    pub comment: String,
+}
+
+impl AgentIdentity {
+    /// the key type, e.g. `ssh-ed25519`
+    pub fn key_type(&self) -> SshResult<String> {
+        key_type(&self.blob)
+    }
+}
+
+/// A client of the ssh agent protocol
+///
+/// The private keys stay in the agent,
+/// the agent is only asked to sign the authentication data
+///
+pub struct AgentClient {
+    stream: Box<dyn AgentStream>,
+}
+
+impl Debug for AgentClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "AgentClient")
+    }
+}
+
+impl AgentClient {
+    /// talk to the agent over any stream
+    ///
+    pub fn new<S>(stream: S) -> Self
+    where
+        S: AgentStream + 'static,
+    {
+        Self {
+            stream: Box::new(stream),
+        }
+    }
+
+    /// connect to the agent listening on the unix socket `path`
+    ///
+    #[cfg(unix)]
+    pub fn connect<P>(path: P) -> SshResult<Self>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        let stream = std::os::unix::net::UnixStream::connect(path)?;
+        Ok(Self::new(stream))
+    }
+
+    /// connect to the agent pointed by `$SSH_AUTH_SOCK`
+    ///
+    #[cfg(unix)]
+    pub fn from_env() -> SshResult<Self> {
+        match std::env::var_os(agent::SSH_AUTH_SOCK) {
+            Some(path) => Self::connect(path),
+            None => Err(SshError::from("SSH_AUTH_SOCK is not set.")),
+        }
+    }
+
+    /*
+        byte            SSH_AGENT_IDENTITIES_ANSWER
+        uint32          nkeys
+        nkeys times:
+        string          key blob
+        string          comment
+    */
+    /// list the keys held by the agent
+    ///
+    pub fn identities(&mut self) -> SshResult<Vec<AgentIdentity>> {
+        let mut data = Data::new();
+        data.put_u8(agent::SSH_AGENTC_REQUEST_IDENTITIES);
+        let mut data = self.request(data)?;
+        match data.get_u8() {
+            agent::SSH_AGENT_IDENTITIES_ANSWER => {
+                let count = data.get_u32();
+                let mut identities = vec![];
+                for _ in 0..count {
+                    let blob = data.get_u8s();
+                    let comment = util::from_utf8(data.get_u8s())?;
+                    identities.push(AgentIdentity { blob, comment })
+                }
+                Ok(identities)
+            }
+            agent::SSH_AGENT_FAILURE => Err(SshError::from("the agent refused to list the keys.")),
+            x => Err(SshError::from(format!("unexpected agent message {}.", x))),
+        }
+    }
+
+    /*
+        byte            SSH_AGENTC_SIGN_REQUEST
+        string          key blob
+        string          data
+        uint32          flags
+    */
+    /// ask the agent to sign `data` with the key `blob`,
+    /// the signature is returned in the ssh format, `string algorithm, string signature`
+    ///
+    /// `flags` selects the rsa-sha2 signature algorithms of a `ssh-rsa` key
+    ///
+    pub fn sign(&mut self, blob: &[u8], data: &[u8], flags: u32) -> SshResult<Vec<u8>> {
+        let mut req = Data::new();
+        req.put_u8(agent::SSH_AGENTC_SIGN_REQUEST)
+            .put_u8s(blob)
+            .put_u8s(data)
+            .put_u32(flags);
+        let mut data = self.request(req)?;
+        match data.get_u8() {
+            agent::SSH_AGENT_SIGN_RESPONSE => Ok(data.get_u8s()),
+            agent::SSH_AGENT_FAILURE => Err(SshError::from("the agent refused to sign.")),
+            x => Err(SshError::from(format!("unexpected agent message {}.", x))),
+        }
+    }
+
+    // every message is prefixed by its length
+    fn request(&mut self, msg: Data) -> SshResult<Data> {
+        let mut buf = Data::new();
+        buf.put_u8s(&msg);
+        let mut reply = Data::from(self.relay(&buf)?);
+        Ok(Data::from(reply.get_u8s()))
+    }
+
+    /// send a length prefixed message to the agent as it is,
+    /// and return the length prefixed reply
+    ///
+    pub(crate) fn relay(&mut self, msg: &[u8]) -> SshResult<Vec<u8>> {
+        self.stream.write_all(msg)?;
+        self.stream.flush()?;
+
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let body_len = u32::from_be_bytes(len) as usize;
+        if body_len == 0 || body_len > agent::MAX_MSG_LEN {
+            return Err(SshError::from("invalid agent message length."));
+        }
+        let mut reply = vec![0; 4 + body_len];
+        reply[..4].copy_from_slice(&len);
+        self.stream.read_exact(&mut reply[4..])?;
+        Ok(reply)
+    }
+}
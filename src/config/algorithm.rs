@@ -7,7 +7,7 @@ use std::{
 use crate::{
     algorithm::{Compress, Enc, Kex, Mac, PubKey},
     client::Client,
-    constant::ssh_msg_code,
+    constant::{ssh_msg_code, ssh_str},
     error::{SshError, SshResult},
     model::{Data, Packet, SecPacket},
     util,
@@ -78,6 +78,11 @@ pub(crate) struct AlgList {
     pub s_mac: Macs,
     pub c_compress: Compresses,
     pub s_compress: Compresses,
+    // `kex-strict-*-v00@openssh.com`, which is advertised by us
+    // or supported by the server
+    pub kex_strict: bool,
+    // advertise `ext-info-c`
+    pub ext_info: bool,
 }
 
 impl Debug for AlgList {
@@ -103,19 +108,62 @@ impl AlgList {
     pub fn client_default() -> Self {
         AlgList {
             key_exchange: vec![
+                Kex::Mlkem768x25519Sha256,
                 Kex::Curve25519Sha256,
                 Kex::EcdhSha2Nistrp256,
                 Kex::DiffieHellmanGroup14Sha256,
                 Kex::DiffieHellmanGroup14Sha1,
             ]
             .into(),
-            public_key: vec![PubKey::RsaSha2_512, PubKey::RsaSha2_256].into(),
-            c_encryption: vec![Enc::Chacha20Poly1305Openssh, Enc::Aes128Ctr].into(),
-            s_encryption: vec![Enc::Chacha20Poly1305Openssh, Enc::Aes128Ctr].into(),
-            c_mac: vec![Mac::HmacSha2_256, Mac::HmacSha2_512, Mac::HmacSha1].into(),
-            s_mac: vec![Mac::HmacSha2_256, Mac::HmacSha2_512, Mac::HmacSha1].into(),
+            public_key: vec![
+                PubKey::SshEd25519,
+                PubKey::RsaSha2_512,
+                PubKey::RsaSha2_256,
+                PubKey::EcdsaSha2Nistp256,
+                PubKey::EcdsaSha2Nistp384,
+                PubKey::EcdsaSha2Nistp521,
+            ]
+            .into(),
+            c_encryption: vec![
+                Enc::Chacha20Poly1305Openssh,
+                Enc::Aes128Ctr,
+                Enc::Aes192Ctr,
+                Enc::Aes256Ctr,
+                Enc::Aes128GcmOpenssh,
+                Enc::Aes256GcmOpenssh,
+            ]
+            .into(),
+            s_encryption: vec![
+                Enc::Chacha20Poly1305Openssh,
+                Enc::Aes128Ctr,
+                Enc::Aes192Ctr,
+                Enc::Aes256Ctr,
+                Enc::Aes128GcmOpenssh,
+                Enc::Aes256GcmOpenssh,
+            ]
+            .into(),
+            c_mac: vec![
+                Mac::HmacSha2_256Etm,
+                Mac::HmacSha2_512Etm,
+                Mac::HmacSha1Etm,
+                Mac::HmacSha2_256,
+                Mac::HmacSha2_512,
+                Mac::HmacSha1,
+            ]
+            .into(),
+            s_mac: vec![
+                Mac::HmacSha2_256Etm,
+                Mac::HmacSha2_512Etm,
+                Mac::HmacSha1Etm,
+                Mac::HmacSha2_256,
+                Mac::HmacSha2_512,
+                Mac::HmacSha1,
+            ]
+            .into(),
             c_compress: vec![Compress::None].into(),
             s_compress: vec![Compress::None].into(),
+            kex_strict: false,
+            ext_info: false,
         }
     }
 
@@ -132,7 +180,10 @@ impl AlgList {
                 server_algorithm.$field = alg_string.try_into()?;
             };
         }
-        try_convert!("key exchange", key_exchange);
+        let kex_string = util::vec_u8_to_string(data.get_u8s(), ",")?;
+        log::info!("server key exchange: {:?}", kex_string);
+        server_algorithm.kex_strict = kex_string.iter().any(|x| x == ssh_str::KEX_STRICT_S);
+        server_algorithm.key_exchange = kex_string.try_into()?;
         try_convert!("public key", public_key);
         try_convert!("c2s encryption", c_encryption);
         try_convert!("s2c encryption", s_encryption);
@@ -195,6 +246,8 @@ impl AlgList {
             s_mac: vec![*s_mac].into(),
             c_compress: vec![*c_compress].into(),
             s_compress: vec![*s_compress].into(),
+            kex_strict: self.kex_strict && other.kex_strict,
+            ext_info: false,
         };
 
         log::info!("matched algorithms [{:?}]", negotiated);
@@ -204,7 +257,19 @@ impl AlgList {
 
     fn as_i(&self) -> Vec<u8> {
         let mut data = Data::new();
-        data.put_str(&self.key_exchange.to_string());
+        // the pseudo algorithms follow the real ones
+        let mut kex = self
+            .key_exchange
+            .iter()
+            .map(|x| x.as_ref())
+            .collect::<Vec<&str>>();
+        if self.ext_info {
+            kex.push(ssh_str::EXT_INFO_C);
+        }
+        if self.kex_strict {
+            kex.push(ssh_str::KEX_STRICT_C);
+        }
+        data.put_str(&kex.join(","));
         data.put_str(&self.public_key.to_string());
         data.put_str(&self.c_encryption.to_string());
         data.put_str(&self.s_encryption.to_string());
@@ -0,0 +1,82 @@
+use crate::error::{SshError, SshResult};
+
+/// One prompt of a `keyboard-interactive` info request
+///
+#[derive(Debug, Clone)]
+pub struct Prompt {
+    pub prompt: String,
+    /// whether the answer can be displayed while typing,
+    /// it is false for passwords & OTPs
+    pub echo: bool,
+}
+
+/// Answer the prompts sent by the server during `keyboard-interactive` authentication (RFC 4256)
+///
+/// `name` & `instruction` may be empty,
+/// return exactly one answer for each prompt, in the same order
+///
+/// This method may be called several times in one authentication, e.g. password then OTP
+///
+pub trait KeyboardInteractive: Send + Sync {
+    fn prompt(&self, name: &str, instruction: &str, prompts: &[Prompt]) -> SshResult<Vec<String>>;
+}
+
+/// A [KeyboardInteractive] handler which answers the password prompts,
+/// for servers that only allow PAM-backed `keyboard-interactive` logins
+///
+/// other prompts are refused
+///
+pub struct PasswordPrompter {
+    password: String,
+}
+
+impl PasswordPrompter {
+    pub fn new(password: &str) -> Self {
+        Self {
+            password: password.to_owned(),
+        }
+    }
+}
+
+impl KeyboardInteractive for PasswordPrompter {
+    fn prompt(
+        &self,
+        _name: &str,
+        _instruction: &str,
+        prompts: &[Prompt],
+    ) -> SshResult<Vec<String>> {
+        prompts
+            .iter()
+            .map(|p| {
+                if p.prompt.to_lowercase().contains("password") {
+                    Ok(self.password.clone())
+                } else {
+                    Err(SshError::from(format!(
+                        "cannot answer the prompt \"{}\".",
+                        p.prompt.trim()
+                    )))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Provide the passphrase of an encrypted private key
+///
+/// `key` tells which key it is, e.g. the path of the key file
+///
+/// It is called when the key is used for the first time,
+/// any closure `Fn(&str) -> SshResult<String>` can be used
+///
+pub trait PassphraseProvider: Send + Sync {
+    fn passphrase(&self, key: &str) -> SshResult<String>;
+}
+
+impl<F> PassphraseProvider for F
+where
+    F: Fn(&str) -> SshResult<String> + Send + Sync,
+{
+    fn passphrase(&self, key: &str) -> SshResult<String> {
+        self(key)
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use ring::hmac;
+
+use crate::{
+    algorithm::public_key::certificate::{Certificate, CERT_SUFFIX, SSH_CERT_TYPE_HOST},
+    error::{SshError, SshErrorKind, SshResult},
+    model::Data,
+    util,
+};
+
+/// hashed host names start with this magic, `|1|base64(salt)|base64(hmac-sha1(salt, host))`
+const HASH_MAGIC: &str = "|1|";
+const MARKER_REVOKED: &str = "@revoked";
+const MARKER_CERT_AUTHORITY: &str = "@cert-authority";
+const DEFAULT_PORT: u16 = 22;
+
+/// Verify the host key presented by the server during the key exchange
+///
+/// `host_key` is the raw public key blob (`K_S`) sent by the server,
+/// return an error to abort the connection
+///
+pub trait HostKeyVerifier: Send + Sync {
+    fn verify(&self, host: &str, port: u16, host_key: &[u8]) -> SshResult<()>;
+
+    /// whether a certificate authority is trusted for the host,
+    /// the host certificate algorithms are preferred if so
+    ///
+    fn has_cert_authority(&self, _host: &str, _port: u16) -> bool {
+        false
+    }
+}
+
+/// the SHA256 fingerprint of a public key blob,
+/// in the same format as `ssh-keygen -l` outputs
+///
+pub fn fingerprint(key: &[u8]) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, key);
+    format!(
+        "SHA256:{}",
+        base64::encode_config(digest.as_ref(), base64::STANDARD_NO_PAD)
+    )
+}
+
+/// the algorithm name stored at the beginning of a public key blob
+pub(crate) fn key_type(key: &[u8]) -> SshResult<String> {
+    let mut data = Data::from(key);
+    let key_type = data
+        .try_get_u8s()
+        .map_err(|_| SshError::from("invalid public key blob."))?;
+    util::from_utf8(key_type)
+}
+
+/// the name looked up in `known_hosts`,
+/// hosts on a non-default port are recorded as `[host]:port`
+fn lookup_name(host: &str, port: u16) -> String {
+    if port == DEFAULT_PORT {
+        host.to_string()
+    } else {
+        format!("[{}]:{}", host, port)
+    }
+}
+
+#[derive(PartialEq, Eq)]
+pub(crate) enum Marker {
+    None,
+    Revoked,
+    CertAuthority,
+}
+
+/// one line of a `known_hosts` file
+///
+/// `[marker] hostpatterns keytype base64-key [comment]`
+///
+pub(crate) struct Entry {
+    pub marker: Marker,
+    pub hosts: String,
+    pub key_type: String,
+    pub key: Vec<u8>,
+}
+
+impl Entry {
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let mut fields = line.split_whitespace();
+        let mut hosts = fields.next()?;
+        let marker = match hosts {
+            MARKER_REVOKED => Marker::Revoked,
+            MARKER_CERT_AUTHORITY => Marker::CertAuthority,
+            x if x.starts_with('@') => {
+                log::warn!("unknown marker {} in known hosts, skip it", x);
+                return None;
+            }
+            _ => Marker::None,
+        };
+        if marker != Marker::None {
+            hosts = fields.next()?;
+        }
+        let key_type = fields.next()?;
+        let key = base64::decode(fields.next()?).ok()?;
+
+        Some(Self {
+            marker,
+            hosts: hosts.to_string(),
+            key_type: key_type.to_string(),
+            key,
+        })
+    }
+
+    pub fn matches(&self, host: &str, port: u16) -> bool {
+        let lookup = lookup_name(host, port).to_lowercase();
+
+        if let Some(hashed) = self.hosts.strip_prefix(HASH_MAGIC) {
+            return match_hashed(hashed, &lookup);
+        }
+
+        let mut matched = false;
+        for pattern in self.hosts.split(',') {
+            let (negate, pattern) = match pattern.strip_prefix('!') {
+                Some(p) => (true, p),
+                None => (false, pattern),
+            };
+            if util::wildcard_match(&pattern.to_lowercase(), &lookup) {
+                if negate {
+                    // a negated match always wins
+                    return false;
+                }
+                matched = true;
+            }
+        }
+        matched
+    }
+}
+
+fn match_hashed(hashed: &str, lookup: &str) -> bool {
+    let mut parts = hashed.split('|');
+    let (salt, hash) = match (parts.next(), parts.next()) {
+        (Some(salt), Some(hash)) => (salt, hash),
+        _ => return false,
+    };
+    let (salt, hash) = match (base64::decode(salt), base64::decode(hash)) {
+        (Ok(salt), Ok(hash)) => (salt, hash),
+        _ => return false,
+    };
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &salt);
+    hmac::verify(&key, lookup.as_bytes(), &hash).is_ok()
+}
+
+/// read all the entries of a `known_hosts` file,
+/// a missing file is treated as an empty one
+pub(crate) fn read_entries(path: &Path) -> SshResult<(String, Vec<Entry>)> {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    let entries = content.lines().filter_map(Entry::parse).collect();
+    Ok((content, entries))
+}
+
+/// A [HostKeyVerifier] backed by an OpenSSH `known_hosts` file
+///
+/// Supports plain and hashed (`|1|`) host names, wildcard & negated patterns,
+/// non-default ports (`[host]:port`), `@revoked` keys
+/// and host certificates signed by the `@cert-authority` keys
+///
+pub struct KnownHosts {
+    path: PathBuf,
+    accept_new: bool,
+}
+
+impl KnownHosts {
+    pub fn new<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            accept_new: false,
+        }
+    }
+
+    /// use `~/.ssh/known_hosts` of the current user
+    ///
+    pub fn user_default() -> SshResult<Self> {
+        match util::home_dir() {
+            Some(home) => Ok(Self::new(home.join(".ssh").join("known_hosts"))),
+            None => Err(SshError::from("cannot find the home directory.")),
+        }
+    }
+
+    /// trust on first use
+    ///
+    /// if set, keys of unknown hosts will be accepted and appended to the file,
+    /// which is the same as `StrictHostKeyChecking accept-new` of OpenSSH
+    ///
+    pub fn accept_new(mut self, accept: bool) -> Self {
+        self.accept_new = accept;
+        self
+    }
+
+    fn append(
+        &self,
+        content: &str,
+        host: &str,
+        port: u16,
+        key_type: &str,
+        host_key: &[u8],
+    ) -> SshResult<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        let mut line = String::new();
+        if !content.is_empty() && !content.ends_with('\n') {
+            line.push('\n');
+        }
+        line.push_str(&format!(
+            "{} {} {}\n",
+            lookup_name(host, port),
+            key_type,
+            base64::encode(host_key)
+        ));
+        file.write_all(line.as_bytes())?;
+        log::info!(
+            "permanently added {} ({}) to the list of known hosts.",
+            lookup_name(host, port),
+            key_type
+        );
+        Ok(())
+    }
+}
+
+impl KnownHosts {
+    fn verify_key(&self, host: &str, port: u16, host_key: &[u8]) -> SshResult<()> {
+        let key_type = key_type(host_key)?;
+        let (content, entries) = read_entries(&self.path)?;
+
+        let mut found = false;
+        let mut changed = false;
+        for entry in entries.iter().filter(|e| e.matches(host, port)) {
+            match entry.marker {
+                Marker::Revoked => {
+                    if entry.key == host_key {
+                        log::error!(
+                            "host key {} of {} is revoked.",
+                            fingerprint(host_key),
+                            lookup_name(host, port)
+                        );
+                        return Err(SshErrorKind::RevokedHostKey(lookup_name(host, port)).into());
+                    }
+                }
+                Marker::CertAuthority => continue,
+                Marker::None => {
+                    if entry.key == host_key {
+                        found = true;
+                    } else if entry.key_type == key_type {
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if found {
+            log::info!("host key of {} verified.", lookup_name(host, port));
+            Ok(())
+        } else if changed {
+            log::error!(
+                "host key of {} has changed, the fingerprint of the new key is {}.",
+                lookup_name(host, port),
+                fingerprint(host_key)
+            );
+            Err(SshErrorKind::HostKeyMismatch(lookup_name(host, port)).into())
+        } else if self.accept_new {
+            self.append(&content, host, port, &key_type, host_key)
+        } else {
+            log::error!(
+                "host key of {} is unknown, the fingerprint is {}.",
+                lookup_name(host, port),
+                fingerprint(host_key)
+            );
+            Err(SshErrorKind::UnknownHostKey(lookup_name(host, port)).into())
+        }
+    }
+
+    // a host certificate is trusted if it is signed by one of the CAs of the host,
+    // otherwise the certified key is looked up as a plain host key
+    fn verify_certificate(&self, host: &str, port: u16, host_key: &[u8]) -> SshResult<()> {
+        let cert = Certificate::parse(host_key)?;
+        let (_, entries) = read_entries(&self.path)?;
+        let entries = entries
+            .into_iter()
+            .filter(|e| e.matches(host, port))
+            .collect::<Vec<Entry>>();
+
+        let revoked = entries.iter().any(|e| {
+            e.marker == Marker::Revoked && (e.key == cert.signature_key || e.key == cert.public_key)
+        });
+        if revoked {
+            log::error!(
+                "host certificate {} of {} or its CA is revoked.",
+                cert.key_id,
+                lookup_name(host, port)
+            );
+            return Err(SshErrorKind::RevokedHostKey(lookup_name(host, port)).into());
+        }
+
+        let trusted = entries
+            .iter()
+            .any(|e| e.marker == Marker::CertAuthority && e.key == cert.signature_key);
+        if !trusted {
+            log::warn!(
+                "host certificate of {} is not signed by a trusted CA, verify the certified key {} instead.",
+                lookup_name(host, port),
+                fingerprint(&cert.public_key)
+            );
+            return self.verify_key(host, port, &cert.public_key);
+        }
+
+        match check_host_certificate(&cert, host) {
+            Ok(()) => {
+                log::info!(
+                    "host certificate {} (serial {}) of {} verified, signed by CA {}.",
+                    cert.key_id,
+                    cert.serial,
+                    lookup_name(host, port),
+                    fingerprint(&cert.signature_key)
+                );
+                Ok(())
+            }
+            Err(reason) => {
+                log::error!(
+                    "host certificate {} of {} is rejected: {}",
+                    cert.key_id,
+                    lookup_name(host, port),
+                    reason
+                );
+                Err(SshErrorKind::InvalidHostCertificate(lookup_name(host, port)).into())
+            }
+        }
+    }
+}
+
+// return the reason if the certificate cannot be accepted
+fn check_host_certificate(cert: &Certificate, host: &str) -> Result<(), String> {
+    if cert.cert_type != SSH_CERT_TYPE_HOST {
+        return Err("not a host certificate.".to_string());
+    }
+    match cert.verify_signature() {
+        Ok(true) => (),
+        Ok(false) => return Err("invalid signature of the CA.".to_string()),
+        Err(e) => return Err(e.to_string()),
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    if !cert.is_valid_at(now) {
+        return Err("expired or not yet valid.".to_string());
+    }
+    let host = host.to_lowercase();
+    if !cert.principals.is_empty() && !cert.principals.iter().any(|p| p.to_lowercase() == host) {
+        return Err(format!("{} is not a principal of the certificate.", host));
+    }
+    // no critical option is defined for host certificates
+    if let Some(option) = cert.critical_options.first() {
+        return Err(format!("unsupported critical option {}.", option));
+    }
+    Ok(())
+}
+
+impl HostKeyVerifier for KnownHosts {
+    fn verify(&self, host: &str, port: u16, host_key: &[u8]) -> SshResult<()> {
+        if key_type(host_key)?.ends_with(CERT_SUFFIX) {
+            self.verify_certificate(host, port, host_key)
+        } else {
+            self.verify_key(host, port, host_key)
+        }
+    }
+
+    fn has_cert_authority(&self, host: &str, port: u16) -> bool {
+        match read_entries(&self.path) {
+            Ok((_, entries)) => entries
+                .iter()
+                .any(|e| e.marker == Marker::CertAuthority && e.matches(host, port)),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(hosts: &str) -> String {
+        format!("{} ssh-ed25519 {} comment", hosts, base64::encode(b"key"))
+    }
+
+    fn hashed(host: &str) -> String {
+        let salt = b"0123456789abcdefghij";
+        let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, salt);
+        let hash = hmac::sign(&key, host.as_bytes());
+        format!(
+            "{}{}|{}",
+            HASH_MAGIC,
+            base64::encode(salt),
+            base64::encode(hash.as_ref())
+        )
+    }
+
+    #[test]
+    fn parse_entry() {
+        let entry = Entry::parse(&line("example.com,10.0.0.1")).unwrap();
+        assert!(entry.marker == Marker::None);
+        assert_eq!(entry.hosts, "example.com,10.0.0.1");
+        assert_eq!(entry.key_type, "ssh-ed25519");
+        assert_eq!(entry.key, b"key");
+
+        assert!(Entry::parse("").is_none());
+        assert!(Entry::parse("# example.com ssh-ed25519 AAAA").is_none());
+        assert!(Entry::parse("example.com ssh-ed25519").is_none());
+        assert!(Entry::parse("example.com ssh-ed25519 !invalid!").is_none());
+    }
+
+    #[test]
+    fn parse_marker() {
+        let revoked = Entry::parse(&format!("@revoked {}", line("*"))).unwrap();
+        assert!(revoked.marker == Marker::Revoked);
+        assert_eq!(revoked.hosts, "*");
+
+        let ca = Entry::parse(&format!("@cert-authority {}", line("*.example.com"))).unwrap();
+        assert!(ca.marker == Marker::CertAuthority);
+        assert_eq!(ca.hosts, "*.example.com");
+
+        assert!(Entry::parse(&format!("@unknown {}", line("*"))).is_none());
+    }
+
+    #[test]
+    fn match_patterns() {
+        let entry = Entry::parse(&line("example.com,*.example.org,10.0.0.?")).unwrap();
+        assert!(entry.matches("example.com", 22));
+        assert!(entry.matches("EXAMPLE.com", 22));
+        assert!(entry.matches("www.example.org", 22));
+        assert!(entry.matches("10.0.0.1", 22));
+        assert!(!entry.matches("example.net", 22));
+        assert!(!entry.matches("10.0.0.10", 22));
+        // the default port only
+        assert!(!entry.matches("example.com", 2222));
+    }
+
+    #[test]
+    fn match_port() {
+        let entry = Entry::parse(&line("[example.com]:2222")).unwrap();
+        assert!(entry.matches("example.com", 2222));
+        assert!(!entry.matches("example.com", 22));
+        assert!(!entry.matches("example.com", 2223));
+    }
+
+    #[test]
+    fn match_negation() {
+        let entry = Entry::parse(&line("*.example.com,!secret.example.com")).unwrap();
+        assert!(entry.matches("www.example.com", 22));
+        assert!(!entry.matches("secret.example.com", 22));
+
+        // a negation alone matches nothing
+        let entry = Entry::parse(&line("!example.com")).unwrap();
+        assert!(!entry.matches("example.com", 22));
+        assert!(!entry.matches("example.org", 22));
+    }
+
+    #[test]
+    fn match_hashed() {
+        let entry = Entry::parse(&line(&hashed("example.com"))).unwrap();
+        assert!(entry.matches("example.com", 22));
+        assert!(!entry.matches("example.org", 22));
+        assert!(!entry.matches("example.com", 2222));
+
+        let entry = Entry::parse(&line(&hashed("[example.com]:2222"))).unwrap();
+        assert!(entry.matches("example.com", 2222));
+        assert!(!entry.matches("example.com", 22));
+
+        let entry = Entry::parse(&line("|1|invalid")).unwrap();
+        assert!(!entry.matches("example.com", 22));
+    }
+
+    #[test]
+    fn verify_revoked() {
+        let path = std::env::temp_dir().join(format!("known_hosts_{}", std::process::id()));
+        let mut key = Data::new();
+        key.put_str("ssh-ed25519").put_u8s(&[1; 32]);
+        let content = format!(
+            "@revoked example.com ssh-ed25519 {}\n",
+            base64::encode(key.as_slice())
+        );
+        fs::write(&path, content).unwrap();
+
+        let known_hosts = KnownHosts::new(&path);
+        let result = known_hosts.verify("example.com", 22, &key);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result.unwrap_err().kind(),
+            SshErrorKind::RevokedHostKey(_)
+        ));
+    }
+}
@@ -1,15 +1,16 @@
+pub(crate) mod agent;
 pub(crate) mod algorithm;
 pub(crate) mod auth;
+pub(crate) mod interactive;
+pub(crate) mod known_hosts;
+mod pem;
+pub(crate) mod ssh_config;
 pub(crate) mod version;
-use crate::algorithm::PubKey as PubKeyAlgs;
+use std::{net::IpAddr, path::PathBuf, sync::Arc};
 
-fn insert_or_move_first(v: &mut Vec<PubKeyAlgs>, alg: PubKeyAlgs) {
-    if let Some(i) = v.iter().position(|each| *each == alg) {
-        v.swap(0, i)
-    } else {
-        v.insert(0, alg)
-    }
-}
+use crate::algorithm::PubKey as PubKeyAlgs;
+use crate::error::{SshErrorKind, SshResult};
+use known_hosts::HostKeyVerifier;
 
 #[derive(Clone)]
 pub(crate) struct Config {
@@ -17,6 +18,19 @@ pub(crate) struct Config {
     pub auth: auth::AuthInfo,
     pub algs: algorithm::AlgList,
     pub timeout: u128, // in milliseconds
+    pub host: String,
+    // the address connected, to check the host key by the ip as well
+    pub host_ip: Option<IpAddr>,
+    pub port: u16,
+    pub host_key_verifier: Option<Arc<dyn HostKeyVerifier>>,
+    // the agent socket to forward
+    pub agent_forward: Option<PathBuf>,
+    // in seconds, 0 to disable
+    pub server_alive_interval: u64,
+    // in bytes, 0 for the default of the cipher
+    pub rekey_limit: u64,
+    // in seconds, 0 to disable
+    pub rekey_interval: u64,
     auto_tune: bool,
 }
 
@@ -27,6 +41,14 @@ impl Default for Config {
             auth: auth::AuthInfo::default(),
             ver: version::SshVersion::default(),
             timeout: 30 * 1000,
+            host: String::new(),
+            host_ip: None,
+            port: 22,
+            host_key_verifier: None,
+            agent_forward: None,
+            server_alive_interval: 0,
+            rekey_limit: 0,
+            rekey_interval: 0,
             auto_tune: true,
         }
     }
@@ -40,27 +62,88 @@ impl Config {
             auth: auth::AuthInfo::default(),
             ver: version::SshVersion::default(),
             timeout: 30 * 1000,
+            host: String::new(),
+            host_ip: None,
+            port: 22,
+            host_key_verifier: None,
+            agent_forward: None,
+            server_alive_interval: 0,
+            rekey_limit: 0,
+            rekey_interval: 0,
             auto_tune: false,
         }
     }
 
-    pub(crate) fn tune_alglist_on_private_key(&mut self) {
+    // prefer the host certificates if a certificate authority is trusted
+    pub(crate) fn tune_alglist_on_host_key_verifier(&mut self) {
         if !self.auto_tune {
             return;
         }
 
-        if let Some(ref key_pair) = self.auth.key_pair {
-            match key_pair.key_type {
-                auth::KeyType::PemRsa | auth::KeyType::SshRsa => {
-                    let pubkeys = &mut self.algs.public_key;
-                    insert_or_move_first(pubkeys, PubKeyAlgs::RsaSha2_256);
-                    insert_or_move_first(pubkeys, PubKeyAlgs::RsaSha2_512);
-                }
-                auth::KeyType::SshEd25519 => {
-                    let pubkeys = &mut self.algs.public_key;
-                    insert_or_move_first(pubkeys, PubKeyAlgs::SshEd25519);
+        let has_ca = match self.host_key_verifier {
+            Some(ref verifier) => verifier.has_cert_authority(&self.host, self.port),
+            None => false,
+        };
+        if has_ca {
+            let pubkeys = &mut self.algs.public_key;
+            let certs = pubkeys
+                .iter()
+                .filter_map(|alg| alg.certificate())
+                .collect::<Vec<PubKeyAlgs>>();
+            pubkeys.retain(|alg| !certs.contains(alg));
+            pubkeys.splice(0..0, certs);
+        }
+    }
+
+    pub(crate) fn verify_host_key(&self, host_key: &[u8]) -> SshResult<()> {
+        match self.host_key_verifier {
+            Some(ref verifier) => {
+                verifier.verify(&self.host, self.port, host_key)?;
+                match self.host_ip.map(|ip| ip.to_string()) {
+                    Some(ip) if ip != self.host => self.check_host_ip(verifier, &ip, host_key),
+                    _ => Ok(()),
                 }
             }
+            None => {
+                log::warn!(
+                    "host key {} of {} is not verified.",
+                    known_hosts::fingerprint(host_key),
+                    self.host
+                );
+                Ok(())
+            }
+        }
+    }
+
+    // `CheckHostIP` of OpenSSH, the key of the name is already verified,
+    // a different key recorded for the ip may be a dns spoofing
+    fn check_host_ip(
+        &self,
+        verifier: &Arc<dyn HostKeyVerifier>,
+        ip: &str,
+        host_key: &[u8],
+    ) -> SshResult<()> {
+        match verifier.verify(ip, self.port, host_key) {
+            Ok(()) => Ok(()),
+            Err(e) => match e.kind() {
+                SshErrorKind::UnknownHostKey(_) => {
+                    log::debug!(
+                        "no host key of {} is recorded for the ip {}.",
+                        self.host,
+                        ip
+                    );
+                    Ok(())
+                }
+                SshErrorKind::HostKeyMismatch(_) => {
+                    log::warn!(
+                        "the host key of {} differs from the key for the ip address {}.",
+                        self.host,
+                        ip
+                    );
+                    Ok(())
+                }
+                _ => Err(e),
+            },
         }
     }
 }
@@ -0,0 +1,623 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    error::{SshError, SshResult},
+    util,
+};
+
+// the same limit as OpenSSH
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// The options of one host read from an OpenSSH `ssh_config` file
+///
+/// The first obtained value of an option is used, except `IdentityFile`
+///
+#[derive(Default)]
+pub(crate) struct HostConfig {
+    pub alias: String,
+    pub host_name: Option<String>,
+    pub port: Option<u16>,
+    pub user: Option<String>,
+    pub identity_files: Vec<String>,
+    pub proxy_jump: Option<String>,
+    pub kex_algorithms: Option<String>,
+    pub ciphers: Option<String>,
+    pub macs: Option<String>,
+    pub host_key_algorithms: Option<String>,
+    pub connect_timeout: Option<u64>,
+    pub server_alive_interval: Option<u64>,
+    // bytes & seconds
+    pub rekey_limit: Option<(u64, u64)>,
+    pub compression: Option<bool>,
+    pub user_known_hosts_file: Option<String>,
+}
+
+impl HostConfig {
+    /// read the options of `alias` from the `ssh_config` file at `path`
+    ///
+    /// relative `Include` paths are looked up in the directory of `path`
+    ///
+    pub fn load(path: &Path, alias: &str) -> SshResult<Self> {
+        let mut config = Self {
+            alias: alias.to_string(),
+            ..Default::default()
+        };
+        let base = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let content = fs::read_to_string(path)
+            .map_err(|e| SshError::from(format!("{}: {}", path.display(), e)))?;
+        config.parse(&content, &base, 0)?;
+        Ok(config)
+    }
+
+    /// the host to connect, `HostName` or the alias itself
+    pub fn host(&self) -> String {
+        match self.host_name {
+            Some(ref h) => h.replace("%h", &self.alias),
+            None => self.alias.clone(),
+        }
+    }
+
+    /// the user to log in, `User` or the local user
+    pub fn user(&self) -> String {
+        self.user.clone().unwrap_or_else(local_user)
+    }
+
+    /// expand `~` & the `%` tokens of the paths
+    pub fn expand_path(&self, path: &str) -> PathBuf {
+        let home = util::home_dir().unwrap_or_default();
+        let local_user = local_user();
+        let mut expanded = String::new();
+        let mut chars = path.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('%') => expanded.push('%'),
+                Some('d') => expanded.push_str(&home.display().to_string()),
+                Some('h') => expanded.push_str(&self.host()),
+                Some('n') => expanded.push_str(&self.alias),
+                Some('p') => expanded.push_str(&self.port.unwrap_or(22).to_string()),
+                Some('r') => expanded.push_str(self.user.as_deref().unwrap_or(&local_user)),
+                Some('u') => expanded.push_str(&local_user),
+                Some(x) => {
+                    log::warn!("unsupported token %{} in {}", x, path);
+                    expanded.push('%');
+                    expanded.push(x);
+                }
+                None => expanded.push('%'),
+            }
+        }
+        match expanded.strip_prefix('~') {
+            Some(rest) => home.join(rest.trim_start_matches('/')),
+            None => PathBuf::from(expanded),
+        }
+    }
+
+    fn parse(&mut self, content: &str, base: &Path, depth: usize) -> SshResult<()> {
+        // options before the first `Host` or `Match` apply to all hosts
+        let mut active = true;
+        for line in content.lines() {
+            let (keyword, args) = match split_line(line) {
+                Some(x) => x,
+                None => continue,
+            };
+            match keyword.as_str() {
+                "host" => active = match_patterns(&args, &self.alias),
+                "match" => active = self.match_criteria(&args)?,
+                "include" if active => {
+                    if depth >= MAX_INCLUDE_DEPTH {
+                        return Err(SshError::from("too many nested Include in ssh config."));
+                    }
+                    for arg in args.iter() {
+                        for path in include_files(&self.expand_path(arg), base)? {
+                            let content = fs::read_to_string(&path).map_err(|e| {
+                                SshError::from(format!("{}: {}", path.display(), e))
+                            })?;
+                            self.parse(&content, base, depth + 1)?;
+                        }
+                    }
+                }
+                _ if active => self.set(&keyword, args)?,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, keyword: &str, mut args: Vec<String>) -> SshResult<()> {
+        if args.is_empty() {
+            return Err(SshError::from(format!("missing argument of {}.", keyword)));
+        }
+        let first = args.remove(0);
+        match keyword {
+            "hostname" => set_once(&mut self.host_name, first),
+            "port" => set_once(&mut self.port, parse_number(keyword, &first)?),
+            "user" => set_once(&mut self.user, first),
+            "identityfile" => self.identity_files.push(first),
+            "proxyjump" => set_once(&mut self.proxy_jump, first),
+            "kexalgorithms" => set_once(&mut self.kex_algorithms, first),
+            "ciphers" => set_once(&mut self.ciphers, first),
+            "macs" => set_once(&mut self.macs, first),
+            "hostkeyalgorithms" => set_once(&mut self.host_key_algorithms, first),
+            "connecttimeout" => set_once(&mut self.connect_timeout, parse_number(keyword, &first)?),
+            "serveraliveinterval" => set_once(
+                &mut self.server_alive_interval,
+                parse_number(keyword, &first)?,
+            ),
+            // `RekeyLimit default|<size> [none|<time>]`
+            "rekeylimit" => {
+                let bytes = match first.as_str() {
+                    "default" => 0,
+                    size => parse_size(keyword, size)?,
+                };
+                let secs = match args.first().map(String::as_str) {
+                    None | Some("none") | Some("default") => 0,
+                    Some(time) => parse_time(keyword, time)?,
+                };
+                set_once(&mut self.rekey_limit, (bytes, secs))
+            }
+            "compression" => {
+                let enable = match first.as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    v => {
+                        return Err(SshError::from(format!(
+                            "invalid value {} of {}.",
+                            v, keyword
+                        )))
+                    }
+                };
+                set_once(&mut self.compression, enable)
+            }
+            // only the first file is used
+            "userknownhostsfile" => set_once(&mut self.user_known_hosts_file, first),
+            x => log::debug!("ssh config option {} is ignored.", x),
+        }
+        Ok(())
+    }
+
+    // all the criteria must match
+    fn match_criteria(&self, args: &[String]) -> SshResult<bool> {
+        let mut matched = true;
+        let mut args = args.iter();
+        while let Some(criterion) = args.next() {
+            let criterion = criterion.to_lowercase();
+            let (negate, criterion) = match criterion.strip_prefix('!') {
+                Some(c) => (true, c),
+                None => (false, criterion.as_str()),
+            };
+            let result = match criterion {
+                "all" => true,
+                // a single pass here, which is also the final one
+                "final" => true,
+                "canonical" => false,
+                _ => {
+                    let arg = args.next().ok_or_else(|| {
+                        SshError::from(format!("missing argument of Match {}.", criterion))
+                    })?;
+                    let patterns = arg.split(',').map(String::from).collect::<Vec<String>>();
+                    match criterion {
+                        "host" => match_patterns(&patterns, &self.host()),
+                        "originalhost" => match_patterns(&patterns, &self.alias),
+                        "user" => {
+                            match_patterns(&patterns, self.user.as_deref().unwrap_or(&local_user()))
+                        }
+                        "localuser" => match_patterns(&patterns, &local_user()),
+                        x => {
+                            log::warn!("Match {} is not supported, treated as not matched.", x);
+                            false
+                        }
+                    }
+                }
+            };
+            matched &= result != negate;
+        }
+        Ok(matched)
+    }
+}
+
+fn set_once<T>(option: &mut Option<T>, value: T) {
+    if option.is_none() {
+        *option = Some(value)
+    }
+}
+
+fn parse_number<T>(keyword: &str, v: &str) -> SshResult<T>
+where
+    T: FromStr,
+{
+    v.parse::<T>()
+        .map_err(|_| SshError::from(format!("invalid value {} of {}.", v, keyword)))
+}
+
+// a number w/ an optional `K`, `M` or `G` suffix
+fn parse_size(keyword: &str, v: &str) -> SshResult<u64> {
+    let (num, unit) = match v.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => (&v[..i], c.to_ascii_uppercase()),
+        _ => (v, ' '),
+    };
+    let shift = match unit {
+        ' ' => 0,
+        'K' => 10,
+        'M' => 20,
+        'G' => 30,
+        _ => {
+            return Err(SshError::from(format!(
+                "invalid value {} of {}.",
+                v, keyword
+            )))
+        }
+    };
+    Ok(parse_number::<u64>(keyword, num)? << shift)
+}
+
+// seconds, or the sum of numbers w/ `s`, `m`, `h`, `d` or `w`, e.g. `1h30m`
+fn parse_time(keyword: &str, v: &str) -> SshResult<u64> {
+    let mut secs = 0;
+    let mut num = String::new();
+    for c in v.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            'w' => 7 * 24 * 60 * 60,
+            _ => {
+                return Err(SshError::from(format!(
+                    "invalid value {} of {}.",
+                    v, keyword
+                )))
+            }
+        };
+        secs += parse_number::<u64>(keyword, &num)? * unit;
+        num.clear();
+    }
+    if !num.is_empty() {
+        secs += parse_number::<u64>(keyword, &num)?;
+    }
+    Ok(secs)
+}
+
+fn local_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_default()
+}
+
+// `keyword [=] arg1 "arg 2" ...`, the keyword is case insensitive
+fn split_line(line: &str) -> Option<(String, Vec<String>)> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line
+        .find(|c: char| c.is_whitespace() || c == '=')
+        .unwrap_or(line.len());
+    let keyword = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let rest = rest.strip_prefix('=').unwrap_or(rest);
+
+    let mut args = vec![];
+    let mut arg = String::new();
+    let mut quoted = false;
+    for c in rest.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && arg.is_empty() => break,
+            c if c.is_whitespace() && !quoted => {
+                if !arg.is_empty() {
+                    args.push(std::mem::take(&mut arg));
+                }
+            }
+            c => arg.push(c),
+        }
+    }
+    if !arg.is_empty() {
+        args.push(arg);
+    }
+    Some((keyword, args))
+}
+
+/// split a `ProxyJump` hop, `[user@]host[:port]` or `ssh://[user@]host[:port]`
+pub(crate) fn parse_jump(jump: &str) -> SshResult<(Option<&str>, &str, Option<u16>)> {
+    let jump = jump.strip_prefix("ssh://").unwrap_or(jump);
+    let (user, host) = match jump.rsplit_once('@') {
+        Some((user, host)) => (Some(user), host),
+        None => (None, jump),
+    };
+    // `[::1]:2222`
+    let (host, port) = if let Some(rest) = host.strip_prefix('[') {
+        match rest.split_once(']') {
+            Some((host, port)) => (host, port.strip_prefix(':')),
+            None => return Err(SshError::from(format!("invalid jump host {}.", jump))),
+        }
+    } else {
+        match host.split_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host, None),
+        }
+    };
+    let port = match port {
+        Some(port) => Some(parse_number("ProxyJump", port)?),
+        None => None,
+    };
+    if host.is_empty() {
+        return Err(SshError::from(format!("invalid jump host {}.", jump)));
+    }
+    Ok((user, host, port))
+}
+
+/// match `host` against a list of patterns, a negated match always wins
+pub(crate) fn match_patterns(patterns: &[String], host: &str) -> bool {
+    let host = host.to_lowercase();
+    let mut matched = false;
+    for pattern in patterns {
+        let (negate, pattern) = match pattern.strip_prefix('!') {
+            Some(p) => (true, p),
+            None => (false, pattern.as_str()),
+        };
+        if util::wildcard_match(&pattern.to_lowercase(), &host) {
+            if negate {
+                return false;
+            }
+            matched = true;
+        }
+    }
+    matched
+}
+
+// the files matched by an `Include` path, wildcards are only allowed in the file name
+fn include_files(path: &Path, base: &Path) -> SshResult<Vec<PathBuf>> {
+    let path = if path.is_relative() {
+        base.join(path)
+    } else {
+        path.to_path_buf()
+    };
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => return Ok(vec![]),
+    };
+    if !name.contains(['*', '?']) {
+        // a missing file is not an error
+        return Ok(if path.is_file() { vec![path] } else { vec![] });
+    }
+    let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    let mut files = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.is_file())
+            .filter(|p| match p.file_name() {
+                Some(n) => util::wildcard_match(&name, &n.to_string_lossy()),
+                None => false,
+            })
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => vec![],
+    };
+    // in lexical order, the same as glob(3)
+    files.sort();
+    Ok(files)
+}
+
+/// apply an algorithm option to `algs`
+///
+/// `+` appends, `-` removes and `^` prepends the algorithms matching the patterns,
+/// otherwise the list is replaced
+///
+pub(crate) fn apply_algorithms<T>(algs: &mut Vec<T>, value: &str)
+where
+    T: Copy + PartialEq + AsRef<str> + IntoEnumIterator,
+{
+    let (modifier, list) = match value.chars().next() {
+        Some(c @ ('+' | '-' | '^')) => (Some(c), &value[1..]),
+        _ => (None, value),
+    };
+    let patterns = list.split(',').collect::<Vec<&str>>();
+    let is_match = |alg: &T| {
+        patterns
+            .iter()
+            .any(|p| util::wildcard_match(p, alg.as_ref()))
+    };
+    // in the order of the patterns
+    let mut selected = vec![];
+    for pattern in patterns.iter() {
+        for alg in T::iter() {
+            if util::wildcard_match(pattern, alg.as_ref()) && !selected.contains(&alg) {
+                selected.push(alg);
+            }
+        }
+    }
+    if selected.is_empty() && modifier != Some('-') {
+        log::warn!("no supported algorithm in {}", value);
+    }
+
+    match modifier {
+        Some('+') => {
+            for alg in selected {
+                if !algs.contains(&alg) {
+                    algs.push(alg);
+                }
+            }
+        }
+        Some('-') => algs.retain(|alg| !is_match(alg)),
+        Some('^') => {
+            algs.retain(|alg| !selected.contains(alg));
+            algs.splice(0..0, selected);
+        }
+        _ => *algs = selected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::algorithm::Enc;
+
+    fn parse(alias: &str, content: &str) -> HostConfig {
+        let mut config = HostConfig {
+            alias: alias.to_string(),
+            ..Default::default()
+        };
+        config.parse(content, Path::new(""), 0).unwrap();
+        config
+    }
+
+    fn names(algs: &[Enc]) -> Vec<&str> {
+        algs.iter().map(|a| a.as_ref()).collect()
+    }
+
+    #[test]
+    fn parse_host() {
+        let content = "
+            # global options
+            Port 2222
+            Host web *.example.com !db.example.com
+                HostName = \"10.0.0.%h\"
+                User alice
+                Port 22
+                IdentityFile ~/.ssh/id_a
+            Host *
+                User bob
+                IdentityFile ~/.ssh/id_b
+                RekeyLimit 1G 1h
+        ";
+        let config = parse("web", content);
+        assert_eq!(config.host(), "10.0.0.web");
+        assert_eq!(config.port, Some(2222));
+        assert_eq!(config.user.as_deref(), Some("alice"));
+        assert_eq!(config.identity_files, ["~/.ssh/id_a", "~/.ssh/id_b"]);
+        assert_eq!(config.rekey_limit, Some((1 << 30, 3600)));
+
+        let config = parse("db.example.com", content);
+        assert_eq!(config.host(), "db.example.com");
+        assert_eq!(config.user.as_deref(), Some("bob"));
+        assert_eq!(config.identity_files, ["~/.ssh/id_b"]);
+    }
+
+    #[test]
+    fn parse_match() {
+        let content = "
+            Match originalhost web !host other
+                User alice
+            Match all
+                User bob
+                Compression yes
+        ";
+        let config = parse("web", content);
+        assert_eq!(config.user.as_deref(), Some("alice"));
+        assert_eq!(config.compression, Some(true));
+
+        let config = parse("other", content);
+        assert_eq!(config.user.as_deref(), Some("bob"));
+
+        let mut config = HostConfig::default();
+        assert!(config.parse("Match host", Path::new(""), 0).is_err());
+        assert!(config.parse("Compression maybe", Path::new(""), 0).is_err());
+    }
+
+    #[test]
+    fn parse_include() {
+        let dir = std::env::temp_dir().join(format!("ssh-rs-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("conf.d")).unwrap();
+        fs::write(dir.join("conf.d/b.conf"), "User second\n").unwrap();
+        fs::write(dir.join("conf.d/a.conf"), "Host web\n  User first\n").unwrap();
+        fs::write(dir.join("loop"), "Include loop\n").unwrap();
+        fs::write(
+            dir.join("config"),
+            "Include conf.d/*.conf missing\nHost web\n  Port 2222\n",
+        )
+        .unwrap();
+
+        let config = HostConfig::load(&dir.join("config"), "web").unwrap();
+        assert_eq!(config.user.as_deref(), Some("first"));
+        assert_eq!(config.port, Some(2222));
+        let config = HostConfig::load(&dir.join("config"), "db").unwrap();
+        assert_eq!(config.user.as_deref(), Some("second"));
+
+        // an include loop ends at the depth limit
+        assert!(HostConfig::load(&dir.join("loop"), "web").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn apply_modifiers() {
+        let mut algs = vec![Enc::Aes128Ctr, Enc::Aes256Ctr];
+        apply_algorithms(&mut algs, "+aes*-gcm@openssh.com");
+        assert_eq!(
+            names(&algs),
+            [
+                "aes128-ctr",
+                "aes256-ctr",
+                "aes128-gcm@openssh.com",
+                "aes256-gcm@openssh.com"
+            ]
+        );
+
+        apply_algorithms(&mut algs, "-*-ctr");
+        assert_eq!(
+            names(&algs),
+            ["aes128-gcm@openssh.com", "aes256-gcm@openssh.com"]
+        );
+
+        apply_algorithms(&mut algs, "^aes256-gcm@openssh.com,aes128-ctr");
+        assert_eq!(
+            names(&algs),
+            [
+                "aes256-gcm@openssh.com",
+                "aes128-ctr",
+                "aes128-gcm@openssh.com"
+            ]
+        );
+
+        apply_algorithms(&mut algs, "chacha20-poly1305@openssh.com,unknown");
+        assert_eq!(names(&algs), ["chacha20-poly1305@openssh.com"]);
+    }
+
+    #[test]
+    fn jump() {
+        assert_eq!(parse_jump("host").unwrap(), (None, "host", None));
+        assert_eq!(
+            parse_jump("user@host:2222").unwrap(),
+            (Some("user"), "host", Some(2222))
+        );
+        assert_eq!(
+            parse_jump("ssh://user@[::1]:2222").unwrap(),
+            (Some("user"), "::1", Some(2222))
+        );
+        assert_eq!(parse_jump("[::1]").unwrap(), (None, "::1", None));
+        assert!(parse_jump("user@").is_err());
+        assert!(parse_jump("host:port").is_err());
+        assert!(parse_jump("[::1").is_err());
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(parse_size("RekeyLimit", "1000").unwrap(), 1000);
+        assert_eq!(parse_size("RekeyLimit", "4k").unwrap(), 4 << 10);
+        assert_eq!(parse_size("RekeyLimit", "512M").unwrap(), 512 << 20);
+        assert_eq!(parse_size("RekeyLimit", "2G").unwrap(), 2 << 30);
+        assert!(parse_size("RekeyLimit", "1T").is_err());
+        assert!(parse_size("RekeyLimit", "G").is_err());
+    }
+
+    #[test]
+    fn time() {
+        assert_eq!(parse_time("RekeyLimit", "90").unwrap(), 90);
+        assert_eq!(parse_time("RekeyLimit", "1h30m").unwrap(), 5400);
+        assert_eq!(parse_time("RekeyLimit", "1w1d10S").unwrap(), 8 * 86400 + 10);
+        assert!(parse_time("RekeyLimit", "1y").is_err());
+        assert!(parse_time("RekeyLimit", "h").is_err());
+    }
+}
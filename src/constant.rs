@@ -13,6 +13,10 @@ pub(crate) mod ssh_str {
     pub const PUBLIC_KEY: &str = "publickey";
     /// 密码认证方式
     pub const PASSWORD: &str = "password";
+    /// 不认证， 用于获取服务端支持的认证方式
+    pub const NONE: &str = "none";
+    /// 键盘交互认证方式
+    pub const KEYBOARD_INTERACTIVE: &str = "keyboard-interactive";
     /// 打开一个会话
     pub const SESSION: &str = "session";
     /// 启动一个命令解释程序
@@ -25,6 +29,42 @@ pub(crate) mod ssh_str {
     pub const PTY_REQ: &str = "pty-req";
     /// 伪终端的样式
     pub const XTERM_VAR: &str = "xterm-256color";
+    /// 命令的退出码
+    pub const EXIT_STATUS: &str = "exit-status";
+    /// 命令被信号终止
+    pub const EXIT_SIGNAL: &str = "exit-signal";
+    /// 启动一个子系统
+    pub const SUBSYSTEM: &str = "subsystem";
+    /// sftp 子系统
+    pub const SFTP: &str = "sftp";
+    /// 本地端口转发通道
+    pub const DIRECT_TCPIP: &str = "direct-tcpip";
+    /// 请求远程端口转发
+    pub const TCPIP_FORWARD: &str = "tcpip-forward";
+    /// 取消远程端口转发
+    pub const CANCEL_TCPIP_FORWARD: &str = "cancel-tcpip-forward";
+    /// 远程端口转发通道
+    pub const FORWARDED_TCPIP: &str = "forwarded-tcpip";
+    /// 请求 agent 转发
+    pub const AUTH_AGENT_REQ: &str = "auth-agent-req@openssh.com";
+    /// agent 转发通道
+    pub const AUTH_AGENT: &str = "auth-agent@openssh.com";
+    /// 保活请求
+    pub const KEEPALIVE: &str = "keepalive@openssh.com";
+    /// 扩展协商 (RFC 8308)
+    pub const EXT_INFO_C: &str = "ext-info-c";
+    /// 服务端支持的用户认证签名算法
+    pub const SERVER_SIG_ALGS: &str = "server-sig-algs";
+    /// 服务端支持的主机绑定公钥认证
+    pub const PUBLICKEY_HOSTBOUND: &str = "publickey-hostbound@openssh.com";
+    /// 服务端可关闭流量控制
+    pub const NO_FLOW_CONTROL: &str = "no-flow-control";
+    /// 用户认证后启用的压缩算法
+    pub const DELAY_COMPRESSION: &str = "delay-compression";
+    /// 严格密钥交换 (客户端)
+    pub const KEX_STRICT_C: &str = "kex-strict-c-v00@openssh.com";
+    /// 严格密钥交换 (服务端)
+    pub const KEX_STRICT_S: &str = "kex-strict-s-v00@openssh.com";
 }
 
 #[allow(dead_code)]
@@ -77,6 +117,97 @@ pub(crate) mod scp {
     pub const FATAL_ERR: u8 = 2;
 }
 
+/// sftp 操作时用到的常量 (version 3)
+#[allow(dead_code)]
+pub(crate) mod sftp {
+    /// 客户端支持的协议版本
+    pub const VERSION: u32 = 3;
+    /// 单次读写的最大数据长度
+    pub const MAX_DATA_LEN: u32 = 32768;
+
+    // 数据包类型
+    pub const SSH_FXP_INIT: u8 = 1;
+    pub const SSH_FXP_VERSION: u8 = 2;
+    pub const SSH_FXP_OPEN: u8 = 3;
+    pub const SSH_FXP_CLOSE: u8 = 4;
+    pub const SSH_FXP_READ: u8 = 5;
+    pub const SSH_FXP_WRITE: u8 = 6;
+    pub const SSH_FXP_LSTAT: u8 = 7;
+    pub const SSH_FXP_FSTAT: u8 = 8;
+    pub const SSH_FXP_SETSTAT: u8 = 9;
+    pub const SSH_FXP_FSETSTAT: u8 = 10;
+    pub const SSH_FXP_OPENDIR: u8 = 11;
+    pub const SSH_FXP_READDIR: u8 = 12;
+    pub const SSH_FXP_REMOVE: u8 = 13;
+    pub const SSH_FXP_MKDIR: u8 = 14;
+    pub const SSH_FXP_RMDIR: u8 = 15;
+    pub const SSH_FXP_REALPATH: u8 = 16;
+    pub const SSH_FXP_STAT: u8 = 17;
+    pub const SSH_FXP_RENAME: u8 = 18;
+    pub const SSH_FXP_READLINK: u8 = 19;
+    pub const SSH_FXP_SYMLINK: u8 = 20;
+    pub const SSH_FXP_STATUS: u8 = 101;
+    pub const SSH_FXP_HANDLE: u8 = 102;
+    pub const SSH_FXP_DATA: u8 = 103;
+    pub const SSH_FXP_NAME: u8 = 104;
+    pub const SSH_FXP_ATTRS: u8 = 105;
+    pub const SSH_FXP_EXTENDED: u8 = 200;
+    pub const SSH_FXP_EXTENDED_REPLY: u8 = 201;
+
+    // 文件打开方式
+    pub const SSH_FXF_READ: u32 = 0x00000001;
+    pub const SSH_FXF_WRITE: u32 = 0x00000002;
+    pub const SSH_FXF_APPEND: u32 = 0x00000004;
+    pub const SSH_FXF_CREAT: u32 = 0x00000008;
+    pub const SSH_FXF_TRUNC: u32 = 0x00000010;
+    pub const SSH_FXF_EXCL: u32 = 0x00000020;
+
+    // 文件属性标志位
+    pub const SSH_FILEXFER_ATTR_SIZE: u32 = 0x00000001;
+    pub const SSH_FILEXFER_ATTR_UIDGID: u32 = 0x00000002;
+    pub const SSH_FILEXFER_ATTR_PERMISSIONS: u32 = 0x00000004;
+    pub const SSH_FILEXFER_ATTR_ACMODTIME: u32 = 0x00000008;
+    pub const SSH_FILEXFER_ATTR_EXTENDED: u32 = 0x80000000;
+
+    // 状态码
+    pub const SSH_FX_OK: u32 = 0;
+    pub const SSH_FX_EOF: u32 = 1;
+    pub const SSH_FX_NO_SUCH_FILE: u32 = 2;
+    pub const SSH_FX_PERMISSION_DENIED: u32 = 3;
+    pub const SSH_FX_FAILURE: u32 = 4;
+    pub const SSH_FX_BAD_MESSAGE: u32 = 5;
+    pub const SSH_FX_NO_CONNECTION: u32 = 6;
+    pub const SSH_FX_CONNECTION_LOST: u32 = 7;
+    pub const SSH_FX_OP_UNSUPPORTED: u32 = 8;
+
+    // 文件类型 (st_mode)
+    pub const S_IFMT: u32 = 0o170000;
+    pub const S_IFDIR: u32 = 0o040000;
+    pub const S_IFREG: u32 = 0o100000;
+    pub const S_IFLNK: u32 = 0o120000;
+}
+
+/// ssh-agent 协议相关
+/// draft-miller-ssh-agent
+pub(crate) mod agent {
+    /// 环境变量， agent 的 unix socket 地址
+    pub const SSH_AUTH_SOCK: &str = "SSH_AUTH_SOCK";
+
+    // 消息类型
+    pub const SSH_AGENT_FAILURE: u8 = 5;
+    pub const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+    pub const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+    pub const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+    pub const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+    // 签名标志
+    pub const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+    pub const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+    /// 单个消息的最大长度， 与 openssh 一致
+    pub const MAX_MSG_LEN: usize = 256 * 1024;
+}
+
 /// 一些默认大小
 #[allow(dead_code)]
 pub(crate) mod size {
@@ -85,6 +216,12 @@ pub(crate) mod size {
     pub const BUF_SIZE: usize = 32768;
     /// 默认客户端的窗口大小
     pub const LOCAL_WINDOW_SIZE: u32 = 2097152;
+    /// 解压后载荷的上限， 与 openssh 的最大包长一致
+    pub const MAX_PAYLOAD: usize = 256 * 1024;
+    /// 接收数据包的最大长度， 与 openssh 一致
+    pub const MAX_PACKET_LEN: usize = 256 * 1024;
+    /// keyboard-interactive 单次请求的最大提示数， 与 openssh 服务端一致
+    pub const MAX_PROMPTS: u32 = 100;
 }
 
 /// ssh 消息码
@@ -96,6 +233,7 @@ pub(crate) mod ssh_msg_code {
     pub const SSH_MSG_DEBUG: u8 = 4;
     pub const SSH_MSG_SERVICE_REQUEST: u8 = 5;
     pub const SSH_MSG_SERVICE_ACCEPT: u8 = 6;
+    pub const SSH_MSG_EXT_INFO: u8 = 7;
     pub const SSH_MSG_KEXINIT: u8 = 20;
     pub const SSH_MSG_NEWKEYS: u8 = 21;
     pub const SSH_MSG_KEXDH_INIT: u8 = 30;
@@ -103,7 +241,10 @@ pub(crate) mod ssh_msg_code {
     pub const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
     pub const SSH_MSG_USERAUTH_FAILURE: u8 = 51;
     pub const SSH_MSG_USERAUTH_SUCCESS: u8 = 52;
+    pub const SSH_MSG_USERAUTH_BANNER: u8 = 53;
     pub const SSH_MSG_USERAUTH_PK_OK: u8 = 60;
+    pub const SSH_MSG_USERAUTH_INFO_REQUEST: u8 = 60;
+    pub const SSH_MSG_USERAUTH_INFO_RESPONSE: u8 = 61;
     pub const SSH_MSG_GLOBAL_REQUEST: u8 = 80;
     pub const SSH_MSG_REQUEST_SUCCESS: u8 = 81;
     pub const SSH_MSG_REQUEST_FAILURE: u8 = 82;
@@ -136,6 +277,9 @@ pub(crate) mod ssh_msg_code {
     pub const SSH_DISCONNECT_NO_MORE_AUTH_METHODS_AVAILABLE: u8 = 14;
     pub const SSH_DISCONNECT_ILLEGAL_USER_NAME: u8 = 15;
 
+    // 扩展数据类型 SSH_MSG_CHANNEL_EXTENDED_DATA
+    pub const SSH_EXTENDED_DATA_STDERR: u32 = 1;
+
     // 通道连接失败码 SSH_MSG_CHANNEL_OPEN_FAILURE
     pub const SSH_OPEN_ADMINISTRATIVELY_PROHIBITED: u32 = 1;
     pub const SSH_OPEN_CONNECT_FAILED: u32 = 2;
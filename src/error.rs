@@ -5,6 +5,8 @@ use std::{
     sync::mpsc::{RecvError, SendError},
 };
 
+use crate::constant::{sftp, ssh_msg_code};
+
 pub type SshResult<I> = Result<I, SshError>;
 
 pub struct SshError {
@@ -54,6 +56,16 @@ pub enum SshErrorKind {
     SendError(String),
     RecvError(String),
     Timeout,
+    UnknownHostKey(String),
+    HostKeyMismatch(String),
+    RevokedHostKey(String),
+    InvalidHostCertificate(String),
+    SftpError(u32, String),
+    ChannelOpenFailure(u32, String),
+    AuthFailure {
+        attempted: Vec<String>,
+        allowed: Vec<String>,
+    },
 }
 
 impl fmt::Display for SshErrorKind {
@@ -64,6 +76,37 @@ impl fmt::Display for SshErrorKind {
             SshErrorKind::SendError(e) => write!(f, "{}", e),
             SshErrorKind::RecvError(e) => write!(f, "{}", e),
             SshErrorKind::Timeout => write!(f, "time out."),
+            SshErrorKind::UnknownHostKey(h) => write!(f, "host key of {} is unknown.", h),
+            SshErrorKind::HostKeyMismatch(h) => {
+                write!(
+                    f,
+                    "host key verification failed, the host key of {} has changed.",
+                    h
+                )
+            }
+            SshErrorKind::RevokedHostKey(h) => write!(f, "host key of {} is revoked.", h),
+            SshErrorKind::InvalidHostCertificate(h) => {
+                write!(f, "host certificate of {} is invalid.", h)
+            }
+            SshErrorKind::SftpError(code, msg) => write!(f, "sftp error {}: {}", code, msg),
+            SshErrorKind::AuthFailure { attempted, allowed } => write!(
+                f,
+                "user auth failure, attempted methods: [{}], allowed methods: [{}]",
+                attempted.join(", "),
+                allowed.join(", ")
+            ),
+            SshErrorKind::ChannelOpenFailure(code, description) => {
+                let reason = match *code {
+                    ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED => {
+                        "SSH_OPEN_ADMINISTRATIVELY_PROHIBITED"
+                    }
+                    ssh_msg_code::SSH_OPEN_CONNECT_FAILED => "SSH_OPEN_CONNECT_FAILED",
+                    ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE => "SSH_OPEN_UNKNOWN_CHANNEL_TYPE",
+                    ssh_msg_code::SSH_OPEN_RESOURCE_SHORTAGE => "SSH_OPEN_RESOURCE_SHORTAGE",
+                    _ => return write!(f, "{}", description),
+                };
+                write!(f, "{}: {}", reason, description)
+            }
         }
     }
 }
@@ -108,6 +151,15 @@ impl<T> From<SendError<T>> for SshError {
     }
 }
 
+#[cfg(feature = "async")]
+impl<T> From<tokio::sync::mpsc::error::SendError<T>> for SshError {
+    fn from(e: tokio::sync::mpsc::error::SendError<T>) -> Self {
+        Self {
+            inner: SshErrorKind::SendError(e.to_string()),
+        }
+    }
+}
+
 impl From<RecvError> for SshError {
     fn from(e: RecvError) -> Self {
         Self {
@@ -115,3 +167,21 @@ impl From<RecvError> for SshError {
         }
     }
 }
+
+impl From<SshError> for io::Error {
+    fn from(e: SshError) -> Self {
+        match e.inner {
+            SshErrorKind::IoError(ie) => ie,
+            SshErrorKind::Timeout => io::Error::new(io::ErrorKind::TimedOut, "time out."),
+            SshErrorKind::SftpError(code, msg) => {
+                let kind = match code {
+                    sftp::SSH_FX_NO_SUCH_FILE => io::ErrorKind::NotFound,
+                    sftp::SSH_FX_PERMISSION_DENIED => io::ErrorKind::PermissionDenied,
+                    _ => io::ErrorKind::Other,
+                };
+                io::Error::new(kind, msg)
+            }
+            x => io::Error::other(x.to_string()),
+        }
+    }
+}
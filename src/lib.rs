@@ -43,8 +43,15 @@ mod util;
 pub mod error;
 
 pub use channel::*;
+pub use config::agent::{AgentClient, AgentIdentity, AgentStream};
+pub use config::auth::AuthMethod;
+pub use config::interactive::{KeyboardInteractive, PassphraseProvider, PasswordPrompter, Prompt};
+pub use config::known_hosts::{fingerprint, HostKeyVerifier, KnownHosts};
 pub(crate) use error::SshError;
 pub use error::{SshErrorKind, SshResult};
+#[cfg(feature = "async")]
+pub use session::AsyncSession;
+pub(crate) use session::BackendStream;
 pub use session::{LocalSession, SessionBroker, SessionBuilder, SessionConnector};
 
 pub mod ssh {
@@ -1,17 +1,66 @@
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{Receiver, Sender};
 
 use super::Data;
+use crate::{channel::ExitSignal, error::SshResult};
 
 pub(crate) enum BackendRqst {
     OpenChannel(u32, Data, Sender<BackendResp>),
     Data(u32, Data),
     Command(u32, Data),
     CloseChannel(u32, Data),
+    GlobalRequest(Data, Sender<BackendResp>, Option<ForwardListener>),
+}
+
+/// the requests to the task of an [crate::AsyncSession]
+#[cfg(feature = "async")]
+pub(crate) enum AsyncRqst {
+    OpenChannel(u32, Data, tokio::sync::mpsc::UnboundedSender<BackendResp>),
+    Data(u32, Data),
+    Command(u32, Data),
+    CloseChannel(u32, Data),
 }
 
 pub(crate) enum BackendResp {
     Ok(u32),
     Fail(String),
+    OpenFailure(u32, String),
     Data(Data),
+    ExtendedData(Data),
+    ExitStatus(u32),
+    ExitSignal(ExitSignal),
     Close,
 }
+
+/// where the responses of a backend channel go
+pub(crate) trait RespSender {
+    fn send_resp(&self, resp: BackendResp) -> SshResult<()>;
+}
+
+impl RespSender for Sender<BackendResp> {
+    fn send_resp(&self, resp: BackendResp) -> SshResult<()> {
+        Ok(self.send(resp)?)
+    }
+}
+
+#[cfg(feature = "async")]
+impl RespSender for tokio::sync::mpsc::UnboundedSender<BackendResp> {
+    fn send_resp(&self, resp: BackendResp) -> SshResult<()> {
+        Ok(self.send(resp)?)
+    }
+}
+
+/// where to deliver the `forwarded-tcpip` channels of a remote forwarding,
+/// registered once the `tcpip-forward` request succeeds
+pub(crate) struct ForwardListener {
+    pub port: u32,
+    pub opens: Sender<ForwardedOpen>,
+}
+
+/// a `forwarded-tcpip` channel opened by the server
+pub(crate) struct ForwardedOpen {
+    pub client_channel_no: u32,
+    pub server_channel_no: u32,
+    pub rcv: Receiver<BackendResp>,
+    pub originator_address: String,
+    pub originator_port: u32,
+}
@@ -1,6 +1,6 @@
 use std::ops::{Deref, DerefMut};
 
-use crate::error::SshResult;
+use crate::error::{SshError, SshResult};
 
 use super::Packet;
 
@@ -77,6 +77,12 @@ impl Data {
         self
     }
 
+    // 64位无符号整型
+    pub fn put_u64(&mut self, v: u64) -> &mut Self {
+        self.0.extend(v.to_be_bytes());
+        self
+    }
+
     // 字符串型数据
     // 需要计算字符串长度
     pub fn put_str(&mut self, str: &str) -> &mut Self {
@@ -126,6 +132,12 @@ impl Data {
         u32::from_be_bytes(u32_buf.try_into().unwrap())
     }
 
+    // 获取64位无符号整型
+    pub fn get_u64(&mut self) -> u64 {
+        let u64_buf = self.0.drain(..8).collect::<Vec<u8>>();
+        u64::from_be_bytes(u64_buf.try_into().unwrap())
+    }
+
     // 获取字节数组
     pub fn get_u8s(&mut self) -> Vec<u8> {
         let len = self.get_u32() as usize;
@@ -133,6 +145,36 @@ impl Data {
         bytes
     }
 
+    // 以下方法用于解析对端发来的数据，长度不足时返回错误而不是 panic
+    pub fn try_get_u8(&mut self) -> SshResult<u8> {
+        self.ensure(1)?;
+        Ok(self.get_u8())
+    }
+
+    pub fn try_get_u32(&mut self) -> SshResult<u32> {
+        self.ensure(4)?;
+        Ok(self.get_u32())
+    }
+
+    pub fn try_get_u64(&mut self) -> SshResult<u64> {
+        self.ensure(8)?;
+        Ok(self.get_u64())
+    }
+
+    pub fn try_get_u8s(&mut self) -> SshResult<Vec<u8>> {
+        self.ensure(4)?;
+        let len = u32::from_be_bytes(self.0[..4].try_into().unwrap()) as usize;
+        self.ensure(4 + len)?;
+        Ok(self.get_u8s())
+    }
+
+    fn ensure(&self, len: usize) -> SshResult<()> {
+        if self.0.len() < len {
+            return Err(SshError::from("unexpected end of data."));
+        }
+        Ok(())
+    }
+
     pub fn into_inner(self) -> Vec<u8> {
         self.0
     }
@@ -136,8 +136,10 @@ impl<'a> SecPacket<'a> {
         S: Write,
     {
         let tm = self.client.get_timeout();
-        let payload_len = self.payload.len() as u32;
+        let payload = self.client.compress(&self.payload)?;
+        let payload_len = payload.len() as u32;
         let bsize = self.client.get_encryptor().bsize() as i32;
+        // the aead ciphers & the etm macs leave the packet length out of the alignment
         let pad_len = {
             let mut pad = (-((payload_len
                 + if self.client.get_encryptor().is_cp() {
@@ -156,12 +158,13 @@ impl<'a> SecPacket<'a> {
         let mut buf = vec![];
         buf.extend(packet_len.to_be_bytes());
         buf.extend([pad_len]);
-        buf.extend(self.payload.iter());
+        buf.extend(payload);
         buf.extend(vec![0; pad_len as usize]);
 
         let seq = self.client.get_seq().get_client();
 
         self.client.get_encryptor().encrypt(seq, &mut buf);
+        self.client.get_seq().sent(buf.len());
 
         write_with_timeout(stream, tm, &buf)
     }
@@ -186,7 +189,8 @@ impl<'a> SecPacket<'a> {
 
         // detect the total len
         let seq = client.get_seq().get_server();
-        let data_len = client.get_encryptor().data_len(seq, &first_block);
+        let data_len = client.get_encryptor().data_len(seq, &first_block)?;
+        client.get_seq().received(data_len);
 
         // read remain
         let mut data = Data::uninit_new(data_len);
@@ -201,7 +205,9 @@ impl<'a> SecPacket<'a> {
         let pad_len = data[4];
         let payload_len = pkt_len - pad_len as u32 - 1;
 
-        let payload = data[5..payload_len as usize + 5].into();
+        let payload = client
+            .decompress(&data[5..payload_len as usize + 5])?
+            .into();
 
         Ok(Self { payload, client })
     }
@@ -226,10 +232,15 @@ impl<'a> SecPacket<'a> {
         if read == 0 {
             return Ok(None);
         }
+        // a packet has arrived, wait for the rest of the block
+        if read < bsize {
+            read_with_timeout(stream, tm, &mut first_block[read..])?;
+        }
 
         // detect the total len
         let seq = client.get_seq().get_server();
-        let data_len = client.get_encryptor().data_len(seq, &first_block);
+        let data_len = client.get_encryptor().data_len(seq, &first_block)?;
+        client.get_seq().received(data_len);
 
         // read remain
         let mut data = Data::uninit_new(data_len);
@@ -244,7 +255,50 @@ impl<'a> SecPacket<'a> {
         let pad_len = data[4];
         let payload_len = pkt_len - pad_len as u32 - 1;
 
-        let payload = data[5..payload_len as usize + 5].into();
+        let payload = client
+            .decompress(&data[5..payload_len as usize + 5])?
+            .into();
+
+        Ok(Some(Self { payload, client }))
+    }
+
+    /// take a whole packet from the bytes received so far,
+    /// return `None` if more bytes are needed
+    ///
+    pub fn from_buf(buf: &mut Vec<u8>, client: &'a mut Client) -> SshResult<Option<Self>> {
+        let bsize = {
+            let bsize = client.get_encryptor().bsize();
+            if bsize > 8 {
+                bsize
+            } else {
+                8
+            }
+        };
+        if buf.len() < bsize {
+            return Ok(None);
+        }
+
+        // detect the total len, the sequence number is only taken with a whole packet
+        let seq = client.get_seq().peek_server();
+        let data_len = client.get_encryptor().data_len(seq, &buf[..bsize])?;
+        if buf.len() < data_len {
+            return Ok(None);
+        }
+        let seq = client.get_seq().get_server();
+        client.get_seq().received(data_len);
+        let mut data = buf.drain(..data_len).collect::<Vec<u8>>();
+
+        // decrypt all
+        let data = client.get_encryptor().decrypt(seq, &mut data)?;
+
+        // unpacking
+        let pkt_len = u32::from_be_bytes(data[0..4].try_into().unwrap());
+        let pad_len = data[4];
+        let payload_len = pkt_len - pad_len as u32 - 1;
+
+        let payload = client
+            .decompress(&data[5..payload_len as usize + 5])?
+            .into();
 
         Ok(Some(Self { payload, client }))
     }
@@ -4,17 +4,54 @@ use super::U32Iter;
 pub(crate) struct Sequence {
     client_sequence_num: U32Iter,
     server_sequence_num: U32Iter,
+    // the packets & bytes since the last key exchange
+    pub client_packets: u64,
+    pub server_packets: u64,
+    pub client_bytes: u64,
+    pub server_bytes: u64,
 }
 
 impl Sequence {
     pub fn get_client(&mut self) -> u32 {
+        self.client_packets += 1;
         self.client_sequence_num.next().unwrap()
     }
 
     pub fn get_server(&mut self) -> u32 {
+        self.server_packets += 1;
         self.server_sequence_num.next().unwrap()
     }
 
+    pub fn sent(&mut self, len: usize) {
+        self.client_bytes += len as u64;
+    }
+
+    pub fn received(&mut self, len: usize) {
+        self.server_bytes += len as u64;
+    }
+
+    // strict kex, the numbers restart from 0 after NEWKEYS
+    pub fn reset_client(&mut self) {
+        self.client_sequence_num = U32Iter::default();
+    }
+
+    pub fn reset_server(&mut self) {
+        self.server_sequence_num = U32Iter::default();
+    }
+
+    // a new key is in use
+    pub fn reset_counters(&mut self) {
+        self.client_packets = 0;
+        self.server_packets = 0;
+        self.client_bytes = 0;
+        self.server_bytes = 0;
+    }
+
+    // the number of the next packet from the server, w/o consuming it
+    pub fn peek_server(&self) -> u32 {
+        self.server_sequence_num.clone().next().unwrap()
+    }
+
     pub fn new() -> Self {
         Self {
             ..Default::default()
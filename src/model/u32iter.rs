@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub(crate) struct U32Iter {
     num: u32,
 }
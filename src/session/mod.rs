@@ -1,22 +1,39 @@
 // pub(crate) use session_inner::SessionInner;
+#[cfg(feature = "async")]
+mod session_async;
 mod session_broker;
 mod session_local;
 
+#[cfg(feature = "async")]
+pub use session_async::AsyncSession;
+pub(crate) use session_broker::BackendStream;
 pub use session_broker::SessionBroker;
 pub use session_local::LocalSession;
 
 use std::{
     io::{Read, Write},
-    net::{TcpStream, ToSocketAddrs},
-    path::Path,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
 };
 
 use crate::{
     algorithm::{Compress, Digest, Enc, Kex, Mac, PubKey},
     client::Client,
-    config::{algorithm::AlgList, version::SshVersion, Config},
-    error::SshResult,
-    model::{Packet, SecPacket},
+    config::{
+        agent::AgentClient,
+        algorithm::AlgList,
+        auth::AuthMethod,
+        interactive::{KeyboardInteractive, PassphraseProvider},
+        known_hosts::{HostKeyVerifier, KnownHosts},
+        ssh_config::{self, HostConfig},
+        version::SshVersion,
+        Config,
+    },
+    constant::ssh_msg_code,
+    error::{SshError, SshResult},
+    model::{Data, Packet, SecPacket},
+    util, DirectTcpipBroker,
 };
 
 enum SessionState<S>
@@ -71,9 +88,23 @@ where
                 // before auth,
                 // we should have a key exchange at first
                 let mut digest = Digest::new();
-                let server_algs = SecPacket::from_stream(&mut stream, &mut client)?;
+                let mut skipped = 0;
+                let server_algs = loop {
+                    let pkt = SecPacket::from_stream(&mut stream, &mut client)?;
+                    if pkt.get_inner()[0] == ssh_msg_code::SSH_MSG_KEXINIT {
+                        break pkt;
+                    }
+                    log::debug!("ignore message {} before key exchange.", pkt.get_inner()[0]);
+                    skipped += 1;
+                };
                 digest.hash_ctx.set_i_s(server_algs.get_inner());
                 let server_algs = AlgList::unpack(server_algs)?;
+                // the algorithm list shall be the first packet of a strict exchange
+                if server_algs.kex_strict && skipped > 0 {
+                    return Err(SshError::from(
+                        "unexpected message before strict key exchange.",
+                    ));
+                }
                 client.key_agreement(&mut stream, server_algs, &mut digest)?;
                 client.do_auth(&mut stream, &mut digest)?;
                 Ok(Self {
@@ -120,9 +151,18 @@ where
     }
 }
 
+// a jump host and how to log in it
+struct JumpHost {
+    host: String,
+    port: u16,
+    builder: SessionBuilder,
+}
+
 #[derive(Default)]
 pub struct SessionBuilder {
     config: Config,
+    jump_hosts: Vec<JumpHost>,
+    key_error: Option<SshError>,
 }
 
 impl SessionBuilder {
@@ -135,6 +175,7 @@ impl SessionBuilder {
     pub fn disable_default() -> Self {
         Self {
             config: Config::disable_default(),
+            ..Default::default()
         }
     }
 
@@ -157,34 +198,351 @@ impl SessionBuilder {
         self
     }
 
+    /// add a private key for public key authentication
+    ///
+    /// can be called multiple times, the keys are tried in order
+    ///
+    /// an invalid key makes the connection fail
+    ///
     pub fn private_key<K>(mut self, private_key: K) -> Self
     where
         K: ToString,
     {
-        match self.config.auth.private_key(private_key) {
-            Ok(_) => (),
-            Err(e) => log::error!(
-                "Parse private key from string: {}, will fallback to password authentication",
-                e
-            ),
-        }
-        self
+        let result = self.config.auth.private_key(private_key, None);
+        self.record_key_error(result)
     }
 
+    /// add an encrypted private key with its passphrase
+    ///
+    /// the key is only decrypted when it is used
+    ///
+    pub fn private_key_with_passphrase<K>(mut self, private_key: K, passphrase: &str) -> Self
+    where
+        K: ToString,
+    {
+        let result = self.config.auth.private_key(private_key, Some(passphrase));
+        self.record_key_error(result)
+    }
+
+    /// add a private key file for public key authentication
+    ///
+    /// can be called multiple times, the keys are tried in order
+    ///
+    /// an invalid key makes the connection fail
+    ///
     pub fn private_key_path<P>(mut self, key_path: P) -> Self
     where
         P: AsRef<Path>,
     {
-        match self.config.auth.private_key_path(key_path) {
-            Ok(_) => (),
-            Err(e) => log::error!(
-                "Parse private key from file: {}, will fallback to password authentication",
-                e
-            ),
+        let result = self.config.auth.private_key_path(key_path, None);
+        self.record_key_error(result)
+    }
+
+    /// add an encrypted private key file with its passphrase
+    ///
+    /// the key is only decrypted when it is used
+    ///
+    pub fn private_key_path_with_passphrase<P>(mut self, key_path: P, passphrase: &str) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let result = self
+            .config
+            .auth
+            .private_key_path(key_path, Some(passphrase));
+        self.record_key_error(result)
+    }
+
+    /// attach an OpenSSH certificate to the last added private key,
+    /// `cert` is the content of the `*-cert.pub` file
+    ///
+    /// the certificate is sent instead of the plain public key in the authentication
+    ///
+    pub fn certificate<C>(mut self, cert: C) -> Self
+    where
+        C: ToString,
+    {
+        let result = self.config.auth.certificate(cert);
+        self.record_key_error(result)
+    }
+
+    /// attach an OpenSSH certificate file to the last added private key
+    ///
+    pub fn certificate_path<P>(mut self, cert_path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        let result = self.config.auth.certificate_path(cert_path);
+        self.record_key_error(result)
+    }
+
+    /// ask `provider` for the passphrase of the encrypted keys
+    /// added without one
+    ///
+    /// it is called at most once for each key, when the key is used to sign
+    ///
+    pub fn passphrase_provider<P>(mut self, provider: P) -> Self
+    where
+        P: PassphraseProvider + 'static,
+    {
+        self.config.auth.passphrase_provider = Some(Arc::new(provider));
+        self
+    }
+
+    // the first invalid key is reported when connecting
+    fn record_key_error(mut self, result: SshResult<()>) -> Self {
+        if let Err(e) = result {
+            log::error!("Parse private key: {}", e);
+            if self.key_error.is_none() {
+                self.key_error = Some(e);
+            }
         }
         self
     }
 
+    /// the authentication methods to try, in order
+    ///
+    /// only the methods allowed by the server are tried,
+    /// and multi-factor chains (e.g. `publickey,password`) are followed on partial success
+    ///
+    /// default is `publickey`, `keyboard-interactive`, `password`, the same as OpenSSH
+    ///
+    pub fn preferred_auth_methods(mut self, methods: &[AuthMethod]) -> Self {
+        self.config.auth.preferred_methods = methods.to_vec();
+        self
+    }
+
+    /// enable `keyboard-interactive` authentication,
+    /// the prompts sent by the server are answered by `handler`
+    ///
+    /// use [crate::PasswordPrompter] if the server only asks for the password
+    ///
+    pub fn keyboard_interactive<H>(mut self, handler: H) -> Self
+    where
+        H: KeyboardInteractive + 'static,
+    {
+        self.config.auth.keyboard_interactive = Some(Arc::new(handler));
+        self
+    }
+
+    /// authenticate with the keys held by an ssh agent,
+    /// they are tried after the keys set by `private_key` / `private_key_path`
+    ///
+    /// e.g. `AgentClient::from_env()` connects to `$SSH_AUTH_SOCK`
+    ///
+    pub fn agent(mut self, agent: AgentClient) -> Self {
+        self.config.auth.agent = Some(Arc::new(Mutex::new(agent)));
+        self
+    }
+
+    /// forward the agent listening on the unix socket `sock` to the server,
+    /// which is `ssh -A`
+    ///
+    /// `auth-agent-req@openssh.com` is sent on every exec & shell channel,
+    /// and each agent connection made on the server is relayed to `sock`
+    ///
+    /// e.g. `forward_agent(std::env::var("SSH_AUTH_SOCK").unwrap())`
+    ///
+    pub fn forward_agent<P>(mut self, sock: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.config.agent_forward = Some(sock.as_ref().to_path_buf());
+        self
+    }
+
+    /// send `keepalive@openssh.com` if nothing is received from the server for `interval` seconds,
+    /// the session is closed when 3 of them are not answered,
+    /// which is `ServerAliveInterval` of OpenSSH
+    ///
+    /// only works with [SessionConnector::run_backend], set 0 to disable
+    ///
+    pub fn server_alive_interval(mut self, interval: u64) -> Self {
+        self.config.server_alive_interval = interval;
+        self
+    }
+
+    /// exchange new keys after `bytes` are sent or received,
+    /// or after `interval` seconds, which is `RekeyLimit` of OpenSSH
+    ///
+    /// set 0 for the default, which is the limit of the cipher by RFC 4344 w/o a time limit,
+    /// and a lower `bytes` than the cipher one is used,
+    /// not supported by the async session yet
+    ///
+    pub fn rekey_limit(mut self, bytes: u64, interval: u64) -> Self {
+        self.config.rekey_limit = bytes;
+        self.config.rekey_interval = interval;
+        self
+    }
+
+    /// compress the payloads w/ zlib, which is `Compression` of OpenSSH
+    ///
+    /// `zlib@openssh.com` is preferred, which starts after the user auth,
+    /// disabled by default
+    ///
+    pub fn compression(mut self, enable: bool) -> Self {
+        let algs = if enable {
+            vec![Compress::ZlibOpenssh, Compress::Zlib, Compress::None]
+        } else {
+            vec![Compress::None]
+        };
+        self.config.algs.c_compress = algs.clone().into();
+        self.config.algs.s_compress = algs.into();
+        self
+    }
+
+    /// the host name used to verify the server host key
+    ///
+    /// by default it is the host passed to [SessionBuilder::connect_host],
+    /// or the ip address of the connected server if there isn't
+    ///
+    pub fn host_name(mut self, host_name: &str) -> Self {
+        self.config.host = host_name.to_owned();
+        self
+    }
+
+    /// verify the server host key with a [HostKeyVerifier]
+    ///
+    /// the host key will not be verified if no verifier is set
+    ///
+    pub fn host_key_verifier<V>(mut self, verifier: V) -> Self
+    where
+        V: HostKeyVerifier + 'static,
+    {
+        self.config.host_key_verifier = Some(Arc::new(verifier));
+        self
+    }
+
+    /// verify the server host key against an OpenSSH `known_hosts` file
+    ///
+    /// a shortcut of `host_key_verifier(KnownHosts::new(path))`
+    ///
+    pub fn known_hosts_path<P>(self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.host_key_verifier(KnownHosts::new(path))
+    }
+
+    /// create a builder of `host_alias` from `~/.ssh/config`
+    ///
+    /// see [SessionBuilder::from_ssh_config_path]
+    ///
+    pub fn from_ssh_config(host_alias: &str) -> SshResult<Self> {
+        match util::home_dir() {
+            Some(home) => Self::from_ssh_config_path(home.join(".ssh").join("config"), host_alias),
+            None => Err(SshError::from("cannot find the home directory.")),
+        }
+    }
+
+    /// create a builder of `host_alias` from an OpenSSH `ssh_config` file
+    ///
+    /// the `Host` & `Match` blocks are applied as OpenSSH does, `Include` is followed,
+    /// and these options are used:
+    ///
+    /// `HostName`, `Port`, `User`, `IdentityFile`, `ProxyJump`,
+    /// `KexAlgorithms`, `Ciphers`, `MACs`, `HostKeyAlgorithms`,
+    /// `ConnectTimeout`, `ServerAliveInterval` and `UserKnownHostsFile`
+    ///
+    /// connect to [SessionBuilder::destination] after that,
+    /// by [SessionBuilder::connect_with_jumps] if `ProxyJump` is set
+    ///
+    pub fn from_ssh_config_path<P>(path: P, host_alias: &str) -> SshResult<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let host_config = HostConfig::load(path.as_ref(), host_alias)?;
+        let mut builder = Self::new().ssh_config(&host_config);
+
+        let jumps = match host_config.proxy_jump {
+            Some(ref jumps) if jumps != "none" => jumps.split(',').collect(),
+            _ => vec![],
+        };
+        for jump in jumps {
+            let (user, host, port) = ssh_config::parse_jump(jump)?;
+            // the `ProxyJump` of the jump hosts themselves are not followed
+            let jump_config = HostConfig::load(path.as_ref(), host)?;
+            let mut jump_builder = Self::new().ssh_config(&jump_config);
+            if let Some(user) = user {
+                jump_builder = jump_builder.username(user);
+            }
+            let host = jump_builder.config.host.clone();
+            let port = port.unwrap_or(jump_builder.config.port);
+            builder = builder.jump_host(&host, port, jump_builder);
+        }
+        Ok(builder)
+    }
+
+    // apply the options of one host
+    fn ssh_config(mut self, host_config: &HostConfig) -> Self {
+        self.config.host = host_config.host();
+        self.config.port = host_config.port.unwrap_or(22);
+        self = self.username(&host_config.user());
+
+        for file in host_config.identity_files.iter() {
+            let path = host_config.expand_path(file);
+            if !path.is_file() {
+                log::debug!("identity file {} does not exist.", path.display());
+                continue;
+            }
+            // keys that cannot be used are skipped as OpenSSH does
+            if let Err(e) = self.config.auth.private_key_path(&path, None) {
+                log::warn!("skip the identity file: {}", e);
+                continue;
+            }
+            // the certificate is loaded along with the key
+            let cert = PathBuf::from(format!("{}-cert.pub", path.display()));
+            if cert.is_file() {
+                if let Err(e) = self.config.auth.certificate_path(&cert) {
+                    log::warn!("skip the certificate: {}", e);
+                }
+            }
+        }
+
+        let algs = &mut self.config.algs;
+        if let Some(ref v) = host_config.kex_algorithms {
+            ssh_config::apply_algorithms(&mut *algs.key_exchange, v);
+        }
+        if let Some(ref v) = host_config.host_key_algorithms {
+            ssh_config::apply_algorithms(&mut *algs.public_key, v);
+        }
+        if let Some(ref v) = host_config.ciphers {
+            ssh_config::apply_algorithms(&mut *algs.c_encryption, v);
+            ssh_config::apply_algorithms(&mut *algs.s_encryption, v);
+        }
+        if let Some(ref v) = host_config.macs {
+            ssh_config::apply_algorithms(&mut *algs.c_mac, v);
+            ssh_config::apply_algorithms(&mut *algs.s_mac, v);
+        }
+
+        if let Some(timeout) = host_config.connect_timeout {
+            self.config.timeout = timeout as u128 * 1000;
+        }
+        if let Some(interval) = host_config.server_alive_interval {
+            self.config.server_alive_interval = interval;
+        }
+        if let Some((bytes, interval)) = host_config.rekey_limit {
+            self = self.rekey_limit(bytes, interval);
+        }
+        if let Some(enable) = host_config.compression {
+            self = self.compression(enable);
+        }
+        match host_config.user_known_hosts_file {
+            Some(ref file) if file != "none" => {
+                let path = host_config.expand_path(file);
+                self.known_hosts_path(path)
+            }
+            _ => self,
+        }
+    }
+
+    /// the host & port to connect,
+    /// which are `HostName` & `Port` for a builder created from the ssh config
+    ///
+    pub fn destination(&self) -> (String, u16) {
+        (self.config.host.clone(), self.config.port)
+    }
+
     pub fn add_kex_algorithms(mut self, alg: Kex) -> Self {
         self.config.algs.key_exchange.push(alg);
         self
@@ -241,29 +599,200 @@ impl SessionBuilder {
         self
     }
 
-    pub fn connect<A>(self, addr: A) -> SshResult<SessionConnector<TcpStream>>
+    /// connect to `addr`, e.g. `"127.0.0.1:22"`
+    ///
+    /// the host key is verified by the ip address connected,
+    /// set [SessionBuilder::host_name] or use [SessionBuilder::connect_host]
+    /// to verify it by the host name
+    ///
+    pub fn connect<A>(mut self, addr: A) -> SshResult<SessionConnector<TcpStream>>
     where
         A: ToSocketAddrs,
     {
+        if let Some(e) = self.key_error.take() {
+            return Err(e);
+        }
         // connect tcp by default
         let tcp = TcpStream::connect(addr)?;
+        if let Ok(peer) = tcp.peer_addr() {
+            if self.config.host.is_empty() {
+                self.config.host = peer.ip().to_string();
+            }
+            self.config.host_ip = Some(peer.ip());
+            self.config.port = peer.port();
+        }
         // default nonblocking
         tcp.set_nonblocking(true).unwrap();
         self.connect_bio(tcp)
     }
 
+    /// connect to `host:port`
+    ///
+    /// the host key is verified by `host`,
+    /// and by the ip address connected as well
+    ///
+    pub fn connect_host(mut self, host: &str, port: u16) -> SshResult<SessionConnector<TcpStream>> {
+        if self.config.host.is_empty() {
+            self.config.host = host.to_string();
+        }
+        self.connect((host, port))
+    }
+
+    /// connect the target through a jump host, which is `ssh -J`
+    ///
+    /// `builder` holds the username, keys and so on to log in the jump host,
+    /// call it multiple times for a chain of jump hosts (`ProxyJump a,b,c`),
+    /// the first added one is connected first
+    ///
+    /// then call [SessionBuilder::connect_with_jumps] to connect the target
+    ///
+    pub fn jump_host(mut self, host: &str, port: u16, builder: SessionBuilder) -> Self {
+        self.jump_hosts.push(JumpHost {
+            host: host.to_string(),
+            port,
+            builder,
+        });
+        self
+    }
+
+    /// connect to `host:port` through the jump hosts,
+    /// each hop is tunneled by a `direct-tcpip` channel on the previous one,
+    /// so the host names are resolved by the jump hosts
+    ///
+    /// the sessions of the jump hosts are closed along with the returned one
+    ///
+    pub fn connect_with_jumps(
+        mut self,
+        host: &str,
+        port: u16,
+    ) -> SshResult<SessionConnector<DirectTcpipBroker>> {
+        let mut jumps = std::mem::take(&mut self.jump_hosts).into_iter();
+        let first = jumps
+            .next()
+            .ok_or_else(|| SshError::from("no jump host is configured."))?;
+        log::info!("connect jump host {}:{}.", first.host, first.port);
+        let mut session = first
+            .builder
+            .connect_host(&first.host, first.port)?
+            .run_backend();
+
+        for jump in jumps {
+            log::info!("connect jump host {}:{}.", jump.host, jump.port);
+            let stream = open_tunnel(&mut session, &jump.host, jump.port)?;
+            // the previous session lives as long as the tunnel
+            session = jump
+                .builder
+                .target(&jump.host, jump.port)
+                .connect_bio(stream)?
+                .run_backend();
+        }
+
+        let stream = open_tunnel(&mut session, host, port)?;
+        self.target(host, port).connect_bio(stream)
+    }
+
+    // the name & port used to verify the host key
+    fn target(mut self, host: &str, port: u16) -> Self {
+        if self.config.host.is_empty() {
+            self.config.host = host.to_string();
+        }
+        self.config.port = port;
+        self
+    }
+
     /// connect to target server w/ a bio object
     ///
     /// which requires to implement `std::io::{Read, Write}`
     ///
+    /// set [SessionBuilder::host_name] if the host key needs to be verified
+    ///
     pub fn connect_bio<S>(mut self, stream: S) -> SshResult<SessionConnector<S>>
     where
         S: Read + Write,
     {
-        self.config.tune_alglist_on_private_key();
+        if let Some(e) = self.key_error.take() {
+            return Err(e);
+        }
+        self.config.tune_alglist_on_host_key_verifier();
         SessionConnector {
             inner: SessionState::Init(self.config, stream),
         }
         .connect()
     }
 }
+
+#[cfg(feature = "async")]
+impl SessionBuilder {
+    /// connect to target server w/ an async stream
+    /// and run the session as a task of the current tokio runtime
+    ///
+    /// which requires to implement `tokio::io::{AsyncRead, AsyncWrite}`
+    ///
+    /// the handshake is done on the blocking threads of tokio,
+    /// set [SessionBuilder::host_name] if the host key needs to be verified
+    ///
+    pub async fn connect_async<S>(self, stream: S) -> SshResult<AsyncSession>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static,
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let handle = tokio::runtime::Handle::current();
+        let timeout = self.config.timeout;
+        let connector = tokio::task::spawn_blocking(move || {
+            self.connect_bio(session_async::BlockingIo::new(stream, handle, timeout))
+        })
+        .await
+        .map_err(|e| SshError::from(e.to_string()))??;
+
+        if let SessionState::Connected(client, stream) = connector.inner {
+            let mut stream = stream.into_inner();
+            stream.flush().await?;
+            Ok(AsyncSession::new(client, stream))
+        } else {
+            unreachable!("Why you here?")
+        }
+    }
+}
+
+/*
+    byte SSH_MSG_CHANNEL_OPEN_FAILURE
+    uint32 recipient channel
+    uint32 reason code
+    string description，ISO-10646 UTF-8 编码[RFC3629]
+    string language tag，[RFC3066]
+*/
+// the recipient channel shall be already consumed,
+// return the reason code & the description
+pub(crate) fn channel_open_failure(data: &mut Data) -> (u32, String) {
+    // 失败原因码
+    let code = data.get_u32();
+    // 消息详情 默认utf-8编码
+    let description = String::from_utf8(data.get_u8s()).unwrap_or_else(|_| String::from("error"));
+    // language tag 暂不处理， 应该是 en-US
+    data.get_u8s();
+    (code, description)
+}
+
+/*
+    string    host to connect
+    uint32    port to connect
+    string    originator IP address
+    uint32    originator port
+*/
+pub(crate) fn direct_tcpip_data(host: &str, port: u16, originator: SocketAddr) -> Data {
+    let mut data = Data::new();
+    data.put_str(host)
+        .put_u32(port as u32)
+        .put_str(&originator.ip().to_string())
+        .put_u32(originator.port() as u32);
+    data
+}
+
+// like `ssh -W`, there isn't a real originator
+fn open_tunnel(session: &mut SessionBroker, host: &str, port: u16) -> SshResult<DirectTcpipBroker> {
+    let originator = SocketAddr::from(([127, 0, 0, 1], 0));
+    let mut stream = session.open_direct_tcpip(host, port, originator)?;
+    stream.set_nonblocking(true);
+    Ok(stream)
+}
@@ -0,0 +1,515 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    runtime::Handle,
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+};
+
+use crate::{
+    algorithm::Digest,
+    channel::{AsyncChannel, AsyncExec, AsyncScp, AsyncShell, BackendChannel},
+    client::Client,
+    config::algorithm::AlgList,
+    constant::{size, ssh_msg_code, ssh_str},
+    error::{SshError, SshErrorKind, SshResult},
+    model::{AsyncRqst, BackendResp, Data, Packet, SecPacket, U32Iter},
+    util,
+};
+
+pub struct AsyncSession {
+    channel_num: U32Iter,
+    snd: UnboundedSender<AsyncRqst>,
+}
+
+impl AsyncSession {
+    pub(crate) fn new<S>(client: Client, stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (rqst_snd, rqst_rcv) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            if let Err(e) = client_loop(client, stream, rqst_rcv).await {
+                log::error!("Error {} occurred when running async task", e)
+            }
+        });
+        Self {
+            channel_num: U32Iter::default(),
+            snd: rqst_snd,
+        }
+    }
+
+    /// close the async session and consume it
+    ///
+    pub fn close(self) {
+        log::info!("Client close");
+        drop(self)
+    }
+
+    /// open an [AsyncExec] channel which can excute commands
+    ///
+    pub async fn open_exec(&mut self) -> SshResult<AsyncExec> {
+        let channel = self.open_channel().await?;
+        channel.exec()
+    }
+
+    /// open an [AsyncScp] channel which can download/upload files/directories
+    ///
+    pub async fn open_scp(&mut self) -> SshResult<AsyncScp> {
+        let channel = self.open_channel().await?;
+        channel.scp()
+    }
+
+    /// open an [AsyncShell] channel which can be used as a pseudo terminal (AKA PTY)
+    ///
+    pub async fn open_shell(&mut self) -> SshResult<AsyncShell> {
+        let channel = self.open_channel().await?;
+        channel.shell().await
+    }
+
+    /// open a raw channel
+    ///
+    /// need call `.exec()`, `.shell()`, `.scp()` and so on to convert it to a specific channel
+    ///
+    pub async fn open_channel(&mut self) -> SshResult<AsyncChannel> {
+        let (resp_send, mut resp_recv) = mpsc::unbounded_channel();
+        let client_id = self.channel_num.next().unwrap();
+
+        // open channel request
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN)
+            .put_str(ssh_str::SESSION)
+            .put_u32(client_id)
+            .put_u32(size::LOCAL_WINDOW_SIZE)
+            .put_u32(size::BUF_SIZE as u32);
+
+        self.snd
+            .send(AsyncRqst::OpenChannel(client_id, data, resp_send))?;
+
+        // get the response
+        match resp_recv.recv().await {
+            Some(BackendResp::Ok(server_id)) => Ok(AsyncChannel::new(
+                client_id,
+                server_id,
+                resp_recv,
+                self.snd.clone(),
+            )),
+            Some(BackendResp::OpenFailure(code, description)) => {
+                Err(SshErrorKind::ChannelOpenFailure(code, description).into())
+            }
+            Some(_) => unreachable!(),
+            None => Err(SshError::from("the session is closed.")),
+        }
+    }
+}
+
+/// Run the blocking handshake over an async stream,
+/// only to be used on the blocking threads of tokio
+///
+pub(super) struct BlockingIo<S> {
+    stream: S,
+    handle: Handle,
+    timeout: Option<Duration>,
+}
+
+impl<S> BlockingIo<S> {
+    pub fn new(stream: S, handle: Handle, timeout: u128) -> Self {
+        Self {
+            stream,
+            handle,
+            timeout: match timeout {
+                0 => None,
+                ms => Some(Duration::from_millis(ms as u64)),
+            },
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S> Read for BlockingIo<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stream.read(buf);
+        let n = match self.timeout {
+            Some(tm) => self
+                .handle
+                .block_on(async { tokio::time::timeout(tm, read).await })
+                .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??,
+            None => self.handle.block_on(read)?,
+        };
+        // the handshake keeps reading until it gets enough,
+        // so the end of the stream is an error here
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(n)
+    }
+}
+
+impl<S> Write for BlockingIo<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.block_on(self.stream.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.block_on(self.stream.flush())
+    }
+}
+
+// the bytes received before a key exchange are read first
+struct KexIo<S> {
+    io: BlockingIo<S>,
+    inbuf: Vec<u8>,
+}
+
+impl<S> Read for KexIo<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.inbuf.is_empty() {
+            return self.io.read(buf);
+        }
+        let len = buf.len().min(self.inbuf.len());
+        buf[..len].copy_from_slice(&self.inbuf[..len]);
+        self.inbuf.drain(..len);
+        Ok(len)
+    }
+}
+
+impl<S> Write for KexIo<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+// the key exchange reads the stream by itself,
+// so it is run on a blocking thread with the stream moved in,
+// started by the server if its algorithm list is given, otherwise by us
+async fn exchange_keys<S>(
+    mut client: Client,
+    stream: S,
+    inbuf: Vec<u8>,
+    kexinit: Option<Data>,
+) -> SshResult<(Client, S, Vec<u8>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let handle = Handle::current();
+    tokio::task::spawn_blocking(move || {
+        let mut kex_io = KexIo {
+            io: BlockingIo::new(stream, handle, 0),
+            inbuf,
+        };
+        match kexinit {
+            Some(data) => {
+                let mut digest = Digest::new();
+                digest.hash_ctx.set_i_s(&data);
+                let server_algs = AlgList::unpack((data, &mut client).into())?;
+                client.key_agreement(&mut kex_io, server_algs, &mut digest)?;
+            }
+            None => client.rekey(&mut kex_io)?,
+        }
+        Ok((client, kex_io.io.into_inner(), kex_io.inbuf))
+    })
+    .await
+    .map_err(|e| SshError::from(e.to_string()))?
+}
+
+// the packets from the channels are packed into `out`,
+// which is written to the stream all at once
+async fn client_loop<S>(
+    mut client: Client,
+    mut stream: S,
+    mut rcv: UnboundedReceiver<AsyncRqst>,
+) -> SshResult<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let mut channels = HashMap::<u32, BackendChannel<UnboundedSender<BackendResp>>>::new();
+    let mut pendings = HashMap::<u32, UnboundedSender<BackendResp>>::new();
+    let mut inbuf = vec![];
+    let mut out = vec![];
+    let mut chunk = [0u8; size::BUF_SIZE];
+    // the algorithm list of a key exchange started by the server
+    let mut kexinit = None;
+    client.set_timeout(0);
+    loop {
+        if kexinit.is_some() || client.need_rekey() {
+            if !out.is_empty() {
+                stream.write_all(&out).await?;
+                stream.flush().await?;
+                out.clear();
+            }
+            (client, stream, inbuf) = exchange_keys(client, stream, inbuf, kexinit.take()).await?;
+        }
+
+        // the messages put aside by the key exchange come first,
+        // then the packets already received
+        loop {
+            let mut data = match client.take_deferred() {
+                Some(data) => data,
+                None => match SecPacket::from_buf(&mut inbuf, &mut client)? {
+                    Some(pkt) => Data::unpack(pkt)?,
+                    None => break,
+                },
+            };
+            if data.first() == Some(&ssh_msg_code::SSH_MSG_KEXINIT) {
+                kexinit = Some(data);
+                break;
+            }
+            let message_code = data.get_u8();
+            handle_message(
+                message_code,
+                data,
+                &mut client,
+                &mut out,
+                &mut channels,
+                &mut pendings,
+            )?;
+        }
+        if kexinit.is_some() {
+            continue;
+        }
+        if !out.is_empty() {
+            stream.write_all(&out).await?;
+            stream.flush().await?;
+            out.clear();
+        }
+
+        let rekey_after = client.rekey_after();
+        tokio::select! {
+            rqst = rcv.recv() => {
+                let rqst = match rqst {
+                    Some(rqst) => rqst,
+                    None => {
+                        log::info!("Session async task Closed");
+                        return Ok(());
+                    }
+                };
+                match rqst {
+                    AsyncRqst::OpenChannel(id, data, sender) => {
+                        log::info!("try open channel {}.", id);
+
+                        data.pack(&mut client).write_stream(&mut out)?;
+
+                        // add to pending open list
+                        pendings.insert(id, sender);
+                    }
+                    AsyncRqst::Data(id, data) => {
+                        if let Some(channel) = channels.get_mut(&id) {
+                            log::trace!("Channel {} send {} data", id, data.len());
+                            channel.send_data(data, &mut client, &mut out)?;
+                        } else {
+                            log::debug!("Channel {} is closed, drop {} data", id, data.len());
+                        }
+                    }
+                    AsyncRqst::Command(id, data) => {
+                        if let Some(channel) = channels.get_mut(&id) {
+                            log::trace!("Channel {} send control data", id);
+                            channel.send(data, &mut client, &mut out)?;
+                        } else {
+                            log::debug!("Channel {} is closed, drop control data", id);
+                        }
+                    }
+                    AsyncRqst::CloseChannel(id, data) => {
+                        log::info!("try close channel {}.", id);
+
+                        if let Some(channel) = channels.get_mut(&id) {
+                            channel.local_close(data, &mut client, &mut out)?;
+                            if channel.is_close() {
+                                channels.remove(&id);
+                            }
+                        }
+                    }
+                }
+            }
+            read = stream.read(&mut chunk) => {
+                let read = read?;
+                if read == 0 {
+                    log::info!("Session closed by the server");
+                    return Ok(());
+                }
+                // handled at the beginning of the next round
+                inbuf.extend_from_slice(&chunk[..read]);
+            }
+            // the time limit of the current key
+            _ = tokio::time::sleep(rekey_after.unwrap_or_default()), if rekey_after.is_some() => {}
+        }
+
+        if !out.is_empty() {
+            stream.write_all(&out).await?;
+            stream.flush().await?;
+            out.clear();
+        }
+    }
+}
+
+fn handle_message(
+    message_code: u8,
+    mut data: Data,
+    client: &mut Client,
+    out: &mut Vec<u8>,
+    channels: &mut HashMap<u32, BackendChannel<UnboundedSender<BackendResp>>>,
+    pendings: &mut HashMap<u32, UnboundedSender<BackendResp>>,
+) -> SshResult<()> {
+    match message_code {
+        // Successfully open a channel
+        ssh_msg_code::SSH_MSG_CHANNEL_OPEN_CONFIRMATION => {
+            let client_channel_no = data.get_u32();
+            let server_channel_no = data.get_u32();
+            let remote_window_size = data.get_u32();
+            // remote packet size, currently don't need it
+            data.get_u32();
+
+            // remove from pending open list
+            let sender = match pendings.remove(&client_channel_no) {
+                Some(sender) => sender,
+                None => {
+                    log::warn!("Channel {} confirmed without opening", client_channel_no);
+                    return Ok(());
+                }
+            };
+
+            // add to opened list
+            let channel = BackendChannel::new(
+                server_channel_no,
+                client_channel_no,
+                remote_window_size,
+                sender,
+                client,
+                out,
+            )?;
+            channels.insert(client_channel_no, channel);
+        }
+        // Fail to open a channel
+        ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE => {
+            //  client channel number
+            let id = data.get_u32();
+
+            let (code, description) = super::channel_open_failure(&mut data);
+            match pendings.remove(&id) {
+                // the opener may have given up
+                Some(sender) => {
+                    let _ = sender.send(BackendResp::OpenFailure(code, description));
+                }
+                None => log::warn!("Channel {} failed without opening", id),
+            }
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_DATA => {
+            let id = data.get_u32();
+            log::trace!("Channel {} get {} data", id, data.len());
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.recv(data, client, out)?;
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_EXTENDED_DATA => {
+            let id = data.get_u32();
+            log::trace!("Channel {} get {} extended data", id, data.len());
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.recv_extended(data, client, out)?;
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_REQUEST => {
+            let id = data.get_u32();
+            log::trace!("Channel {} get request", id);
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.recv_request(data, client, out)?;
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        // flow_control msg
+        ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST => {
+            // client channel number
+            let id = data.get_u32();
+            // to_add
+            let rws = data.get_u32();
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.recv_window_adjust(rws, client, out)?;
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_CLOSE => {
+            let id = data.get_u32();
+            log::info!("Channel {} recv close", id);
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.remote_close(client, out)?;
+                if channel.is_close() {
+                    channels.remove(&id);
+                }
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => {
+            let request = util::from_utf8(data.get_u8s())?;
+            let want_reply = data.get_u8() != 0;
+            log::debug!("Currently ignore global request {}", request);
+            if want_reply {
+                let mut data = Data::new();
+                data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
+                data.pack(client).write_stream(out)?;
+            }
+        }
+        // no forwarding in the async session
+        ssh_msg_code::SSH_MSG_CHANNEL_OPEN => {
+            let channel_type = util::from_utf8(data.get_u8s())?;
+            let server_channel_no = data.get_u32();
+            log::debug!("reject {} channel opened by the server", channel_type);
+            let mut data = Data::new();
+            data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE)
+                .put_u32(server_channel_no)
+                .put_u32(ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED)
+                .put_str("")
+                .put_str("");
+            data.pack(client).write_stream(out)?;
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_SUCCESS => {
+            let id = data.get_u32();
+            log::trace!("Channel {} control success", id);
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.success(client, out)?
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        ssh_msg_code::SSH_MSG_CHANNEL_FAILURE => {
+            let id = data.get_u32();
+            log::trace!("Channel {} control failed", id);
+            if let Some(channel) = channels.get_mut(&id) {
+                channel.failed(client, out)?
+            } else {
+                log::warn!("Channel {} not found", id);
+            }
+        }
+        x => {
+            log::debug!("Currently ignore message {}", x);
+        }
+    }
+    Ok(())
+}
@@ -1,29 +1,40 @@
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::{
-    collections::HashMap,
-    io::{Read, Write},
+    any::Any,
+    collections::{HashMap, VecDeque},
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
     sync::{
-        mpsc::{self, Receiver, Sender, TryRecvError},
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
-    thread::spawn,
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
 };
 
 use log::info;
 
 use crate::{
     algorithm::Digest,
-    channel::{BackendChannel, ExecBroker},
+    channel::{AgentChannels, BackendChannel, ExecBroker},
     client::Client,
     config::algorithm::AlgList,
     constant::{size, ssh_msg_code, ssh_str},
-    error::{SshError, SshResult},
-    model::{ArcMut, BackendResp, BackendRqst, Data, Packet, SecPacket, U32Iter},
-    ChannelBroker, ScpBroker, ShellBrocker,
+    error::{SshErrorKind, SshResult},
+    model::{
+        ArcMut, BackendResp, BackendRqst, Data, ForwardListener, ForwardedOpen, Packet, SecPacket,
+        U32Iter,
+    },
+    util, ChannelBroker, DirectTcpipBroker, RemoteForwardBroker, ScpBroker, SftpBroker,
+    ShellBrocker,
 };
 
 pub struct SessionBroker {
     channel_num: ArcMut<U32Iter>,
     snd: Sender<BackendRqst>,
+    forward_agent: bool,
 }
 
 impl SessionBroker {
@@ -31,15 +42,31 @@ impl SessionBroker {
     where
         S: Read + Write + Send + 'static,
     {
+        let forward_agent = client.agent_forward().is_some();
         let (rqst_snd, rqst_rcv) = mpsc::channel();
+        let (event_snd, event_rcv) = mpsc::channel();
+        let channel_num = Arc::new(Mutex::new(U32Iter::default()));
+        // channels opened by the server also take numbers from it
+        let loop_channel_num = channel_num.clone();
+        // forward the requests, so the loop waits on one channel only
+        let forward_snd = event_snd.clone();
         spawn(move || {
-            if let Err(e) = client_loop(client, stream, rqst_rcv) {
+            while let Ok(rqst) = rqst_rcv.recv() {
+                if forward_snd.send(Event::Rqst(rqst)).is_err() {
+                    return;
+                }
+            }
+            let _ = forward_snd.send(Event::Closed);
+        });
+        spawn(move || {
+            if let Err(e) = run_stream(client, stream, event_rcv, event_snd, loop_channel_num) {
                 log::error!("Error {} occurred when running backend task", e.to_string())
             }
         });
         Self {
-            channel_num: Arc::new(Mutex::new(U32Iter::default())),
+            channel_num,
             snd: rqst_snd,
+            forward_agent,
         }
     }
 
@@ -54,6 +81,7 @@ impl SessionBroker {
     ///
     pub fn open_exec(&mut self) -> SshResult<ExecBroker> {
         let channel = self.open_channel()?;
+        self.request_agent_forwarding(&channel)?;
         channel.exec()
     }
 
@@ -68,24 +96,120 @@ impl SessionBroker {
     ///
     pub fn open_shell(&mut self) -> SshResult<ShellBrocker> {
         let channel = self.open_channel()?;
+        self.request_agent_forwarding(&channel)?;
         channel.shell()
     }
 
+    /// open a [SftpBroker] client which can operate remote files
+    ///
+    pub fn open_sftp(&mut self) -> SshResult<SftpBroker> {
+        let channel = self.open_channel()?;
+        channel.sftp()
+    }
+
     /// open a raw channel
     ///
     /// need call `.exec()`, `.shell()`, `.scp()` and so on to convert it to a specific channel
     ///
     pub fn open_channel(&mut self) -> SshResult<ChannelBroker> {
+        self.open_channel_with(ssh_str::SESSION, Data::new())
+    }
+
+    /// open a [DirectTcpipBroker] channel,
+    /// the server will connect to `host:port` and relay the data, which is `ssh -L`
+    ///
+    /// `originator` is the address where the connection comes from
+    ///
+    pub fn open_direct_tcpip(
+        &mut self,
+        host: &str,
+        port: u16,
+        originator: SocketAddr,
+    ) -> SshResult<DirectTcpipBroker> {
+        log::info!("direct-tcpip to {}:{} opened.", host, port);
+        let channel = self.open_channel_with(
+            ssh_str::DIRECT_TCPIP,
+            super::direct_tcpip_data(host, port, originator),
+        )?;
+        Ok(DirectTcpipBroker::open(channel))
+    }
+
+    /// listen on `local_addr` and forward every accepted connection
+    /// to `host:port` through the server, which is `ssh -L local_addr:host:port`
+    ///
+    /// Each connection is served by its own threads,
+    /// a connection refused by the server is logged and dropped
+    ///
+    /// This method blocks until the listener fails,
+    /// spawn a thread for it if the session is still needed
+    ///
+    pub fn forward_local_port<A>(&mut self, local_addr: A, host: &str, port: u16) -> SshResult<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(local_addr)?;
+        log::info!("forward {} to {}:{}.", listener.local_addr()?, host, port);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let originator = stream.peer_addr()?;
+            match self.open_direct_tcpip(host, port, originator) {
+                Ok(channel) => {
+                    spawn(move || {
+                        if let Err(e) = channel.relay(stream) {
+                            log::debug!("forwarding from {} stopped: {}", originator, e);
+                        }
+                    });
+                }
+                Err(e) => log::error!("cannot forward connection from {}: {}", originator, e),
+            }
+        }
+        Ok(())
+    }
+
+    /// ask the server to listen on `address:port` and forward the connections back,
+    /// which is `ssh -R`
+    ///
+    /// port 0 means any port the server can allocate,
+    /// call [RemoteForwardBroker::port] to get the allocated one
+    ///
+    pub fn tcpip_forward(&mut self, address: &str, port: u32) -> SshResult<RemoteForwardBroker> {
+        RemoteForwardBroker::open(address, port, self.snd.clone())
+    }
+
+    /// stop a remote forwarding started by [SessionBroker::tcpip_forward]
+    ///
+    /// same as [RemoteForwardBroker::cancel]
+    ///
+    pub fn cancel_tcpip_forward(&mut self, forward: RemoteForwardBroker) -> SshResult<()> {
+        forward.cancel()
+    }
+
+    /// listen on `address:port` of the server and forward every connection to `target`,
+    /// which is `ssh -R address:port:target`
+    ///
+    /// This method blocks until the session is closed,
+    /// spawn a thread for it if the session is still needed
+    ///
+    pub fn forward_remote_port<A>(&mut self, address: &str, port: u32, target: A) -> SshResult<()>
+    where
+        A: ToSocketAddrs,
+    {
+        let forward = self.tcpip_forward(address, port)?;
+        forward.forward_to(target)
+    }
+
+    fn open_channel_with(&mut self, channel_type: &str, extra: Data) -> SshResult<ChannelBroker> {
         let (resp_send, resp_recv) = mpsc::channel();
         let client_id = self.channel_num.lock().unwrap().next().unwrap();
 
         // open channel request
         let mut data = Data::new();
         data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN)
-            .put_str(ssh_str::SESSION)
+            .put_str(channel_type)
             .put_u32(client_id)
             .put_u32(size::LOCAL_WINDOW_SIZE)
             .put_u32(size::BUF_SIZE as u32);
+        data.extend_from_slice(&extra);
 
         self.snd
             .send(BackendRqst::OpenChannel(client_id, data, resp_send))?;
@@ -99,65 +223,432 @@ impl SessionBroker {
                     resp_recv,
                     self.snd.clone(),
                 )),
-                BackendResp::Fail(msg) => Err(SshError::from(msg)),
+                BackendResp::OpenFailure(code, description) => {
+                    Err(SshErrorKind::ChannelOpenFailure(code, description).into())
+                }
                 _ => unreachable!(),
             },
             Err(e) => Err(e.into()),
         }
     }
+
+    // ask the server to forward the agent on this channel, if enabled
+    fn request_agent_forwarding(&self, channel: &ChannelBroker) -> SshResult<()> {
+        if self.forward_agent {
+            channel.request_agent_forwarding()?;
+        }
+        Ok(())
+    }
 }
 
-fn client_loop<S>(mut client: Client, mut stream: S, rcv: Receiver<BackendRqst>) -> SshResult<()>
+// `ServerAliveCountMax` of OpenSSH
+const SERVER_ALIVE_COUNT_MAX: u32 = 3;
+// the bounds of the backoff to poll a stream which cannot be split
+const POLL_MIN: Duration = Duration::from_millis(1);
+const POLL_MAX: Duration = Duration::from_millis(20);
+
+// a stream read by the backend on a dedicated thread,
+// so it is split into the read half & the write half
+pub(crate) trait BackendStream: Send + 'static {
+    type Reader: Read + Send + 'static;
+    type Writer: Write;
+
+    fn split(self) -> io::Result<(Self::Reader, Self::Writer)>;
+
+    /// called when the session is closed,
+    /// which shall unblock the reader
+    fn shutdown(writer: &mut Self::Writer);
+}
+
+impl BackendStream for TcpStream {
+    type Reader = TcpStream;
+    type Writer = TcpStream;
+
+    fn split(self) -> io::Result<(TcpStream, TcpStream)> {
+        self.set_nonblocking(false)?;
+        Ok((self.try_clone()?, self))
+    }
+
+    fn shutdown(writer: &mut TcpStream) {
+        let _ = writer.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(unix)]
+impl BackendStream for UnixStream {
+    type Reader = UnixStream;
+    type Writer = UnixStream;
+
+    fn split(self) -> io::Result<(UnixStream, UnixStream)> {
+        self.set_nonblocking(false)?;
+        Ok((self.try_clone()?, self))
+    }
+
+    fn shutdown(writer: &mut UnixStream) {
+        let _ = writer.shutdown(Shutdown::Both);
+    }
+}
+
+// any other stream of `connect_bio`, which is nonblocking,
+// is shared by the reader thread polling it & the loop writing it
+struct PolledStream<S> {
+    stream: Arc<Mutex<S>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl<S> Clone for PolledStream<S> {
+    fn clone(&self) -> Self {
+        Self {
+            stream: self.stream.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<S> Read for PolledStream<S>
+where
+    S: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut backoff = POLL_MIN;
+        while !self.closed.load(Ordering::Relaxed) {
+            match self.stream.lock().unwrap().read(buf) {
+                // nothing to read yet
+                Ok(0) => (),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                x => return x,
+            }
+            sleep(backoff);
+            backoff = (backoff * 2).min(POLL_MAX);
+        }
+        Ok(0)
+    }
+}
+
+impl<S> Write for PolledStream<S>
+where
+    S: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.lock().unwrap().flush()
+    }
+}
+
+impl<S> BackendStream for PolledStream<S>
+where
+    S: Read + Write + Send + 'static,
+{
+    type Reader = Self;
+    type Writer = Self;
+
+    fn split(self) -> io::Result<(Self, Self)> {
+        Ok((self.clone(), self))
+    }
+
+    fn shutdown(writer: &mut Self) {
+        writer.closed.store(true, Ordering::Relaxed)
+    }
+}
+
+// the streams known to be split are read in blocking mode,
+// the others are polled
+fn run_stream<S>(
+    client: Client,
+    stream: S,
+    events: Receiver<Event>,
+    event_snd: Sender<Event>,
+    channel_num: ArcMut<U32Iter>,
+) -> SshResult<()>
+where
+    S: Read + Write + Send + 'static,
+{
+    let stream: Box<dyn Any + Send> = Box::new(stream);
+    let stream = match stream.downcast::<TcpStream>() {
+        Ok(tcp) => return client_loop(client, *tcp, events, event_snd, channel_num),
+        Err(stream) => stream,
+    };
+    #[cfg(unix)]
+    let stream = match stream.downcast::<UnixStream>() {
+        Ok(unix) => return client_loop(client, *unix, events, event_snd, channel_num),
+        Err(stream) => stream,
+    };
+    let stream = match stream.downcast::<DirectTcpipBroker>() {
+        Ok(jump) => return client_loop(client, *jump, events, event_snd, channel_num),
+        Err(stream) => stream,
+    };
+    match stream.downcast::<S>() {
+        Ok(stream) => {
+            let stream = PolledStream {
+                stream: Arc::new(Mutex::new(*stream)),
+                closed: Arc::new(AtomicBool::new(false)),
+            };
+            client_loop(client, stream, events, event_snd, channel_num)
+        }
+        Err(_) => unreachable!("the stream is always of its own type"),
+    }
+}
+
+// what wakes up the backend loop
+enum Event {
+    Rqst(BackendRqst),
+    // bytes read by the reader thread
+    Received(Vec<u8>),
+    // all the senders of requests have gone
+    Closed,
+    // the server has closed the stream
+    Eof,
+    // a reply of the forwarded agent is ready
+    Agent,
+}
+
+// the read half is read by a dedicated thread,
+// so an idle session takes no cpu
+fn spawn_reader<R>(mut reader: R, snd: Sender<Event>)
 where
-    S: Read + Write,
+    R: Read + Send + 'static,
 {
+    spawn(move || {
+        let mut buf = vec![0; size::BUF_SIZE];
+        loop {
+            match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(i) => {
+                    if snd.send(Event::Received(buf[..i].to_vec())).is_err() {
+                        return;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::debug!("reader thread exits on {}", e);
+                    break;
+                }
+            }
+        }
+        let _ = snd.send(Event::Eof);
+    });
+}
+
+// the write half, which unblocks the reader thread when the loop exits
+struct BackendWriter<S: BackendStream>(S::Writer);
+
+impl<S: BackendStream> Write for BackendWriter<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<S: BackendStream> Drop for BackendWriter<S> {
+    fn drop(&mut self) {
+        S::shutdown(&mut self.0)
+    }
+}
+
+// the stream seen by a key re-exchange in the loop,
+// which reads the bytes from the reader thread
+// and puts aside the other events until the exchange is done
+struct KexStream<'a, W> {
+    stream: &'a mut W,
+    inbuf: &'a mut Vec<u8>,
+    events: &'a Receiver<Event>,
+    deferred: &'a mut VecDeque<Event>,
+}
+
+impl<'a, W> Read for KexStream<'a, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.inbuf.is_empty() {
+            match self.events.recv() {
+                Ok(Event::Received(mut data)) => self.inbuf.append(&mut data),
+                Ok(Event::Eof) | Err(_) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(event) => self.deferred.push_back(event),
+            }
+        }
+        let len = buf.len().min(self.inbuf.len());
+        buf[..len].copy_from_slice(&self.inbuf[..len]);
+        self.inbuf.drain(..len);
+        Ok(len)
+    }
+}
+
+impl<'a, W> Write for KexStream<'a, W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn client_loop<S>(
+    mut client: Client,
+    stream: S,
+    events: Receiver<Event>,
+    event_snd: Sender<Event>,
+    channel_num: ArcMut<U32Iter>,
+) -> SshResult<()>
+where
+    S: BackendStream,
+{
+    let (reader, writer) = stream.split()?;
+    // forwarded agent channels, served in this loop
+    let agent_snd = Mutex::new(event_snd.clone());
+    let mut agents = AgentChannels::with_waker(Arc::new(move || {
+        let _ = agent_snd.lock().unwrap().send(Event::Agent);
+    }));
+    spawn_reader(reader, event_snd);
+    let mut stream = BackendWriter::<S>(writer);
+
     let mut channels = HashMap::<u32, BackendChannel>::new();
     let mut pendings = HashMap::<u32, Sender<BackendResp>>::new();
+    // global requests are replied in order
+    let mut global_pendings = VecDeque::<(Sender<BackendResp>, Option<ForwardListener>)>::new();
+    // remote forwardings, by the bound port
+    let mut forwards = HashMap::<u32, Sender<ForwardedOpen>>::new();
+    // the replies of keepalive requests, only to keep the order of global requests
+    let (alive_snd, alive_rcv) = mpsc::channel::<BackendResp>();
+    let alive_interval = client.server_alive_interval();
+    let mut last_recv = Instant::now();
+    let mut alive_unanswered = 0;
+    // the bytes received but not yet a whole packet
+    let mut inbuf = vec![];
+    // the events put aside by a key re-exchange
+    let mut deferred = VecDeque::<Event>::new();
     client.set_timeout(0);
     loop {
-        let try_recv = rcv.try_recv();
-        if try_recv.is_err() {
-            if let Err(TryRecvError::Disconnected) = try_recv {
-                info!("Session backend Closed");
-                return Ok(());
+        // the data of the channels waits in `deferred` during the exchange
+        if client.need_rekey() {
+            let mut kex_stream = KexStream {
+                stream: &mut stream,
+                inbuf: &mut inbuf,
+                events: &events,
+                deferred: &mut deferred,
+            };
+            client.rekey(&mut kex_stream)?;
+        }
+
+        // how long to wait for an event
+        let mut wait = if client.has_deferred() {
+            Some(Duration::ZERO)
+        } else {
+            None
+        };
+        if let Some(interval) = alive_interval {
+            while alive_rcv.try_recv().is_ok() {}
+            if last_recv.elapsed() >= interval {
+                if alive_unanswered >= SERVER_ALIVE_COUNT_MAX {
+                    log::error!("Timeout, server not responding.");
+                    return Err(SshErrorKind::Timeout.into());
+                }
+                log::debug!("send keepalive.");
+                let mut data = Data::new();
+                data.put_u8(ssh_msg_code::SSH_MSG_GLOBAL_REQUEST)
+                    .put_str(ssh_str::KEEPALIVE)
+                    .put_u8(true as u8);
+                data.pack(&mut client).write_stream(&mut stream)?;
+                global_pendings.push_back((alive_snd.clone(), None));
+                alive_unanswered += 1;
+                last_recv = Instant::now();
             }
-        } else if let Ok(rqst) = try_recv {
-            match rqst {
-                BackendRqst::OpenChannel(id, data, sender) => {
-                    log::info!("try open channel {}.", id);
+            let left = interval.saturating_sub(last_recv.elapsed());
+            wait = Some(wait.map_or(left, |w| w.min(left)));
+        }
+        if let Some(left) = client.rekey_after() {
+            wait = Some(wait.map_or(left, |w| w.min(left)));
+        }
 
-                    data.pack(&mut client).write_stream(&mut stream)?;
+        let event = match deferred.pop_front() {
+            Some(event) => Some(event),
+            None => match wait {
+                None => Some(events.recv().unwrap_or(Event::Closed)),
+                Some(wait) => match events.recv_timeout(wait) {
+                    Ok(event) => Some(event),
+                    Err(RecvTimeoutError::Timeout) => None,
+                    Err(RecvTimeoutError::Disconnected) => Some(Event::Closed),
+                },
+            },
+        };
 
-                    // add to pending open list
-                    assert!(pendings.insert(id, sender).is_none());
-                }
-                BackendRqst::Data(id, data) => {
-                    let channel = channels.get_mut(&id).unwrap();
+        match event {
+            Some(Event::Rqst(rqst)) => {
+                match rqst {
+                    BackendRqst::OpenChannel(id, data, sender) => {
+                        log::info!("try open channel {}.", id);
 
-                    log::trace!("Channel {} send {} data", id, data.len());
-                    channel.send_data(data, &mut client, &mut stream)?;
-                }
-                BackendRqst::Command(id, data) => {
-                    let channel = channels.get_mut(&id).unwrap();
+                        data.pack(&mut client).write_stream(&mut stream)?;
 
-                    log::trace!("Channel {} send control data", id);
-                    channel.send(data, &mut client, &mut stream)?;
-                }
-                BackendRqst::CloseChannel(id, data) => {
-                    log::info!("try close channel {}.", id);
-
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.send(data, &mut client, &mut stream)?;
-                    channel.local_close()?;
-                    if channel.is_close() {
-                        channels.remove(&id);
+                        // add to pending open list
+                        assert!(pendings.insert(id, sender).is_none());
+                    }
+                    // the channel may be already closed
+                    // when a forwarding thread sends its last data
+                    BackendRqst::Data(id, data) => {
+                        if let Some(channel) = channels.get_mut(&id) {
+                            log::trace!("Channel {} send {} data", id, data.len());
+                            channel.send_data(data, &mut client, &mut stream)?;
+                        } else {
+                            log::debug!("Channel {} is closed, drop {} data", id, data.len());
+                        }
+                    }
+                    BackendRqst::Command(id, data) => {
+                        if let Some(channel) = channels.get_mut(&id) {
+                            log::trace!("Channel {} send control data", id);
+                            channel.send(data, &mut client, &mut stream)?;
+                        } else {
+                            log::debug!("Channel {} is closed, drop control data", id);
+                        }
+                    }
+                    BackendRqst::GlobalRequest(data, sender, listener) => {
+                        log::info!("send global request.");
+
+                        data.pack(&mut client).write_stream(&mut stream)?;
+                        global_pendings.push_back((sender, listener));
+                    }
+                    BackendRqst::CloseChannel(id, data) => {
+                        log::info!("try close channel {}.", id);
+
+                        if let Some(channel) = channels.get_mut(&id) {
+                            channel.local_close(data, &mut client, &mut stream)?;
+                            if channel.is_close() {
+                                channels.remove(&id);
+                            }
+                        }
                     }
                 }
             }
+            Some(Event::Received(mut data)) => inbuf.append(&mut data),
+            Some(Event::Agent) => agents.poll(&mut client, &mut stream)?,
+            Some(Event::Closed) => {
+                info!("Session backend Closed");
+                return Ok(());
+            }
+            Some(Event::Eof) => {
+                info!("Session closed by the server");
+                return Ok(());
+            }
+            None => (),
         }
 
-        if let Some(pkt) = SecPacket::try_from_stream(&mut stream, &mut client)? {
-            let mut data = Data::unpack(pkt)?;
+        loop {
+            let mut data = match client.take_deferred() {
+                Some(data) => data,
+                None => match SecPacket::from_buf(&mut inbuf, &mut client)? {
+                    Some(pkt) => Data::unpack(pkt)?,
+                    None => break,
+                },
+            };
+            last_recv = Instant::now();
+            alive_unanswered = 0;
             let message_code = data.get_u8();
 
             match message_code {
@@ -170,116 +661,263 @@ where
                     data.get_u32();
 
                     // remove from pending open list
-                    let sender = pendings.remove(&client_channel_no);
-                    assert!(sender.is_some());
+                    let sender = match pendings.remove(&client_channel_no) {
+                        Some(sender) => sender,
+                        None => {
+                            log::warn!("Channel {} confirmed without opening", client_channel_no);
+                            continue;
+                        }
+                    };
 
                     // add to opened list
-                    assert!(channels
-                        .insert(
-                            client_channel_no,
-                            BackendChannel::new(
-                                server_channel_no,
-                                client_channel_no,
-                                remote_window_size,
-                                sender.unwrap()
-                            )?
-                        )
-                        .is_none())
+                    let channel = BackendChannel::new(
+                        server_channel_no,
+                        client_channel_no,
+                        remote_window_size,
+                        sender,
+                        &mut client,
+                        &mut stream,
+                    )?;
+                    channels.insert(client_channel_no, channel);
                 }
-                /*
-                    byte SSH_MSG_CHANNEL_OPEN_FAILURE
-                    uint32 recipient channel
-                    uint32 reason code
-                    string description，ISO-10646 UTF-8 编码[RFC3629]
-                    string language tag，[RFC3066]
-                */
                 // Fail to open a channel
                 ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE => {
                     //  client channel number
                     let id = data.get_u32();
 
-                    let sender = pendings.remove(&id);
-                    assert!(sender.is_some());
-                    // error code
-                    let code = data.get_u32();
-                    // error detail: By default is utf-8
-                    let description =
-                        String::from_utf8(data.get_u8s()).unwrap_or_else(|_| String::from("error"));
-                    // language tag, assume to be en-US
-                    data.get_u8s();
-
-                    let err_msg = match code {
-                        ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED => {
-                            format!("SSH_OPEN_ADMINISTRATIVELY_PROHIBITED: {}", description)
+                    let (code, description) = super::channel_open_failure(&mut data);
+                    match pendings.remove(&id) {
+                        // the opener may have given up
+                        Some(sender) => {
+                            let _ = sender.send(BackendResp::OpenFailure(code, description));
                         }
-                        ssh_msg_code::SSH_OPEN_CONNECT_FAILED => {
-                            format!("SSH_OPEN_CONNECT_FAILED: {}", description)
-                        }
-                        ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE => {
-                            format!("SSH_OPEN_UNKNOWN_CHANNEL_TYPE: {}", description)
-                        }
-                        ssh_msg_code::SSH_OPEN_RESOURCE_SHORTAGE => {
-                            format!("SSH_OPEN_RESOURCE_SHORTAGE: {}", description)
-                        }
-                        _ => description,
-                    };
-                    sender.unwrap().send(BackendResp::Fail(err_msg))?;
+                        None => log::warn!("Channel {} failed without opening", id),
+                    }
                 }
                 ssh_msg_code::SSH_MSG_KEXINIT => {
                     data.insert(0, message_code);
                     let mut digest = Digest::new();
                     digest.hash_ctx.set_i_s(&data);
                     let server_algs = AlgList::unpack((data, &mut client).into())?;
-                    client.key_agreement(&mut stream, server_algs, &mut digest)?;
+                    let mut kex_stream = KexStream {
+                        stream: &mut stream,
+                        inbuf: &mut inbuf,
+                        events: &events,
+                        deferred: &mut deferred,
+                    };
+                    client.key_agreement(&mut kex_stream, server_algs, &mut digest)?;
                 }
                 ssh_msg_code::SSH_MSG_CHANNEL_DATA => {
                     let id = data.get_u32();
                     log::trace!("Channel {} get {} data", id, data.len());
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.recv(data, &mut client, &mut stream)?;
+                    if agents.contains(id) {
+                        agents.handle(message_code, id, data, &mut client, &mut stream)?;
+                        continue;
+                    }
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.recv(data, &mut client, &mut stream)?;
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
+                }
+                ssh_msg_code::SSH_MSG_CHANNEL_EXTENDED_DATA => {
+                    let id = data.get_u32();
+                    log::trace!("Channel {} get {} extended data", id, data.len());
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.recv_extended(data, &mut client, &mut stream)?;
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
+                }
+                ssh_msg_code::SSH_MSG_CHANNEL_REQUEST => {
+                    let id = data.get_u32();
+                    log::trace!("Channel {} get request", id);
+                    if agents.contains(id) {
+                        agents.handle(message_code, id, data, &mut client, &mut stream)?;
+                        continue;
+                    }
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.recv_request(data, &mut client, &mut stream)?;
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
                 }
                 // flow_control msg
                 ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST => {
                     // client channel number
                     let id = data.get_u32();
+                    if agents.contains(id) {
+                        agents.handle(message_code, id, data, &mut client, &mut stream)?;
+                        continue;
+                    }
                     // to_add
                     let rws = data.get_u32();
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.recv_window_adjust(rws, &mut client, &mut stream)?;
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.recv_window_adjust(rws, &mut client, &mut stream)?;
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
                 }
                 ssh_msg_code::SSH_MSG_CHANNEL_CLOSE => {
                     let id = data.get_u32();
                     log::info!("Channel {} recv close", id);
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.remote_close()?;
-                    if channel.is_close() {
-                        channels.remove(&id);
+                    if agents.contains(id) {
+                        agents.handle(message_code, id, data, &mut client, &mut stream)?;
+                        continue;
+                    }
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.remote_close(&mut client, &mut stream)?;
+                        if channel.is_close() {
+                            channels.remove(&id);
+                        }
+                    } else {
+                        log::warn!("Channel {} not found", id);
                     }
                 }
                 ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => {
+                    let request = util::from_utf8(data.get_u8s())?;
+                    let want_reply = data.get_u8() != 0;
+                    log::debug!("Currently ignore global request {}", request);
+                    if want_reply {
+                        let mut data = Data::new();
+                        data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
+                        data.pack(&mut client).write_stream(&mut stream)?;
+                    }
+                    continue;
+                }
+                ssh_msg_code::SSH_MSG_REQUEST_SUCCESS => {
+                    let (sender, listener) = match global_pendings.pop_front() {
+                        Some(pending) => pending,
+                        None => {
+                            log::warn!("Ignore request success without a global request");
+                            continue;
+                        }
+                    };
+                    // only `tcpip-forward` with port 0 gets a port back
+                    let port = if data.len() >= 4 { data.get_u32() } else { 0 };
+                    if let Some(listener) = listener {
+                        let bound = if listener.port == 0 {
+                            port
+                        } else {
+                            listener.port
+                        };
+                        forwards.insert(bound, listener.opens);
+                    }
+                    // the requester may have given up
+                    let _ = sender.send(BackendResp::Ok(port));
+                }
+                ssh_msg_code::SSH_MSG_REQUEST_FAILURE => match global_pendings.pop_front() {
+                    Some((sender, _)) => {
+                        let _ =
+                            sender.send(BackendResp::Fail("global request failure.".to_owned()));
+                    }
+                    None => log::warn!("Ignore request failure without a global request"),
+                },
+                /*
+                    byte      SSH_MSG_CHANNEL_OPEN
+                    string    channel type
+                    uint32    sender channel
+                    uint32    initial window size
+                    uint32    maximum packet size
+                    ....      channel type specific data follows
+                */
+                ssh_msg_code::SSH_MSG_CHANNEL_OPEN => {
+                    let channel_type = util::from_utf8(data.get_u8s())?;
+                    if channel_type == ssh_str::AUTH_AGENT {
+                        let client_channel_no = channel_num.lock().unwrap().next().unwrap();
+                        agents.open(data, client_channel_no, &mut client, &mut stream)?;
+                        continue;
+                    }
+                    let server_channel_no = data.get_u32();
+                    let remote_window_size = data.get_u32();
+                    // remote packet size, currently don't need it
+                    data.get_u32();
+
+                    let reason = if channel_type == ssh_str::FORWARDED_TCPIP {
+                        /*
+                            string    address that was connected
+                            uint32    port that was connected
+                            string    originator IP address
+                            uint32    originator port
+                        */
+                        data.get_u8s();
+                        let port = data.get_u32();
+                        let originator_address = util::from_utf8(data.get_u8s())?;
+                        let originator_port = data.get_u32();
+
+                        let client_channel_no = channel_num.lock().unwrap().next().unwrap();
+                        let (resp_send, resp_recv) = mpsc::channel();
+                        let open = ForwardedOpen {
+                            client_channel_no,
+                            server_channel_no,
+                            rcv: resp_recv,
+                            originator_address,
+                            originator_port,
+                        };
+                        match forwards.get(&port).map(|opens| opens.send(open)) {
+                            Some(Ok(_)) => {
+                                log::info!("accept forwarded channel {}.", client_channel_no);
+                                let mut data = Data::new();
+                                data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_CONFIRMATION)
+                                    .put_u32(server_channel_no)
+                                    .put_u32(client_channel_no)
+                                    .put_u32(size::LOCAL_WINDOW_SIZE)
+                                    .put_u32(size::BUF_SIZE as u32);
+                                data.pack(&mut client).write_stream(&mut stream)?;
+                                channels.insert(
+                                    client_channel_no,
+                                    BackendChannel::new(
+                                        server_channel_no,
+                                        client_channel_no,
+                                        remote_window_size,
+                                        resp_send,
+                                        &mut client,
+                                        &mut stream,
+                                    )?,
+                                );
+                                continue;
+                            }
+                            Some(Err(_)) => {
+                                // the user side has gone
+                                forwards.remove(&port);
+                                ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED
+                            }
+                            None => ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED,
+                        }
+                    } else {
+                        ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE
+                    };
+
+                    log::debug!("reject {} channel opened by the server", channel_type);
                     let mut data = Data::new();
-                    data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE)
+                        .put_u32(server_channel_no)
+                        .put_u32(reason)
+                        .put_str("")
+                        .put_str("");
                     data.pack(&mut client).write_stream(&mut stream)?;
-                    continue;
                 }
 
                 x @ ssh_msg_code::SSH_MSG_CHANNEL_EOF => {
                     log::debug!("Currently ignore message {}", x);
                 }
-                x @ ssh_msg_code::SSH_MSG_CHANNEL_REQUEST => {
-                    log::debug!("Currently ignore message {}", x);
-                }
                 _x @ ssh_msg_code::SSH_MSG_CHANNEL_SUCCESS => {
                     let id = data.get_u32();
                     log::trace!("Channel {} control success", id);
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.success()?
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.success(&mut client, &mut stream)?
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
                 }
                 ssh_msg_code::SSH_MSG_CHANNEL_FAILURE => {
                     let id = data.get_u32();
                     log::trace!("Channel {} control failed", id);
-                    let channel = channels.get_mut(&id).unwrap();
-                    channel.failed()?
+                    if let Some(channel) = channels.get_mut(&id) {
+                        channel.failed(&mut client, &mut stream)?
+                    } else {
+                        log::warn!("Channel {} not found", id);
+                    }
                 }
 
                 x => {
@@ -1,15 +1,21 @@
 use std::{
     cell::RefCell,
     io::{Read, Write},
+    net::SocketAddr,
     rc::Rc,
 };
 
 use crate::{
-    channel::{LocalChannel, LocalExec, LocalScp, LocalShell},
+    algorithm::Digest,
+    channel::{
+        AgentChannels, LocalChannel, LocalDirectTcpip, LocalExec, LocalScp, LocalSftp, LocalShell,
+    },
     client::Client,
+    config::algorithm::AlgList,
     constant::{size, ssh_msg_code, ssh_str},
-    error::{SshError, SshResult},
+    error::{SshError, SshErrorKind, SshResult},
     model::{Data, Packet, RcMut, SecPacket, U32Iter},
+    util,
 };
 
 pub struct LocalSession<S>
@@ -18,7 +24,8 @@ where
 {
     client: RcMut<Client>,
     stream: RcMut<S>,
-    channel_num: U32Iter,
+    channel_num: RcMut<U32Iter>,
+    agents: RcMut<AgentChannels>,
 }
 
 impl<S> LocalSession<S>
@@ -29,7 +36,8 @@ where
         Self {
             client: Rc::new(RefCell::new(client)),
             stream: Rc::new(RefCell::new(stream)),
-            channel_num: U32Iter::default(),
+            channel_num: Rc::new(RefCell::new(U32Iter::default())),
+            agents: Rc::new(RefCell::new(AgentChannels::default())),
         }
     }
 
@@ -43,7 +51,8 @@ where
     /// open a [LocalExec] channel which can excute commands
     ///
     pub fn open_exec(&mut self) -> SshResult<LocalExec<S>> {
-        let channel = self.open_channel()?;
+        let mut channel = self.open_channel()?;
+        self.request_agent_forwarding(&mut channel)?;
         channel.exec()
     }
 
@@ -57,10 +66,18 @@ where
     /// open a [LocalShell] channel which can download/upload files/directories
     ///
     pub fn open_shell(&mut self) -> SshResult<LocalShell<S>> {
-        let channel = self.open_channel()?;
+        let mut channel = self.open_channel()?;
+        self.request_agent_forwarding(&mut channel)?;
         channel.shell(24, 80)
     }
 
+    /// open a [LocalSftp] client which can operate remote files
+    ///
+    pub fn open_sftp(&mut self) -> SshResult<LocalSftp<S>> {
+        let channel = self.open_channel()?;
+        channel.sftp()
+    }
+
     pub fn get_raw_io(&mut self) -> RcMut<S> {
         self.stream.clone()
     }
@@ -71,9 +88,241 @@ where
     ///
     pub fn open_channel(&mut self) -> SshResult<LocalChannel<S>> {
         log::info!("channel opened.");
+        self.open_channel_with(ssh_str::SESSION, Data::new())
+    }
+
+    /// open a [LocalDirectTcpip] channel,
+    /// the server will connect to `host:port` and relay the data, which is `ssh -L`
+    ///
+    /// `originator` is the address where the connection comes from
+    ///
+    /// NOTE: all channels of a local session share one stream,
+    /// so only one forwarded connection can be served at a time,
+    /// use [crate::SessionBroker::forward_local_port] for a real port forwarding
+    ///
+    pub fn open_direct_tcpip(
+        &mut self,
+        host: &str,
+        port: u16,
+        originator: SocketAddr,
+    ) -> SshResult<LocalDirectTcpip<S>> {
+        log::info!("direct-tcpip to {}:{} opened.", host, port);
+        let channel = self.open_channel_with(
+            ssh_str::DIRECT_TCPIP,
+            super::direct_tcpip_data(host, port, originator),
+        )?;
+        Ok(LocalDirectTcpip::open(channel))
+    }
+
+    /// ask the server to listen on `address:port` and forward the connections back,
+    /// which is `ssh -R`
+    ///
+    /// port 0 means any port the server can allocate,
+    /// return the port bound by the server
+    ///
+    /// call [LocalSession::accept_forwarded] to get the connections
+    ///
+    pub fn tcpip_forward(&mut self, address: &str, port: u32) -> SshResult<u32> {
+        let allocated = self.global_request(ssh_str::TCPIP_FORWARD, address, port)?;
+        let port = if port == 0 { allocated } else { port };
+        log::info!("remote forwarding on {}:{} started.", address, port);
+        Ok(port)
+    }
+
+    /// stop a remote forwarding started by [LocalSession::tcpip_forward]
+    ///
+    pub fn cancel_tcpip_forward(&mut self, address: &str, port: u32) -> SshResult<()> {
+        self.global_request(ssh_str::CANCEL_TCPIP_FORWARD, address, port)?;
+        log::info!("remote forwarding on {}:{} cancelled.", address, port);
+        Ok(())
+    }
+
+    /// wait for the next connection of a remote forwarding,
+    /// return the channel with the originator address & port
+    ///
+    pub fn accept_forwarded(&mut self) -> SshResult<(LocalDirectTcpip<S>, String, u32)> {
+        loop {
+            let mut data = self.recv_once()?;
+
+            match data.get_u8() {
+                /*
+                    byte      SSH_MSG_CHANNEL_OPEN
+                    string    "forwarded-tcpip"
+                    uint32    sender channel
+                    uint32    initial window size
+                    uint32    maximum packet size
+                    string    address that was connected
+                    uint32    port that was connected
+                    string    originator IP address
+                    uint32    originator port
+                */
+                ssh_msg_code::SSH_MSG_CHANNEL_OPEN => {
+                    let channel_type = util::from_utf8(data.get_u8s())?;
+                    if channel_type == ssh_str::AUTH_AGENT {
+                        let client_channel_no = self.channel_num.borrow_mut().next().unwrap();
+                        self.agents.borrow_mut().open(
+                            data,
+                            client_channel_no,
+                            &mut self.client.borrow_mut(),
+                            &mut *self.stream.borrow_mut(),
+                        )?;
+                        continue;
+                    }
+                    let server_channel_no = data.get_u32();
+                    let remote_window_size = data.get_u32();
+                    // 远程的最大数据包大小， 暂时不需要
+                    data.get_u32();
+
+                    if channel_type != ssh_str::FORWARDED_TCPIP {
+                        log::debug!("reject {} channel opened by the server", channel_type);
+                        let mut data = Data::new();
+                        data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE)
+                            .put_u32(server_channel_no)
+                            .put_u32(ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE)
+                            .put_str("")
+                            .put_str("");
+                        self.send(data)?;
+                        continue;
+                    }
+                    data.get_u8s();
+                    data.get_u32();
+                    let originator_address = util::from_utf8(data.get_u8s())?;
+                    let originator_port = data.get_u32();
+
+                    let client_channel_no = self.channel_num.borrow_mut().next().unwrap();
+                    let mut data = Data::new();
+                    data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN_CONFIRMATION)
+                        .put_u32(server_channel_no)
+                        .put_u32(client_channel_no)
+                        .put_u32(size::LOCAL_WINDOW_SIZE)
+                        .put_u32(size::BUF_SIZE as u32);
+                    self.send(data)?;
+
+                    log::info!(
+                        "accept forwarded connection from {}:{}.",
+                        originator_address,
+                        originator_port
+                    );
+                    let channel = LocalChannel::new(
+                        server_channel_no,
+                        client_channel_no,
+                        remote_window_size,
+                        self.client.clone(),
+                        self.stream.clone(),
+                        self.channel_num.clone(),
+                        self.agents.clone(),
+                    );
+                    return Ok((
+                        LocalDirectTcpip::open(channel),
+                        originator_address,
+                        originator_port,
+                    ));
+                }
+                ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => self.reject_global_request(data)?,
+                // the agent channels may be in use meanwhile
+                x @ (ssh_msg_code::SSH_MSG_CHANNEL_DATA
+                | ssh_msg_code::SSH_MSG_CHANNEL_WINDOW_ADJUST
+                | ssh_msg_code::SSH_MSG_CHANNEL_REQUEST
+                | ssh_msg_code::SSH_MSG_CHANNEL_CLOSE) => {
+                    let cc = data.get_u32();
+                    self.agents.borrow_mut().handle(
+                        x,
+                        cc,
+                        data,
+                        &mut self.client.borrow_mut(),
+                        &mut *self.stream.borrow_mut(),
+                    )?;
+                }
+                x => log::debug!("Ignore ssh msg {}", x),
+            }
+        }
+    }
+
+    /*
+        byte      SSH_MSG_GLOBAL_REQUEST
+        string    "tcpip-forward" / "cancel-tcpip-forward"
+        boolean   want reply
+        string    address to bind (e.g., "0.0.0.0")
+        uint32    port number to bind
+    */
+    // return the port sent back by the server, 0 if there isn't
+    fn global_request(&mut self, request: &str, address: &str, port: u32) -> SshResult<u32> {
+        let mut data = Data::new();
+        data.put_u8(ssh_msg_code::SSH_MSG_GLOBAL_REQUEST)
+            .put_str(request)
+            .put_u8(true as u8)
+            .put_str(address)
+            .put_u32(port);
+        self.send(data)?;
+
+        loop {
+            let mut data = self.recv_once()?;
 
-        let client_channel_no = self.channel_num.next().unwrap();
-        self.send_open_channel(client_channel_no)?;
+            match data.get_u8() {
+                ssh_msg_code::SSH_MSG_REQUEST_SUCCESS => {
+                    // only `tcpip-forward` with port 0 gets a port back
+                    return Ok(if data.len() >= 4 { data.get_u32() } else { 0 });
+                }
+                ssh_msg_code::SSH_MSG_REQUEST_FAILURE => {
+                    return Err(SshError::from(format!("{} failure.", request)))
+                }
+                ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => self.reject_global_request(data)?,
+                x => log::debug!("Ignore ssh msg {}", x),
+            }
+        }
+    }
+
+    fn send(&mut self, data: Data) -> SshResult<()> {
+        let mut client = self.client.borrow_mut();
+        let mut stream = self.stream.borrow_mut();
+        if client.need_rekey() {
+            client.rekey(&mut *stream)?;
+        }
+        data.pack(&mut client).write_stream(&mut *stream)
+    }
+
+    // the messages received during a rekey come first,
+    // and the rekey started by the server is done here as in the channels
+    fn recv_once(&mut self) -> SshResult<Data> {
+        loop {
+            let deferred = self.client.borrow_mut().take_deferred();
+            let data = match deferred {
+                Some(data) => data,
+                None => Data::unpack(SecPacket::from_stream(
+                    &mut *self.stream.borrow_mut(),
+                    &mut self.client.borrow_mut(),
+                )?)?,
+            };
+            if data.first() != Some(&ssh_msg_code::SSH_MSG_KEXINIT) {
+                return Ok(data);
+            }
+            let mut digest = Digest::new();
+            digest.hash_ctx.set_i_s(&data);
+            let server_algs = AlgList::unpack((data, &mut *self.client.borrow_mut()).into())?;
+            self.client.borrow_mut().key_agreement(
+                &mut *self.stream.borrow_mut(),
+                server_algs,
+                &mut digest,
+            )?;
+        }
+    }
+
+    // global requests from the server are not supported
+    fn reject_global_request(&mut self, mut data: Data) -> SshResult<()> {
+        let request = util::from_utf8(data.get_u8s())?;
+        let want_reply = data.get_u8() != 0;
+        log::debug!("Currently ignore global request {}", request);
+        if want_reply {
+            let mut data = Data::new();
+            data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
+            self.send(data)?;
+        }
+        Ok(())
+    }
+
+    fn open_channel_with(&mut self, channel_type: &str, extra: Data) -> SshResult<LocalChannel<S>> {
+        let client_channel_no = self.channel_num.borrow_mut().next().unwrap();
+        self.send_open_channel(client_channel_no, channel_type, extra)?;
         let (server_channel_no, remote_window_size) = self.receive_open_channel()?;
 
         Ok(LocalChannel::new(
@@ -82,28 +331,40 @@ where
             remote_window_size,
             self.client.clone(),
             self.stream.clone(),
+            self.channel_num.clone(),
+            self.agents.clone(),
         ))
     }
 
+    // ask the server to forward the agent on this channel, if enabled
+    fn request_agent_forwarding(&mut self, channel: &mut LocalChannel<S>) -> SshResult<()> {
+        if self.client.borrow().agent_forward().is_some() {
+            channel.request_agent_forwarding()?;
+        }
+        Ok(())
+    }
+
     // 本地请求远程打开通道
-    fn send_open_channel(&mut self, client_channel_no: u32) -> SshResult<()> {
+    fn send_open_channel(
+        &mut self,
+        client_channel_no: u32,
+        channel_type: &str,
+        extra: Data,
+    ) -> SshResult<()> {
         let mut data = Data::new();
         data.put_u8(ssh_msg_code::SSH_MSG_CHANNEL_OPEN)
-            .put_str(ssh_str::SESSION)
+            .put_str(channel_type)
             .put_u32(client_channel_no)
             .put_u32(size::LOCAL_WINDOW_SIZE)
             .put_u32(size::BUF_SIZE as u32);
-        data.pack(&mut self.client.borrow_mut())
-            .write_stream(&mut *self.stream.borrow_mut())
+        data.extend_from_slice(&extra);
+        self.send(data)
     }
 
     // 远程回应是否可以打开通道
     fn receive_open_channel(&mut self) -> SshResult<(u32, u32)> {
         loop {
-            let mut data = Data::unpack(SecPacket::from_stream(
-                &mut *self.stream.borrow_mut(),
-                &mut self.client.borrow_mut(),
-            )?)?;
+            let mut data = self.recv_once()?;
 
             let message_code = data.get_u8();
             match message_code {
@@ -119,48 +380,13 @@ where
                     data.get_u32();
                     return Ok((server_channel_no, remote_window_size));
                 }
-                /*
-                    byte SSH_MSG_CHANNEL_OPEN_FAILURE
-                    uint32 recipient channel
-                    uint32 reason code
-                    string description，ISO-10646 UTF-8 编码[RFC3629]
-                    string language tag，[RFC3066]
-                */
                 // 打开请求拒绝
                 ssh_msg_code::SSH_MSG_CHANNEL_OPEN_FAILURE => {
                     data.get_u32();
-                    // 失败原因码
-                    let code = data.get_u32();
-                    // 消息详情 默认utf-8编码
-                    let description =
-                        String::from_utf8(data.get_u8s()).unwrap_or_else(|_| String::from("error"));
-                    // language tag 暂不处理， 应该是 en-US
-                    data.get_u8s();
-
-                    let err_msg = match code {
-                        ssh_msg_code::SSH_OPEN_ADMINISTRATIVELY_PROHIBITED => {
-                            format!("SSH_OPEN_ADMINISTRATIVELY_PROHIBITED: {}", description)
-                        }
-                        ssh_msg_code::SSH_OPEN_CONNECT_FAILED => {
-                            format!("SSH_OPEN_CONNECT_FAILED: {}", description)
-                        }
-                        ssh_msg_code::SSH_OPEN_UNKNOWN_CHANNEL_TYPE => {
-                            format!("SSH_OPEN_UNKNOWN_CHANNEL_TYPE: {}", description)
-                        }
-                        ssh_msg_code::SSH_OPEN_RESOURCE_SHORTAGE => {
-                            format!("SSH_OPEN_RESOURCE_SHORTAGE: {}", description)
-                        }
-                        _ => description,
-                    };
-                    return Err(SshError::from(err_msg));
-                }
-                ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => {
-                    let mut data = Data::new();
-                    data.put_u8(ssh_msg_code::SSH_MSG_REQUEST_FAILURE);
-                    data.pack(&mut self.client.borrow_mut())
-                        .write_stream(&mut *self.stream.borrow_mut())?;
-                    continue;
+                    let (code, description) = super::channel_open_failure(&mut data);
+                    return Err(SshErrorKind::ChannelOpenFailure(code, description).into());
                 }
+                ssh_msg_code::SSH_MSG_GLOBAL_REQUEST => self.reject_global_request(data)?,
                 x => {
                     log::debug!("Ignore ssh msg {}", x);
                     continue;
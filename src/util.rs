@@ -3,7 +3,7 @@ use crate::slog::log;
 use rand::rngs::OsRng;
 use rand::Rng;
 use std::{
-    path::Path,
+    path::{Path, PathBuf},
     str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -72,3 +72,26 @@ pub(crate) fn file_time(v: Vec<u8>) -> SshResult<(i64, i64)> {
     let ut = from_utf8(t[a..(t.len() - 1)].to_vec())?;
     Ok((str_to_i64(&ct)?, str_to_i64(&ut)?))
 }
+
+pub(crate) fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
+}
+
+// glob style matching, `*` matches any sequence and `?` matches any single character
+pub(crate) fn wildcard_match(pattern: &str, s: &str) -> bool {
+    fn do_match(pattern: &[u8], s: &[u8]) -> bool {
+        match (pattern.first(), s.first()) {
+            (None, None) => true,
+            (Some(b'*'), _) => {
+                do_match(&pattern[1..], s) || (!s.is_empty() && do_match(pattern, &s[1..]))
+            }
+            (Some(b'?'), Some(_)) => do_match(&pattern[1..], &s[1..]),
+            (Some(p), Some(c)) if p == c => do_match(&pattern[1..], &s[1..]),
+            _ => false,
+        }
+    }
+    do_match(pattern.as_bytes(), s.as_bytes())
+}